@@ -0,0 +1,505 @@
+/*
+    Precached - A Linux process monitor and pre-caching daemon
+    Copyright (C) 2017-2020 the precached developers
+
+    This file is part of precached.
+
+    Precached is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Precached is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Precached.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::fs::read_dir;
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use clap::ArgMatches;
+use log::{trace, debug, info, warn, error, log, LevelFilter};
+use pbr::ProgressBar;
+use prettytable::Cell;
+use prettytable::format::*;
+use prettytable::Row;
+use prettytable::Table;
+use regex::Regex;
+use serde_derive::{Serialize, Deserialize};
+use term::color::*;
+use term::Attr;
+use crate::constants;
+use crate::iotrace;
+use crate::util;
+use crate::{default_table_format, Config, PROGRESS_BAR_INDICATORS};
+use crate::{get_io_traces_filtered_and_sorted, parse_sort_field, parse_sort_order};
+
+/// The version of the format of trace bundles written by this build
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// The file the hot applications plugin of the daemon keeps its histogram in
+const HOT_APPLICATIONS_STATE_FILE: &str = "hot_applications.state";
+
+/// A portable bundle of I/O trace logs, used to transfer
+/// the I/O trace logs learned on one system to other systems
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceBundle {
+    /// Version of the bundle format, see `BUNDLE_FORMAT_VERSION`
+    pub format_version: u32,
+    /// Date and Time (in UTC) this bundle was created at
+    pub created_at: DateTime<Utc>,
+    /// The bundled I/O trace logs. Kept in their JSON representation,
+    /// so that older on-disk formats get migrated on import
+    pub traces: Vec<serde_json::Value>,
+    /// The histogram of hot applications, restricted to the bundled I/O trace logs
+    pub app_histogram: HashMap<String, usize>,
+}
+
+impl TraceBundle {
+    pub fn new() -> Self {
+        TraceBundle {
+            format_version: BUNDLE_FORMAT_VERSION,
+            created_at: Utc::now(),
+            traces: vec![],
+            app_histogram: HashMap::new(),
+        }
+    }
+
+    /// De-serialize from a file
+    pub fn from_file(filename: &Path) -> io::Result<TraceBundle> {
+        let text = util::read_compressed_text_file(filename)?;
+
+        let reader = BufReader::new(text.as_bytes());
+        let deserialized = serde_json::from_reader::<_, TraceBundle>(reader)?;
+
+        if deserialized.format_version > BUNDLE_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported bundle format version {} (newest supported: {})",
+                    deserialized.format_version, BUNDLE_FORMAT_VERSION
+                ),
+            ));
+        }
+
+        Ok(deserialized)
+    }
+
+    /// Write the bundle to disk
+    pub fn save(&self, filename: &Path) -> io::Result<()> {
+        let serialized = serde_json::to_string_pretty(&self)?;
+        util::write_text_file(filename, &serialized)?;
+
+        Ok(())
+    }
+}
+
+/// Load the histogram of hot applications from the state directory `state_dir`
+fn load_app_histogram(state_dir: &Path) -> io::Result<HashMap<String, usize>> {
+    let text = util::read_compressed_text_file(&state_dir.join(HOT_APPLICATIONS_STATE_FILE))?;
+
+    let reader = BufReader::new(text.as_bytes());
+    let deserialized = serde_json::from_reader::<_, HashMap<String, usize>>(reader)?;
+
+    Ok(deserialized)
+}
+
+/// Save the histogram of hot applications to the state directory `state_dir`
+fn save_app_histogram(state_dir: &Path, app_histogram: &HashMap<String, usize>) -> io::Result<()> {
+    let serialized = serde_json::to_string_pretty(&app_histogram)?;
+    util::write_text_file(&state_dir.join(HOT_APPLICATIONS_STATE_FILE), &serialized)?;
+
+    Ok(())
+}
+
+/// Parse the path-prefix remapping rules given on the command line, in the form `OLD=NEW`.
+/// Returns the offending rule if a rule could not be parsed
+fn parse_remap_rules(matches: &ArgMatches) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut result = vec![];
+
+    if let Some(values) = matches.values_of("remap") {
+        for rule in values {
+            let mut parts = rule.splitn(2, '=');
+
+            match (parts.next(), parts.next()) {
+                (Some(from), Some(to)) if !from.is_empty() && !to.is_empty() => {
+                    result.push((PathBuf::from(from), PathBuf::from(to)));
+                }
+
+                _ => return Err(String::from(rule)),
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Prepare the I/O trace log `io_trace` taken from a bundle for use on this system:
+/// Rewrite its paths using the path-prefix remapping rules `rules`, and re-validate
+/// all of its entries against the local filesystem. Entries that reference missing
+/// files are dropped. Returns `false` if the I/O trace log is not valid on this system
+fn localize_io_trace(io_trace: &mut iotrace::IOTraceLog, rules: &[(PathBuf, PathBuf)], normalization_rules: &[Regex]) -> bool {
+    io_trace.remap_paths(rules, normalization_rules);
+
+    // the trace log has been recorded on a different system,
+    // so treat it as if it had been created at import time
+    io_trace.rebase_timestamps(Utc::now());
+
+    let mut trace_log = vec![];
+
+    for mut entry in io_trace.trace_log.drain(..) {
+        let (_flags, err, _color) = util::get_io_trace_log_entry_flags_and_err(&entry);
+
+        if !err {
+            entry.rebind();
+            trace_log.push(entry);
+        }
+    }
+
+    let files: HashSet<PathBuf> = trace_log
+        .iter()
+        .map(|e| e.operation.get_filename().to_path_buf())
+        .collect();
+
+    io_trace.file_map.retain(|k, _| files.contains(k));
+    io_trace.run_hits.retain(|k, _| files.contains(k));
+    io_trace.accumulated_size = trace_log.iter().map(|e| e.size).sum();
+    io_trace.trace_log = trace_log;
+
+    let (_flags, err, _color) = util::get_io_trace_flags_and_err(io_trace);
+
+    !err && !io_trace.trace_log.is_empty()
+}
+
+/// Export I/O traces and the histogram of hot applications to a bundle file
+pub fn export_io_traces(config: &Config, daemon_config: util::ConfigFile) {
+    let state_dir = daemon_config
+        .clone()
+        .state_dir
+        .unwrap_or(Path::new(constants::STATE_DIR).to_path_buf());
+    let traces_path = state_dir.join(Path::new(constants::IOTRACE_DIR).to_path_buf());
+
+    let count = read_dir(&traces_path).unwrap().count();
+    let mut pb = ProgressBar::new(count as u64);
+
+    let display_progress = unsafe { nix::libc::isatty(1) == 1 };
+
+    if display_progress {
+        pb.format(PROGRESS_BAR_INDICATORS);
+    }
+
+    let matches = config.matches.subcommand_matches("export").unwrap();
+    let output = Path::new(matches.value_of("FILE").unwrap());
+
+    let (result, total, matching, mut errors) = get_io_traces_filtered_and_sorted(
+        config,
+        daemon_config,
+        display_progress,
+        &mut pb,
+        &matches,
+        parse_sort_field(&matches),
+        parse_sort_order(&matches),
+    )
+    .unwrap();
+
+    let mut pb = ProgressBar::new(matching as u64);
+
+    if display_progress {
+        pb.format(PROGRESS_BAR_INDICATORS);
+        pb.message(tr!("iotracectl-exporting-trace-logs"));
+    }
+
+    let app_histogram = load_app_histogram(&state_dir).unwrap_or_else(|e| {
+        warn!("Histogram of hot applications could not be loaded! {}", e);
+        HashMap::new()
+    });
+
+    let mut bundle = TraceBundle::new();
+
+    let mut table = Table::new();
+    table.set_format(default_table_format(&config));
+
+    // Add table row header
+    table.add_row(Row::new(vec![
+        Cell::new("#"),
+        Cell::new(tr!("iotracectl-io-trace-log")),
+        Cell::new(tr!("status")),
+    ]));
+
+    let mut index = 0;
+    for (io_trace, path) in result {
+        let filename = String::from(path.to_string_lossy());
+
+        match io_trace.to_value() {
+            Err(e) => {
+                error!("I/O trace log {:?} could not be serialized: {}", &path, e);
+
+                // Print in "tabular" format (the default)
+                table.add_row(Row::new(vec![
+                    Cell::new_align(&format!("{}", index + 1), Alignment::RIGHT),
+                    Cell::new(&filename).with_style(Attr::Bold),
+                    Cell::new(tr!("iotracectl-error"))
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(RED)),
+                ]));
+
+                errors += 1;
+            }
+
+            Ok(value) => {
+                bundle.traces.push(value);
+
+                if let Some(count) = app_histogram.get(&io_trace.hash) {
+                    bundle.app_histogram.insert(io_trace.hash.clone(), *count);
+                }
+
+                // Print in "tabular" format (the default)
+                table.add_row(Row::new(vec![
+                    Cell::new_align(&format!("{}", index + 1), Alignment::RIGHT),
+                    Cell::new(&filename).with_style(Attr::Bold),
+                    Cell::new(tr!("iotracectl-exported"))
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(GREEN)),
+                ]));
+            }
+        }
+
+        if display_progress {
+            pb.inc();
+        }
+
+        index += 1;
+    }
+
+    if display_progress {
+        pb.finish_println("\n");
+    }
+
+    if total < 1 {
+        println_tr!("iotracectl-no-traces");
+    } else if table.len() > 1 {
+        if let Err(e) = bundle.save(output) {
+            println_tr!("iotracectl-bundle-write-failed",
+                "filename" => format!("{}", output.to_string_lossy()),
+                "error" => format!("{}", e)
+            );
+
+            return;
+        }
+
+        table.printstd();
+
+        println!();
+        println_tr!("iotracectl-summary-8",
+            "total" => format!("{}", total),
+            "matching" => format!("{}", bundle.traces.len()),
+            "errors" => format!("{}", errors)
+        );
+    } else {
+        println_tr!("iotracectl-no-matches");
+    }
+}
+
+/// Import I/O traces and the histogram of hot applications from a bundle file
+pub fn import_io_traces(config: &Config, daemon_config: util::ConfigFile) {
+    let state_dir = daemon_config
+        .clone()
+        .state_dir
+        .unwrap_or(Path::new(constants::STATE_DIR).to_path_buf());
+    let traces_path = state_dir.join(Path::new(constants::IOTRACE_DIR).to_path_buf());
+
+    let matches = config.matches.subcommand_matches("import").unwrap();
+    let input = Path::new(matches.value_of("FILE").unwrap());
+
+    let dry_run = matches.is_present("dryrun");
+    let force = matches.is_present("force");
+
+    let rules = match parse_remap_rules(&matches) {
+        Err(rule) => {
+            println_tr!("iotracectl-invalid-remap", "rule" => rule);
+            return;
+        }
+
+        Ok(rules) => rules,
+    };
+
+    let normalization_rules = iotrace::compile_normalization_rules(&daemon_config.get_cmdline_normalization_rules());
+
+    let TraceBundle {
+        traces,
+        app_histogram: bundle_histogram,
+        ..
+    } = match TraceBundle::from_file(input) {
+        Err(e) => {
+            println_tr!("iotracectl-bundle-read-failed",
+                "filename" => format!("{}", input.to_string_lossy()),
+                "error" => format!("{}", e)
+            );
+
+            return;
+        }
+
+        Ok(bundle) => bundle,
+    };
+
+    if !dry_run {
+        if let Err(e) = util::mkdir(&traces_path) {
+            error!("Could not create the I/O trace log directory {:?}: {}", &traces_path, e);
+            return;
+        }
+    }
+
+    // a missing histogram is fine, e.g. if the daemon has never been run on this system
+    let mut app_histogram = load_app_histogram(&state_dir).unwrap_or_default();
+    let mut histogram_changed = false;
+
+    let total = traces.len();
+    let mut pb = ProgressBar::new(total as u64);
+
+    let display_progress = unsafe { nix::libc::isatty(1) == 1 };
+
+    if display_progress {
+        pb.format(PROGRESS_BAR_INDICATORS);
+        pb.message(tr!("iotracectl-importing-trace-logs"));
+    }
+
+    let mut table = Table::new();
+    table.set_format(default_table_format(&config));
+
+    // Add table row header
+    table.add_row(Row::new(vec![
+        Cell::new("#"),
+        Cell::new(tr!("iotracectl-io-trace-log")),
+        Cell::new(tr!("status")),
+    ]));
+
+    let mut index = 0;
+    let mut matching = 0;
+    let mut errors = 0;
+
+    for value in traces {
+        match iotrace::IOTraceLog::from_value(value) {
+            Err(e) => {
+                error!("I/O trace log in bundle {:?} not readable: {}", input, e);
+
+                // Print in "tabular" format (the default)
+                table.add_row(Row::new(vec![
+                    Cell::new_align(&format!("{}", index + 1), Alignment::RIGHT),
+                    Cell::new(&input.to_string_lossy()).with_style(Attr::Bold),
+                    Cell::new(tr!("iotracectl-error"))
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(RED)),
+                ]));
+
+                errors += 1;
+            }
+
+            Ok(mut io_trace) => {
+                let original_hash = io_trace.hash.clone();
+                let valid = localize_io_trace(&mut io_trace, &rules, &normalization_rules);
+
+                let path = traces_path.join(Path::new(&format!("{}.trace", io_trace.hash)));
+                let filename = String::from(path.to_string_lossy());
+
+                let status = if !valid {
+                    Cell::new(tr!("iotracectl-import-invalid"))
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(YELLOW))
+                } else if path.exists() && !force {
+                    Cell::new(tr!("iotracectl-import-exists"))
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(YELLOW))
+                } else if dry_run {
+                    matching += 1;
+
+                    Cell::new(tr!("iotracectl-imported"))
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(GREEN))
+                } else {
+                    match io_trace.save(&path, 0, 0, true) {
+                        Err(e) => {
+                            error!("I/O trace log {:?} could not be saved: {}", &path, e);
+                            errors += 1;
+
+                            Cell::new(tr!("iotracectl-error"))
+                                .with_style(Attr::Bold)
+                                .with_style(Attr::ForegroundColor(RED))
+                        }
+
+                        Ok(_) => {
+                            if let Some(count) = bundle_histogram.get(&original_hash) {
+                                let val = app_histogram.entry(io_trace.hash.clone()).or_insert(0);
+                                *val = std::cmp::max(*val, *count);
+
+                                histogram_changed = true;
+                            }
+
+                            matching += 1;
+
+                            Cell::new(tr!("iotracectl-imported"))
+                                .with_style(Attr::Bold)
+                                .with_style(Attr::ForegroundColor(GREEN))
+                        }
+                    }
+                };
+
+                // Print in "tabular" format (the default)
+                table.add_row(Row::new(vec![
+                    Cell::new_align(&format!("{}", index + 1), Alignment::RIGHT),
+                    Cell::new(&filename).with_style(Attr::Bold),
+                    status,
+                ]));
+            }
+        }
+
+        if display_progress {
+            pb.inc();
+        }
+
+        index += 1;
+    }
+
+    if display_progress {
+        pb.finish_println("\n");
+    }
+
+    if histogram_changed {
+        if let Err(e) = save_app_histogram(&state_dir, &app_histogram) {
+            error!("Histogram of hot applications could not be saved! {}", e);
+            errors += 1;
+        }
+    }
+
+    if total < 1 {
+        println_tr!("iotracectl-no-traces");
+    } else {
+        table.printstd();
+
+        if dry_run {
+            println!();
+            println_tr!("iotracectl-summary-10",
+                "total" => format!("{}", total),
+                "matching" => format!("{}", matching),
+                "errors" => format!("{}", errors)
+            );
+        } else {
+            println!();
+            println_tr!("iotracectl-summary-9",
+                "total" => format!("{}", total),
+                "matching" => format!("{}", matching),
+                "errors" => format!("{}", errors)
+            );
+
+            if histogram_changed {
+                println_tr!("iotracectl-import-restart-daemon");
+            }
+        }
+    }
+}
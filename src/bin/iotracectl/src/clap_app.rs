@@ -163,6 +163,8 @@ where
                             tr!("filter-current"),
                             tr!("filter-outdated"),
                             tr!("filter-missing"),
+                            tr!("filter-truncated"),
+                            tr!("filter-corrupt"),
                         ])
                         .help(tr!("iotracectl-filter-iotrace")),
                 )
@@ -265,6 +267,8 @@ where
                             tr!("filter-current"),
                             tr!("filter-outdated"),
                             tr!("filter-missing"),
+                            tr!("filter-truncated"),
+                            tr!("filter-corrupt"),
                         ])
                         .help(tr!("iotracectl-filter-iotrace")),
                 )
@@ -345,6 +349,101 @@ where
                         .help(tr!("iotracectl-terse")),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .setting(AppSettings::DeriveDisplayOrder)
+                .about(&*tr!("iotracectl-diff"))
+                .arg(
+                    Arg::with_name("TRACE")
+                        .multiple(true)
+                        .min_values(2)
+                        .max_values(2)
+                        .required(false)
+                        .help(tr!("iotracectl-diff-trace")),
+                )
+                .arg(
+                    Arg::with_name("executable")
+                        .long("executable")
+                        .short("e")
+                        .takes_value(true)
+                        .required(false)
+                        .help(tr!("iotracectl-filter-executable")),
+                )
+                .arg(
+                    Arg::with_name("optimized")
+                        .long("optimized")
+                        .short("o")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[tr!("true"), tr!("false")])
+                        .help(tr!("iotracectl-filter-optimized")),
+                )
+                .arg(
+                    Arg::with_name("blacklisted")
+                        .long("blacklisted")
+                        .short("b")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[tr!("true"), tr!("false")])
+                        .help(tr!("iotracectl-filter-blacklisted")),
+                )
+                .arg(
+                    Arg::with_name("flags")
+                        .long("flags")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[
+                            tr!("filter-valid"),
+                            tr!("filter-invalid"),
+                            tr!("filter-fresh"),
+                            tr!("filter-expired"),
+                            tr!("filter-current"),
+                            tr!("filter-outdated"),
+                            tr!("filter-missing"),
+                            tr!("filter-truncated"),
+                            tr!("filter-corrupt"),
+                        ])
+                        .help(tr!("iotracectl-filter-iotrace")),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[
+                            tr!("sort-executable"),
+                            tr!("sort-hash"),
+                            tr!("sort-date"),
+                            tr!("sort-numfiles"),
+                            tr!("sort-numioops"),
+                            tr!("sort-iosize"),
+                            tr!("sort-optimized"),
+                            tr!("sort-blacklisted"),
+                        ])
+                        .default_value("date")
+                        .help(tr!("iotracectl-sort")),
+                )
+                .arg(
+                    Arg::with_name("order")
+                        .long("order")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[
+                            tr!("sort-asc"),
+                            tr!("sort-ascending"),
+                            tr!("sort-desc"),
+                            tr!("sort-descending"),
+                        ])
+                        .default_value(tr!("sort-ascending"))
+                        .help(tr!("iotracectl-sort-order")),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .short("j")
+                        .help(tr!("iotracectl-json")),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("sizes")
                 .setting(AppSettings::DeriveDisplayOrder)
@@ -410,6 +509,8 @@ where
                             tr!("filter-current"),
                             tr!("filter-outdated"),
                             tr!("filter-missing"),
+                            tr!("filter-truncated"),
+                            tr!("filter-corrupt"),
                         ])
                         .help(tr!("iotracectl-filter-iotrace")),
                 ),
@@ -465,6 +566,8 @@ where
                             tr!("filter-current"),
                             tr!("filter-outdated"),
                             tr!("filter-missing"),
+                            tr!("filter-truncated"),
+                            tr!("filter-corrupt"),
                         ])
                         .help(tr!("iotracectl-filter-iotrace")),
                 )
@@ -563,6 +666,8 @@ where
                                     tr!("filter-current"),
                                     tr!("filter-outdated"),
                                     tr!("filter-missing"),
+                                    tr!("filter-truncated"),
+                                    tr!("filter-corrupt"),
                                 ])
                                 .help(tr!("iotracectl-filter-iotrace")),
                         )
@@ -656,6 +761,8 @@ where
                                     tr!("filter-current"),
                                     tr!("filter-outdated"),
                                     tr!("filter-missing"),
+                                    tr!("filter-truncated"),
+                                    tr!("filter-corrupt"),
                                 ])
                                 .help(tr!("iotracectl-filter-iotrace")),
                         )
@@ -699,6 +806,132 @@ where
                         ),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("export")
+                .setting(AppSettings::DeriveDisplayOrder)
+                .about(&*tr!("iotracectl-export"))
+                .arg(
+                    Arg::with_name("hash")
+                        .long("hash")
+                        .short("p")
+                        .takes_value(true)
+                        .required(false)
+                        .help(tr!("iotracectl-filter-hash")),
+                )
+                .arg(
+                    Arg::with_name("executable")
+                        .long("executable")
+                        .short("e")
+                        .takes_value(true)
+                        .required(false)
+                        .help(tr!("iotracectl-filter-executable")),
+                )
+                .arg(
+                    Arg::with_name("optimized")
+                        .long("optimized")
+                        .short("o")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[tr!("true"), tr!("false")])
+                        .help(tr!("iotracectl-filter-optimized")),
+                )
+                .arg(
+                    Arg::with_name("blacklisted")
+                        .long("blacklisted")
+                        .short("b")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[tr!("true"), tr!("false")])
+                        .help(tr!("iotracectl-filter-blacklisted")),
+                )
+                .arg(
+                    Arg::with_name("flags")
+                        .long("flags")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[
+                            tr!("filter-valid"),
+                            tr!("filter-invalid"),
+                            tr!("filter-fresh"),
+                            tr!("filter-expired"),
+                            tr!("filter-current"),
+                            tr!("filter-outdated"),
+                            tr!("filter-missing"),
+                            tr!("filter-truncated"),
+                            tr!("filter-corrupt"),
+                        ])
+                        .help(tr!("iotracectl-filter-iotrace")),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[
+                            tr!("sort-executable"),
+                            tr!("sort-hash"),
+                            tr!("sort-date"),
+                            tr!("sort-numfiles"),
+                            tr!("sort-numioops"),
+                            tr!("sort-iosize"),
+                            tr!("sort-optimized"),
+                            tr!("sort-blacklisted"),
+                        ])
+                        .default_value("date")
+                        .help(tr!("iotracectl-sort")),
+                )
+                .arg(
+                    Arg::with_name("order")
+                        .long("order")
+                        .takes_value(true)
+                        .required(false)
+                        .possible_values(&[
+                            tr!("sort-asc"),
+                            tr!("sort-ascending"),
+                            tr!("sort-desc"),
+                            tr!("sort-descending"),
+                        ])
+                        .default_value(tr!("sort-ascending"))
+                        .help(tr!("iotracectl-sort-order")),
+                )
+                .arg(
+                    Arg::with_name("FILE")
+                        .required(true)
+                        .help(tr!("iotracectl-export-file")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .setting(AppSettings::DeriveDisplayOrder)
+                .about(&*tr!("iotracectl-import"))
+                .arg(
+                    Arg::with_name("remap")
+                        .long("remap")
+                        .short("r")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(false)
+                        .help(tr!("iotracectl-remap")),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .short("f")
+                        .help(tr!("iotracectl-force")),
+                )
+                .arg(
+                    Arg::with_name("dryrun")
+                        .long("dry-run")
+                        .short("n")
+                        .help(tr!("iotracectl-dry-run")),
+                )
+                .arg(
+                    Arg::with_name("FILE")
+                        .required(true)
+                        .help(tr!("iotracectl-import-file")),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("remove")
                 .setting(AppSettings::DeriveDisplayOrder)
@@ -751,6 +984,8 @@ where
                             tr!("filter-current"),
                             tr!("filter-outdated"),
                             tr!("filter-missing"),
+                            tr!("filter-truncated"),
+                            tr!("filter-corrupt"),
                         ])
                         .help(tr!("iotracectl-filter-iotrace")),
                 )
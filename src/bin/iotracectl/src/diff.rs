@@ -0,0 +1,490 @@
+/*
+    Precached - A Linux process monitor and pre-caching daemon
+    Copyright (C) 2017-2020 the precached developers
+
+    This file is part of precached.
+
+    Precached is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Precached is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Precached.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::fs::read_dir;
+use std::io;
+use std::path::{Path, PathBuf};
+use log::{trace, debug, info, warn, error, log, LevelFilter};
+use pbr::ProgressBar;
+use prettytable::Cell;
+use prettytable::format::*;
+use prettytable::Row;
+use prettytable::Table;
+use serde_derive::Serialize;
+use term::color::*;
+use term::Attr;
+use crate::constants;
+use crate::iotrace;
+use crate::util;
+use crate::{default_table_format, format_date, Config, PROGRESS_BAR_INDICATORS};
+use crate::{get_io_traces_filtered_and_sorted, parse_sort_field, parse_sort_order};
+
+/// The kinds of changes of a file between two I/O trace logs
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum FileChange {
+    /// The file is only referenced by the new I/O trace log
+    Added,
+    /// The file is only referenced by the old I/O trace log
+    Removed,
+    /// The amount of data referenced by the I/O trace logs differs
+    Resized,
+    /// The file is accessed at a different position, relative to the files referenced by both I/O trace logs
+    Moved,
+}
+
+pub fn map_file_change_to_string(change: FileChange) -> String {
+    match change {
+        FileChange::Added => tr!("diff-added").to_string(),
+        FileChange::Removed => tr!("diff-removed").to_string(),
+        FileChange::Resized => tr!("diff-resized").to_string(),
+        FileChange::Moved => tr!("diff-moved").to_string(),
+    }
+}
+
+/// A file that differs between two I/O trace logs
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiff {
+    pub filename: PathBuf,
+    pub changes: Vec<FileChange>,
+    /// Position of the first access to the file in the old I/O trace log
+    pub old_position: Option<usize>,
+    /// Position of the first access to the file in the new I/O trace log
+    pub new_position: Option<usize>,
+    pub old_size: Option<u64>,
+    pub new_size: Option<u64>,
+    pub size_delta: i64,
+}
+
+/// The differences between two I/O trace logs
+#[derive(Debug, Clone, Serialize)]
+pub struct IOTraceDiff {
+    pub old_filename: PathBuf,
+    pub new_filename: PathBuf,
+    pub old_hash: String,
+    pub new_hash: String,
+    pub old_accumulated_size: u64,
+    pub new_accumulated_size: u64,
+    pub accumulated_size_delta: i64,
+    pub old_num_ioops: usize,
+    pub new_num_ioops: usize,
+    /// All files that differ, ordered by their position in the new,
+    /// followed by removed files in the order of the old I/O trace log
+    pub files: Vec<FileDiff>,
+}
+
+impl IOTraceDiff {
+    pub fn count(&self, change: FileChange) -> usize {
+        self.files.iter().filter(|f| f.changes.contains(&change)).count()
+    }
+}
+
+/// Returns the position of the first access and the accumulated
+/// size of every file referenced by the I/O trace log `io_trace`
+fn summarize_files(io_trace: &iotrace::IOTraceLog) -> HashMap<PathBuf, (usize, u64)> {
+    let mut result = HashMap::new();
+
+    for entry in io_trace.trace_log.iter() {
+        let position = result.len();
+        let val = result
+            .entry(entry.operation.get_filename().to_path_buf())
+            .or_insert((position, 0));
+
+        val.1 += entry.size;
+    }
+
+    result
+}
+
+/// Returns the files of `files` that are contained in `other`, sorted by position
+fn get_common_files(files: &HashMap<PathBuf, (usize, u64)>, other: &HashMap<PathBuf, (usize, u64)>) -> Vec<PathBuf> {
+    let mut result: Vec<(&PathBuf, usize)> = files
+        .iter()
+        .filter(|(k, _)| other.contains_key(*k))
+        .map(|(k, v)| (k, v.0))
+        .collect();
+
+    result.sort_by(|a, b| a.1.cmp(&b.1));
+
+    result.into_iter().map(|(k, _)| k.clone()).collect()
+}
+
+/// Compare the I/O trace logs `old` and `new`. Files that are referenced by both of them count
+/// as moved, if their rank among the common files differs, so that added or removed files do
+/// not cause all subsequent files to be reported as moved
+pub fn diff_io_traces(old: (&iotrace::IOTraceLog, &Path), new: (&iotrace::IOTraceLog, &Path)) -> IOTraceDiff {
+    let (old_trace, old_filename) = old;
+    let (new_trace, new_filename) = new;
+
+    let old_files = summarize_files(old_trace);
+    let new_files = summarize_files(new_trace);
+
+    let old_ranks: HashMap<PathBuf, usize> = get_common_files(&old_files, &new_files)
+        .into_iter()
+        .enumerate()
+        .map(|(rank, filename)| (filename, rank))
+        .collect();
+
+    let new_ranks: HashMap<PathBuf, usize> = get_common_files(&new_files, &old_files)
+        .into_iter()
+        .enumerate()
+        .map(|(rank, filename)| (filename, rank))
+        .collect();
+
+    let mut files = vec![];
+
+    let mut new_sorted: Vec<(&PathBuf, &(usize, u64))> = new_files.iter().collect();
+    new_sorted.sort_by(|a, b| (a.1).0.cmp(&(b.1).0));
+
+    for (filename, (new_position, new_size)) in new_sorted {
+        let mut changes = vec![];
+
+        let (old_position, old_size) = match old_files.get(filename) {
+            None => {
+                changes.push(FileChange::Added);
+
+                (None, None)
+            }
+
+            Some((old_position, old_size)) => {
+                if old_size != new_size {
+                    changes.push(FileChange::Resized);
+                }
+
+                if old_ranks.get(filename) != new_ranks.get(filename) {
+                    changes.push(FileChange::Moved);
+                }
+
+                (Some(*old_position), Some(*old_size))
+            }
+        };
+
+        if !changes.is_empty() {
+            files.push(FileDiff {
+                filename: filename.clone(),
+                changes,
+                old_position,
+                new_position: Some(*new_position),
+                old_size,
+                new_size: Some(*new_size),
+                size_delta: *new_size as i64 - old_size.unwrap_or(0) as i64,
+            });
+        }
+    }
+
+    let mut old_sorted: Vec<(&PathBuf, &(usize, u64))> = old_files.iter().filter(|(k, _)| !new_files.contains_key(*k)).collect();
+    old_sorted.sort_by(|a, b| (a.1).0.cmp(&(b.1).0));
+
+    for (filename, (old_position, old_size)) in old_sorted {
+        files.push(FileDiff {
+            filename: filename.clone(),
+            changes: vec![FileChange::Removed],
+            old_position: Some(*old_position),
+            new_position: None,
+            old_size: Some(*old_size),
+            new_size: None,
+            size_delta: -(*old_size as i64),
+        });
+    }
+
+    IOTraceDiff {
+        old_filename: old_filename.to_path_buf(),
+        new_filename: new_filename.to_path_buf(),
+        old_hash: old_trace.hash.clone(),
+        new_hash: new_trace.hash.clone(),
+        old_accumulated_size: old_trace.accumulated_size,
+        new_accumulated_size: new_trace.accumulated_size,
+        accumulated_size_delta: new_trace.accumulated_size as i64 - old_trace.accumulated_size as i64,
+        old_num_ioops: old_trace.trace_log.len(),
+        new_num_ioops: new_trace.trace_log.len(),
+        files,
+    }
+}
+
+/// Load the I/O trace log `spec`, which may either be the file name
+/// of an I/O trace log, or the hash value of an I/O trace log
+fn load_io_trace(spec: &str, traces_path: &Path) -> io::Result<(iotrace::IOTraceLog, PathBuf)> {
+    let path = Path::new(spec);
+
+    let filename = if path.is_file() {
+        path.to_path_buf()
+    } else {
+        traces_path.join(Path::new(&format!("{}.trace", spec)))
+    };
+
+    let io_trace = iotrace::IOTraceLog::from_file(&filename)?;
+
+    Ok((io_trace, filename))
+}
+
+/// Format a size delta in KiB, including its sign
+fn format_size_delta(delta: i64) -> String {
+    if delta > 0 {
+        format!("+{} KiB", delta / 1024)
+    } else {
+        format!("{} KiB", delta / 1024)
+    }
+}
+
+/// Format an optional value, using "n/a" for missing values
+fn format_optional<T: std::fmt::Display>(value: Option<T>, unit: &str) -> String {
+    match value {
+        None => tr!("na").to_string(),
+        Some(value) => format!("{}{}", value, unit),
+    }
+}
+
+/// Compare two I/O trace logs, given either on the command line, or
+/// selected by the filter parameters matching exactly two I/O trace logs
+pub fn diff_io_traces_cmd(config: &Config, daemon_config: util::ConfigFile) {
+    let state_dir = daemon_config
+        .clone()
+        .state_dir
+        .unwrap_or(Path::new(constants::STATE_DIR).to_path_buf());
+    let traces_path = state_dir.join(Path::new(constants::IOTRACE_DIR).to_path_buf());
+
+    let matches = config.matches.subcommand_matches("diff").unwrap();
+
+    let traces: Vec<(iotrace::IOTraceLog, PathBuf)> = if let Some(specs) = matches.values_of("TRACE") {
+        let mut result = vec![];
+
+        for spec in specs {
+            match load_io_trace(spec, &traces_path) {
+                Err(e) => {
+                    error!("Invalid I/O trace file, file not readable: {}", e);
+                    println_tr!("iotracectl-no-matches");
+
+                    return;
+                }
+
+                Ok(trace) => result.push(trace),
+            }
+        }
+
+        result
+    } else {
+        let count = read_dir(&traces_path).unwrap().count();
+        let mut pb = ProgressBar::new(count as u64);
+
+        let display_progress = unsafe { nix::libc::isatty(1) == 1 } && !matches.is_present("json");
+
+        if display_progress {
+            pb.format(PROGRESS_BAR_INDICATORS);
+        }
+
+        let (result, _total, _matching, _errors) = get_io_traces_filtered_and_sorted(
+            config,
+            daemon_config,
+            display_progress,
+            &mut pb,
+            &matches,
+            parse_sort_field(&matches),
+            parse_sort_order(&matches),
+        )
+        .unwrap();
+
+        if display_progress {
+            pb.finish_println("\n");
+        }
+
+        result
+    };
+
+    if traces.len() != 2 {
+        println_tr!("iotracectl-diff-requires-two", "matching" => format!("{}", traces.len()));
+        return;
+    }
+
+    let diff = diff_io_traces((&traces[0].0, &traces[0].1), (&traces[1].0, &traces[1].1));
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+        return;
+    }
+
+    println_tr!("iotracectl-diff-header",
+        "oldfilename" => format!("{}", diff.old_filename.to_string_lossy()),
+        "olddate" => format_date(traces[0].0.created_at),
+        "newfilename" => format!("{}", diff.new_filename.to_string_lossy()),
+        "newdate" => format_date(traces[1].0.created_at)
+    );
+
+    println!();
+
+    if diff.files.is_empty() {
+        println_tr!("iotracectl-diff-no-changes");
+    } else {
+        let mut table = Table::new();
+        table.set_format(default_table_format(&config));
+
+        // Add table row header
+        table.add_row(Row::new(vec![
+            Cell::new("#"),
+            Cell::new(tr!("file")),
+            Cell::new(tr!("iotracectl-diff-change")),
+            Cell::new(tr!("iotracectl-diff-position")),
+            Cell::new(tr!("io-size")),
+            Cell::new(tr!("iotracectl-diff-size-delta")),
+        ]));
+
+        for (index, file) in diff.files.iter().enumerate() {
+            let changes = file
+                .changes
+                .iter()
+                .map(|c| map_file_change_to_string(*c))
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            let color = if file.changes.contains(&FileChange::Removed) {
+                RED
+            } else if file.changes.contains(&FileChange::Added) {
+                GREEN
+            } else {
+                YELLOW
+            };
+
+            table.add_row(Row::new(vec![
+                Cell::new_align(&format!("{}", index + 1), Alignment::RIGHT),
+                Cell::new(&file.filename.to_string_lossy()).with_style(Attr::Bold),
+                Cell::new(&changes)
+                    .with_style(Attr::Bold)
+                    .with_style(Attr::ForegroundColor(color)),
+                Cell::new_align(
+                    &format!(
+                        "{} -> {}",
+                        format_optional(file.old_position.map(|p| p + 1), ""),
+                        format_optional(file.new_position.map(|p| p + 1), "")
+                    ),
+                    Alignment::RIGHT,
+                ),
+                Cell::new_align(
+                    &format!(
+                        "{} -> {}",
+                        format_optional(file.old_size.map(|s| s / 1024), " KiB"),
+                        format_optional(file.new_size.map(|s| s / 1024), " KiB")
+                    ),
+                    Alignment::RIGHT,
+                ),
+                Cell::new_align(&format_size_delta(file.size_delta), Alignment::RIGHT),
+            ]));
+        }
+
+        // Print the generated table to stdout
+        table.printstd();
+    }
+
+    println!();
+    println_tr!("iotracectl-diff-summary",
+        "added" => format!("{}", diff.count(FileChange::Added)),
+        "removed" => format!("{}", diff.count(FileChange::Removed)),
+        "resized" => format!("{}", diff.count(FileChange::Resized)),
+        "moved" => format!("{}", diff.count(FileChange::Moved)),
+        "oldsize" => format!("{} KiB", diff.old_accumulated_size / 1024),
+        "newsize" => format!("{} KiB", diff.new_accumulated_size / 1024),
+        "delta" => format_size_delta(diff.accumulated_size_delta)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use crate::diff::*;
+    use crate::iotrace::*;
+
+    fn create_io_trace(files: &[(&str, u64)]) -> IOTraceLog {
+        let mut io_trace: IOTraceLog = serde_json::from_value(serde_json::json!({
+            "format_version": IOTRACE_FORMAT_VERSION, "hash": "42", "match_key": "Normalized",
+            "exe": "/usr/bin/app", "comm": "app", "cmdline": "app",
+            "created_at": "2020-01-01T00:00:00Z", "trace_stopped_at": "2020-01-01T00:00:00Z",
+            "stop_reason": "Quiescent", "file_map": {}, "trace_log": [], "accumulated_size": 0,
+            "num_runs": 1, "run_hits": {}, "trace_log_optimized": false, "truncated": false
+        }))
+        .unwrap();
+
+        for (filename, size) in files {
+            io_trace
+                .trace_log
+                .push(TraceLogEntry::new(IOOperation::Open(PathBuf::from(filename)), *size));
+            io_trace.accumulated_size += size;
+        }
+
+        io_trace
+    }
+
+    fn diff(old: &IOTraceLog, new: &IOTraceLog) -> IOTraceDiff {
+        diff_io_traces((old, Path::new("old.trace")), (new, Path::new("new.trace")))
+    }
+
+    fn get_changes(diff: &IOTraceDiff, filename: &str) -> Vec<FileChange> {
+        diff.files
+            .iter()
+            .find(|f| f.filename == Path::new(filename))
+            .map(|f| f.changes.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_diff_identical_io_traces() {
+        let io_trace = create_io_trace(&[("/a", 4096), ("/b", 8192)]);
+
+        let diff = diff(&io_trace, &io_trace.clone());
+
+        assert!(diff.files.is_empty());
+        assert_eq!(0, diff.accumulated_size_delta);
+        assert_eq!(diff.old_num_ioops, diff.new_num_ioops);
+    }
+
+    #[test]
+    fn test_diff_added_and_removed_files() {
+        let old = create_io_trace(&[("/a", 4096), ("/b", 8192)]);
+        let new = create_io_trace(&[("/c", 1024), ("/a", 4096)]);
+
+        let diff = diff(&old, &new);
+
+        assert_eq!(2, diff.files.len());
+        assert_eq!(vec![FileChange::Added], get_changes(&diff, "/c"));
+        assert_eq!(vec![FileChange::Removed], get_changes(&diff, "/b"));
+
+        // removed files are listed last
+        assert_eq!(PathBuf::from("/b"), diff.files[1].filename);
+        assert_eq!(-8192, diff.files[1].size_delta);
+
+        // an added file in front of "/a" does not count as a move of "/a"
+        assert!(get_changes(&diff, "/a").is_empty());
+        assert_eq!(1024 - 8192, diff.accumulated_size_delta);
+    }
+
+    #[test]
+    fn test_diff_changed_files() {
+        let old = create_io_trace(&[("/a", 4096), ("/b", 8192), ("/c", 1024)]);
+        let new = create_io_trace(&[("/b", 8192), ("/a", 4096), ("/c", 2048)]);
+
+        let diff = diff(&old, &new);
+
+        assert_eq!(3, diff.files.len());
+        assert_eq!(vec![FileChange::Moved], get_changes(&diff, "/a"));
+        assert_eq!(vec![FileChange::Moved], get_changes(&diff, "/b"));
+        assert_eq!(vec![FileChange::Resized], get_changes(&diff, "/c"));
+
+        assert_eq!(1, diff.count(FileChange::Resized));
+        assert_eq!(1024, diff.files[2].size_delta);
+        assert_eq!(1024, diff.accumulated_size_delta);
+    }
+}
@@ -42,8 +42,10 @@ use crate::i18n::initialize_i18n;
 
 #[macro_use]
 mod i18n;
+mod bundle;
 mod clap_app;
 mod constants;
+mod diff;
 mod iotrace;
 mod plugins;
 mod process;
@@ -197,7 +199,9 @@ fn filter_matches(matches: &ArgMatches, _filename: &String, io_trace: &iotrace::
                 return true;
             }
 
-            if result.contains(&IOTraceLogFlag::Outdated) && value == tr!("filter-outdated") {
+            if (result.contains(&IOTraceLogFlag::Outdated) || result.contains(&IOTraceLogFlag::BuildIdMismatch))
+                && value == tr!("filter-outdated")
+            {
                 return true;
             }
 
@@ -205,6 +209,14 @@ fn filter_matches(matches: &ArgMatches, _filename: &String, io_trace: &iotrace::
                 return true;
             }
 
+            if result.contains(&IOTraceLogFlag::Truncated) && value == tr!("filter-truncated") {
+                return true;
+            }
+
+            if result.contains(&IOTraceLogFlag::Corrupt) && value == tr!("filter-corrupt") {
+                return true;
+            }
+
             return false;
         }
     }
@@ -242,13 +254,18 @@ fn print_io_trace(filename: &Path, io_trace: &iotrace::IOTraceLog, _index: usize
             "command" => format!("{}", io_trace.comm),
             "commandline" => format!("{}", io_trace.cmdline),
             "hash" => format!("{}", io_trace.hash),
+            "matchkey" => iotrace::map_trace_match_key_to_string(io_trace.match_key),
+            "buildid" => io_trace.build_id.clone().unwrap_or_else(|| tr!("unknown").to_string()),
+            "formatversion" => format_format_version(&io_trace),
             "creationdate" => format_date(io_trace.created_at),
             "enddate" => format_date(io_trace.trace_stopped_at),
+            "stopreason" => iotrace::map_trace_stop_reason_to_string(io_trace.stop_reason),
             "numfiles" => format!("{}", io_trace.file_map.len()),
             "numioops" => format!("{}", io_trace.trace_log.len()),
             "iosize" => format!("{} KiB", io_trace.accumulated_size / 1024),
             "optimized" => tr!(&format!("{}", io_trace.trace_log_optimized)),
             "blacklisted" => tr!(&format!("{}", io_trace.blacklisted)),
+            "staleness" => format_staleness(&io_trace),
             "flags" => format!("{:?}", flags)
         );
 
@@ -294,6 +311,9 @@ fn print_io_trace(filename: &Path, io_trace: &iotrace::IOTraceLog, _index: usize
             Cell::new(tr!(&format!("{}", io_trace.blacklisted)))
                 .with_style(Attr::Bold)
                 .with_style(Attr::ForegroundColor(map_bool_to_color_blacklist(io_trace.blacklisted))),
+            Cell::new_align(&format!("{}", io_trace.source_format_version), Alignment::RIGHT)
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(map_bool_to_color(!io_trace.is_format_outdated()))),
             Cell::new(&format!("{}", flags))
                 .with_style(Attr::Bold)
                 .with_style(Attr::ForegroundColor(color)),
@@ -330,10 +350,42 @@ fn print_io_trace_msg(message: &str, _index: usize, config: &Config, table: &mut
             Cell::new(tr!("na")),
             Cell::new(tr!("na")),
             Cell::new(tr!("na")),
+            Cell::new(tr!("na")),
         ]));
     }
 }
 
+/// Returns a human readable description of the on-disk format version of `io_trace`
+fn format_format_version(io_trace: &iotrace::IOTraceLog) -> String {
+    if io_trace.is_format_outdated() {
+        String::from(tr!("iotracectl-format-version-outdated",
+            "version" => format!("{}", io_trace.source_format_version),
+            "current" => format!("{}", iotrace::IOTRACE_FORMAT_VERSION)
+        ))
+    } else {
+        format!("{}", io_trace.source_format_version)
+    }
+}
+
+/// Returns a human readable description of how stale the I/O trace log `io_trace` is
+fn format_staleness(io_trace: &iotrace::IOTraceLog) -> String {
+    let (changed, missing) = util::get_io_trace_staleness(&io_trace);
+    let total = io_trace.trace_log.len();
+
+    let percentage = if total > 0 {
+        (changed + missing) as f64 * 100.0 / total as f64
+    } else {
+        0.0
+    };
+
+    String::from(tr!("iotracectl-staleness",
+        "changed" => format!("{}", changed),
+        "missing" => format!("{}", missing),
+        "total" => format!("{}", total),
+        "percentage" => format!("{:.1}", percentage)
+    ))
+}
+
 fn map_bool_to_color(b: bool) -> Color {
     if b {
         GREEN
@@ -511,10 +563,28 @@ where
     match util::walk_directories(&vec![traces_path], &mut |path| {
         // trace!("{:?}", path);
 
+        // skip leftovers of interrupted writes
+        if util::is_temporary_file(path) {
+            return;
+        }
+
         let filename = String::from(path.to_string_lossy());
         match iotrace::IOTraceLog::from_file(&path) {
             Err(e) => {
-                error!("I/O trace log file {:?} not readable: {}", &path, e);
+                if iotrace::is_corrupt_error(&e) {
+                    error!("I/O trace log file {:?} is corrupt: {}", &path, e);
+
+                    // list corrupt I/O trace logs too, so that they can be selected by the flag filters
+                    let io_trace = iotrace::IOTraceLog::new_corrupt(&path);
+
+                    if filter_matches(matches, &filename, &io_trace, &config) {
+                        result.push((io_trace, path.to_path_buf()));
+                        matching += 1;
+                    }
+                } else {
+                    error!("I/O trace log file {:?} not readable: {}", &path, e);
+                }
+
                 errors += 1;
             }
 
@@ -670,6 +740,7 @@ fn list_io_traces(config: &Config, daemon_config: util::ConfigFile) {
         Cell::new(tr!("iotracectl-iosize")),
         Cell::new(tr!("iotracectl-optimized")),
         Cell::new(tr!("iotracectl-blacklisted")),
+        Cell::new(tr!("iotracectl-format-version")),
         Cell::new(tr!("iotracectl-flags")),
     ]));
 
@@ -715,13 +786,18 @@ fn print_io_trace_info(filename: &Path, io_trace: &iotrace::IOTraceLog, _index:
         "command" => format!("{}", io_trace.comm),
         "commandline" => format!("{}", io_trace.cmdline),
         "hash" => format!("{}", io_trace.hash),
+        "matchkey" => iotrace::map_trace_match_key_to_string(io_trace.match_key),
+        "buildid" => io_trace.build_id.clone().unwrap_or_else(|| tr!("unknown").to_string()),
+        "formatversion" => format_format_version(&io_trace),
         "creationdate" => format_date(io_trace.created_at),
         "enddate" => format_date(io_trace.trace_stopped_at),
+        "stopreason" => iotrace::map_trace_stop_reason_to_string(io_trace.stop_reason),
         "numfiles" => format!("{}", io_trace.file_map.len()),
         "numioops" => format!("{}", io_trace.trace_log.len()),
         "iosize" => format!("{} KiB", io_trace.accumulated_size / 1024),
         "optimized" => tr!(&format!("{}", io_trace.trace_log_optimized)),
         "blacklisted" => tr!(&format!("{}", io_trace.blacklisted)),
+        "staleness" => format_staleness(&io_trace),
         "flags" => format!("{:?}", flags.0)
     );
 
@@ -823,13 +899,18 @@ fn dump_io_traces(config: &Config, daemon_config: util::ConfigFile) {
                 "command" => format!("{}", io_trace.comm),
                 "commandline" => format!("{}", io_trace.cmdline),
                 "hash" => format!("{}", io_trace.hash),
+                "matchkey" => iotrace::map_trace_match_key_to_string(io_trace.match_key),
+                "buildid" => io_trace.build_id.clone().unwrap_or_else(|| tr!("unknown").to_string()),
+                "formatversion" => format_format_version(&io_trace),
                 "creationdate" => format_date(io_trace.created_at),
                 "enddate" => format_date(io_trace.trace_stopped_at),
+                "stopreason" => iotrace::map_trace_stop_reason_to_string(io_trace.stop_reason),
                 "numfiles" => format!("{}", io_trace.file_map.len()),
                 "numioops" => format!("{}", io_trace.trace_log.len()),
                 "iosize" => format!("{} KiB", io_trace.accumulated_size / 1024),
                 "optimized" => tr!(&format!("{}", io_trace.trace_log_optimized)),
                 "blacklisted" => tr!(&format!("{}", io_trace.blacklisted)),
+                "staleness" => format_staleness(&io_trace),
                 "flags" => format!("{:?}", flags.0)
             );
 
@@ -847,6 +928,7 @@ fn dump_io_traces(config: &Config, daemon_config: util::ConfigFile) {
             table.add_row(Row::new(vec![
                 Cell::new("#"),
                 Cell::new(tr!("timestamp")),
+                Cell::new(tr!("process")),
                 Cell::new(tr!("io-ops")),
                 Cell::new(tr!("io-size")),
             ]));
@@ -860,6 +942,7 @@ fn dump_io_traces(config: &Config, daemon_config: util::ConfigFile) {
                     table.add_row(Row::new(vec![
                         Cell::new_align(&format!("{}", index + 1), Alignment::RIGHT),
                         Cell::new(&format_date(e.timestamp)),
+                        Cell::new(e.origin.as_ref().unwrap_or(&io_trace.comm)),
                         Cell::new(&format!("{:?}", e.operation)),
                         Cell::new_align(&format!("{} KiB", e.size / 1024), Alignment::RIGHT),
                     ]));
@@ -940,13 +1023,18 @@ fn analyze_io_traces(config: &Config, daemon_config: util::ConfigFile) {
                 "command" => format!("{}", io_trace.comm),
                 "commandline" => format!("{}", io_trace.cmdline),
                 "hash" => format!("{}", io_trace.hash),
+                "matchkey" => iotrace::map_trace_match_key_to_string(io_trace.match_key),
+                "buildid" => io_trace.build_id.clone().unwrap_or_else(|| tr!("unknown").to_string()),
+                "formatversion" => format_format_version(&io_trace),
                 "creationdate" => format_date(io_trace.created_at),
                 "enddate" => format_date(io_trace.trace_stopped_at),
+                "stopreason" => iotrace::map_trace_stop_reason_to_string(io_trace.stop_reason),
                 "numfiles" => format!("{}", io_trace.file_map.len()),
                 "numioops" => format!("{}", io_trace.trace_log.len()),
                 "iosize" => format!("{} KiB", io_trace.accumulated_size / 1024),
                 "optimized" => tr!(&format!("{}", io_trace.trace_log_optimized)),
                 "blacklisted" => tr!(&format!("{}", io_trace.blacklisted)),
+                "staleness" => format_staleness(&io_trace),
                 "flags" => format!("{:?}", flags.0)
             );
 
@@ -964,6 +1052,7 @@ fn analyze_io_traces(config: &Config, daemon_config: util::ConfigFile) {
             table.add_row(Row::new(vec![
                 Cell::new("#"),
                 Cell::new(tr!("timestamp")),
+                Cell::new(tr!("process")),
                 Cell::new(tr!("io-ops")),
                 Cell::new(tr!("io-size")),
                 Cell::new(tr!("flags")),
@@ -983,6 +1072,7 @@ fn analyze_io_traces(config: &Config, daemon_config: util::ConfigFile) {
                     table.add_row(Row::new(vec![
                         Cell::new_align(&format!("{}", index), Alignment::RIGHT),
                         Cell::new(&format_date(e.timestamp)),
+                        Cell::new(e.origin.as_ref().unwrap_or(&io_trace.comm)),
                         Cell::new(&format!("{:?}", e.operation)),
                         Cell::new_align(&format!("{} KiB", e.size / 1024), Alignment::RIGHT),
                         Cell::new(&format!("{}", flags))
@@ -1068,7 +1158,7 @@ fn display_io_traces_sizes(config: &Config, daemon_config: util::ConfigFile) {
         // Build a histogram of files (make unique)
         for entry in io_trace.trace_log {
             match entry.operation {
-                IOOperation::Open(path) => {
+                IOOperation::Open(path) | IOOperation::OpenRanges(path, _) | IOOperation::Exec(path) => {
                     let &mut (ref mut cnt, ref mut vm_size) = files_histogram.entry(path).or_insert((0, 0));
 
                     // count size of every file only once
@@ -1078,7 +1168,9 @@ fn display_io_traces_sizes(config: &Config, daemon_config: util::ConfigFile) {
 
                     *cnt += 1;
                     *vm_size = entry.size;
-                } // _ => { /* Ignore all others */ }
+                }
+
+                _ => { /* Ignore all others */ }
             }
         }
 
@@ -1574,6 +1666,10 @@ fn main() {
                 analyze_io_traces(&config, daemon_config.clone());
             }
 
+            "diff" => {
+                diff::diff_io_traces_cmd(&config, daemon_config.clone());
+            }
+
             "sizes" => {
                 display_io_traces_sizes(&config, daemon_config.clone());
             }
@@ -1606,6 +1702,14 @@ fn main() {
                 }
             }
 
+            "export" => {
+                bundle::export_io_traces(&config, daemon_config.clone());
+            }
+
+            "import" => {
+                bundle::import_io_traces(&config, daemon_config.clone());
+            }
+
             "remove" | "delete" => {
                 remove_io_traces(&config, daemon_config.clone());
             }
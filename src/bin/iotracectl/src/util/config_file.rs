@@ -36,6 +36,7 @@ pub struct ConfigFile {
     pub whitelist: Option<Vec<PathBuf>>,
     pub blacklist: Option<Vec<PathBuf>>,
     pub disabled_plugins: Option<Vec<String>>,
+    pub cmdline_normalization_rules: Option<Vec<String>>,
 }
 
 impl Default for ConfigFile {
@@ -52,6 +53,12 @@ impl Default for ConfigFile {
             whitelist: Some(vec![PathBuf::new()]),
             blacklist: Some(vec![PathBuf::new()]),
             disabled_plugins: Some(vec![String::from("")]),
+            cmdline_normalization_rules: Some(
+                constants::CMDLINE_NORMALIZATION_RULES
+                    .iter()
+                    .map(|r| String::from(*r))
+                    .collect(),
+            ),
         }
     }
 }
@@ -81,4 +88,13 @@ impl ConfigFile {
     pub fn get_disabled_plugins(&self) -> Vec<String> {
         self.disabled_plugins.clone().unwrap_or_default()
     }
+
+    pub fn get_cmdline_normalization_rules(&self) -> Vec<String> {
+        self.cmdline_normalization_rules.clone().unwrap_or_else(|| {
+            constants::CMDLINE_NORMALIZATION_RULES
+                .iter()
+                .map(|r| String::from(*r))
+                .collect()
+        })
+    }
 }
@@ -49,7 +49,32 @@ pub struct InternalState {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GlobalStatistics {}
+pub struct PipelineStatistics {
+    pub backend: String,
+    pub events_received: u64,
+    pub events_processed: u64,
+    pub events_dropped: u64,
+    pub queue_depth: usize,
+    pub max_queue_depth: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThrottleStatistics {
+    pub throttle_events: u64,
+    pub throttled_millis: u64,
+    pub aborted_chunks: u64,
+    pub io_pressure: Option<f32>,
+    pub disk_utilization: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GlobalStatistics {
+    // Hook: Fanotify Logger
+    pub fanotify_pipeline: Option<PipelineStatistics>,
+
+    // Hook: I/O Trace Prefetcher
+    pub prefetch_throttling: Option<ThrottleStatistics>,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum IpcCommand {
@@ -375,7 +375,7 @@ pub fn display_global_stats(config: &Config, _daemon_config: &util::ConfigFile)
                             trace!("{:?}", data);
 
                             match data.command {
-                                ipc::IpcCommand::SendGlobalStatistics(_stats) => {
+                                ipc::IpcCommand::SendGlobalStatistics(stats) => {
                                     // Print in "tabular" format (the default)
                                     let mut table = prettytable::Table::new();
                                     table.set_format(default_table_format(&config));
@@ -387,11 +387,92 @@ pub fn display_global_stats(config: &Config, _daemon_config: &util::ConfigFile)
                                         Cell::new(tr!("status")),
                                     ]));
 
-                                    let field_defs = vec![(
-                                        String::from("Not implemented"),
-                                        fmt_option(Some(0)),
-                                        fmt_cell(Some(0), Some(ValueRange::new(0..usize::max_value(), 0..0, 0..0))),
-                                    )];
+                                    let pipeline = stats.fanotify_pipeline;
+                                    let throttling = stats.prefetch_throttling;
+
+                                    let field_defs = vec![
+                                        // Hook: Fanotify Logger
+                                        (
+                                            String::from("fanotify_logger.backend"),
+                                            fmt_option(pipeline.as_ref().map(|p| p.backend.clone())),
+                                            fmt_cell(pipeline.as_ref().map(|p| p.backend.clone()), None),
+                                        ),
+                                        (
+                                            String::from("fanotify_logger.events_received"),
+                                            fmt_option(pipeline.as_ref().map(|p| p.events_received)),
+                                            fmt_cell(
+                                                pipeline.as_ref().map(|p| p.events_received),
+                                                Some(ValueRange::new(0..u64::max_value(), 0..0, 0..0)),
+                                            ),
+                                        ),
+                                        (
+                                            String::from("fanotify_logger.events_processed"),
+                                            fmt_option(pipeline.as_ref().map(|p| p.events_processed)),
+                                            fmt_cell(
+                                                pipeline.as_ref().map(|p| p.events_processed),
+                                                Some(ValueRange::new(0..u64::max_value(), 0..0, 0..0)),
+                                            ),
+                                        ),
+                                        (
+                                            String::from("fanotify_logger.events_dropped"),
+                                            fmt_option(pipeline.as_ref().map(|p| p.events_dropped)),
+                                            fmt_cell(
+                                                pipeline.as_ref().map(|p| p.events_dropped),
+                                                Some(ValueRange::new(0..1, 1..u64::max_value(), 0..0)),
+                                            ),
+                                        ),
+                                        (
+                                            String::from("fanotify_logger.queue_depth"),
+                                            fmt_option(pipeline.as_ref().map(|p| p.queue_depth)),
+                                            fmt_cell(
+                                                pipeline.as_ref().map(|p| p.queue_depth),
+                                                Some(ValueRange::new(0..usize::max_value(), 0..0, 0..0)),
+                                            ),
+                                        ),
+                                        (
+                                            String::from("fanotify_logger.max_queue_depth"),
+                                            fmt_option(pipeline.as_ref().map(|p| p.max_queue_depth)),
+                                            fmt_cell(
+                                                pipeline.as_ref().map(|p| p.max_queue_depth),
+                                                Some(ValueRange::new(0..usize::max_value(), 0..0, 0..0)),
+                                            ),
+                                        ),
+                                        // Hook: I/O Trace Prefetcher
+                                        (
+                                            String::from("iotrace_prefetcher.throttle_events"),
+                                            fmt_option(throttling.as_ref().map(|t| t.throttle_events)),
+                                            fmt_cell(
+                                                throttling.as_ref().map(|t| t.throttle_events),
+                                                Some(ValueRange::new(0..u64::max_value(), 0..0, 0..0)),
+                                            ),
+                                        ),
+                                        (
+                                            String::from("iotrace_prefetcher.throttled_millis"),
+                                            fmt_option(throttling.as_ref().map(|t| t.throttled_millis)),
+                                            fmt_cell(
+                                                throttling.as_ref().map(|t| t.throttled_millis),
+                                                Some(ValueRange::new(0..u64::max_value(), 0..0, 0..0)),
+                                            ),
+                                        ),
+                                        (
+                                            String::from("iotrace_prefetcher.aborted_chunks"),
+                                            fmt_option(throttling.as_ref().map(|t| t.aborted_chunks)),
+                                            fmt_cell(
+                                                throttling.as_ref().map(|t| t.aborted_chunks),
+                                                Some(ValueRange::new(0..1, 1..u64::max_value(), 0..0)),
+                                            ),
+                                        ),
+                                        (
+                                            String::from("iotrace_prefetcher.io_pressure"),
+                                            fmt_option(throttling.as_ref().and_then(|t| t.io_pressure)),
+                                            fmt_cell(throttling.as_ref().and_then(|t| t.io_pressure), None),
+                                        ),
+                                        (
+                                            String::from("iotrace_prefetcher.disk_utilization"),
+                                            fmt_option(throttling.as_ref().and_then(|t| t.disk_utilization)),
+                                            fmt_cell(throttling.as_ref().and_then(|t| t.disk_utilization), None),
+                                        ),
+                                    ];
 
                                     for (index, &(ref f, ref v, ref cell)) in field_defs.iter().enumerate() {
                                         table.add_row(Row::new(vec![
@@ -19,12 +19,14 @@
 */
 
 pub mod config_file;
+pub mod elf;
 pub mod files;
 pub mod mountinfo;
 pub mod namespace;
 pub mod value_range;
 
 pub use self::config_file::*;
+pub use self::elf::*;
 pub use self::files::*;
 pub use self::value_range::*;
 pub use self::mountinfo::*;
@@ -48,12 +48,39 @@ pub enum ThreadState {
     PrefetchedFile(PathBuf),
     PrefetchedFileMetadata(PathBuf),
     UnmappedFile(PathBuf),
+    Throttled,
+}
+
+/// The priority of a prefetch request
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize, Deserialize)]
+pub enum PrefetchPriority {
+    Background,
+    HotApplication,
+    Interactive,
+}
+
+/// Specifies what will be prefetched for the entries of a chunk
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum PrefetchKind {
+    Data,
+    Metadata,
+}
+
+/// The state of a queued prefetch request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefetchQueueEntry {
+    pub name: String,
+    pub priority: PrefetchPriority,
+    pub kind: PrefetchKind,
+    pub pending_chunks: usize,
+    pub pending_entries: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrefetchStats {
     pub datetime: DateTime<Utc>,
     pub thread_states: Vec<ThreadState>,
+    pub queue: Vec<PrefetchQueueEntry>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -336,6 +336,13 @@ impl Application {
 
                                 ctr += 1;
                             }
+
+                            for q in stats.queue {
+                                prefetcher.push(format!(
+                                    "Queued: {} ({:?}, {:?}): {} chunks, {} entries",
+                                    q.name, q.priority, q.kind, q.pending_chunks, q.pending_entries
+                                ));
+                            }
                         } else {
                             prefetcher.push(tr!("no-data").to_owned());
                         }
@@ -0,0 +1 @@
+../../../../util/elf.rs
\ No newline at end of file
@@ -19,11 +19,13 @@
 */
 
 pub mod config_file;
+pub mod elf;
 pub mod files;
 pub mod mountinfo;
 pub mod namespace;
 
 pub use self::config_file::*;
+pub use self::elf::*;
 pub use self::files::*;
 pub use self::mountinfo::*;
 pub use self::namespace::*;
@@ -19,6 +19,7 @@
 */
 
 pub mod config_file;
+pub mod elf;
 pub mod files;
 pub mod iotrace;
 pub mod mountinfo;
@@ -26,6 +27,7 @@ pub mod namespace;
 pub mod vec;
 
 pub use self::config_file::*;
+pub use self::elf::*;
 pub use self::files::*;
 pub use self::iotrace::*;
 pub use self::vec::*;
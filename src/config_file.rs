@@ -25,8 +25,17 @@ use serde_derive::{Serialize, Deserialize};
 use toml;
 use crate::globals::*;
 use crate::constants;
+use crate::iotrace::TruncationPolicy;
 use crate::util;
 
+/// Per-program overrides of the I/O tracer parameters
+#[derive(Debug, Clone, Deserialize)]
+pub struct TraceOverride {
+    pub program: PathBuf,
+    pub trace_quiet_period: Option<u64>,
+    pub trace_max_duration: Option<u64>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct ConfigFile {
     pub user: Option<String>,
@@ -35,8 +44,30 @@ pub struct ConfigFile {
     pub available_mem_critical_threshold: Option<u8>,
     pub available_mem_upper_threshold: Option<u8>,
     pub available_mem_lower_threshold: Option<u8>,
+    pub memory_pressure_upper_threshold: Option<u8>,
+    pub memory_pressure_critical_threshold: Option<u8>,
     pub min_trace_log_length: Option<usize>,
     pub min_trace_log_prefetch_size: Option<u64>,
+    pub trace_quiet_period: Option<u64>,
+    pub trace_max_duration: Option<u64>,
+    pub trace_overrides: Option<Vec<TraceOverride>>,
+    pub trace_written_files: Option<bool>,
+    pub trace_max_entries: Option<usize>,
+    pub trace_max_size: Option<u64>,
+    pub trace_truncation_policy: Option<TruncationPolicy>,
+    pub cmdline_normalization_rules: Option<Vec<String>>,
+    pub prefetch_timeline_replay: Option<bool>,
+    pub prefetch_timeline_lead_time: Option<u64>,
+    pub prefetch_rotational_mode: Option<bool>,
+    pub prefetch_rotational_batching: Option<bool>,
+    pub prefetch_strategy: Option<util::PrefetchStrategy>,
+    pub prefetch_strategy_iotrace: Option<util::PrefetchStrategy>,
+    pub prefetch_strategy_whitelist: Option<util::PrefetchStrategy>,
+    pub prefetch_strategy_hot_applications: Option<util::PrefetchStrategy>,
+    pub prefetch_strategy_log_cache: Option<util::PrefetchStrategy>,
+    pub io_pressure_upper_threshold: Option<u8>,
+    pub disk_utilization_upper_threshold: Option<u8>,
+    pub io_throttle_max_pause: Option<u64>,
     pub state_dir: Option<PathBuf>,
     pub whitelist: Option<Vec<PathBuf>>,
     pub metadata_whitelist: Option<Vec<PathBuf>>,
@@ -56,8 +87,35 @@ impl Default for ConfigFile {
             available_mem_critical_threshold: Some(constants::AVAILABLE_MEMORY_CRITICAL_THRESHOLD),
             available_mem_upper_threshold: Some(constants::AVAILABLE_MEMORY_UPPER_THRESHOLD),
             available_mem_lower_threshold: Some(constants::AVAILABLE_MEMORY_LOWER_THRESHOLD),
+            memory_pressure_upper_threshold: Some(constants::MEMORY_PRESSURE_UPPER_THRESHOLD),
+            memory_pressure_critical_threshold: Some(constants::MEMORY_PRESSURE_CRITICAL_THRESHOLD),
             min_trace_log_length: Some(constants::MIN_TRACE_LOG_LENGTH),
             min_trace_log_prefetch_size: Some(constants::MIN_TRACE_LOG_PREFETCH_SIZE_BYTES),
+            trace_quiet_period: Some(constants::IO_TRACE_QUIET_PERIOD_MILLIS),
+            trace_max_duration: Some(constants::IO_TRACE_MAX_TIME_SECS),
+            trace_overrides: Some(vec![]),
+            trace_written_files: Some(constants::TRACE_WRITTEN_FILES),
+            trace_max_entries: Some(constants::MAX_TRACE_LOG_ENTRIES),
+            trace_max_size: Some(constants::MAX_TRACE_LOG_SIZE_BYTES),
+            trace_truncation_policy: Some(TruncationPolicy::Earliest),
+            cmdline_normalization_rules: Some(
+                constants::CMDLINE_NORMALIZATION_RULES
+                    .iter()
+                    .map(|r| String::from(*r))
+                    .collect(),
+            ),
+            prefetch_timeline_replay: Some(constants::PREFETCH_TIMELINE_REPLAY),
+            prefetch_timeline_lead_time: Some(constants::PREFETCH_TIMELINE_LEAD_TIME_MILLIS),
+            prefetch_rotational_mode: Some(constants::PREFETCH_ROTATIONAL_MODE),
+            prefetch_rotational_batching: Some(constants::PREFETCH_ROTATIONAL_BATCHING),
+            prefetch_strategy: Some(util::PrefetchStrategy::Mlock),
+            prefetch_strategy_iotrace: None,
+            prefetch_strategy_whitelist: None,
+            prefetch_strategy_hot_applications: None,
+            prefetch_strategy_log_cache: None,
+            io_pressure_upper_threshold: Some(constants::IO_PRESSURE_UPPER_THRESHOLD),
+            disk_utilization_upper_threshold: Some(constants::DISK_UTILIZATION_UPPER_THRESHOLD),
+            io_throttle_max_pause: Some(constants::IO_THROTTLE_MAX_PAUSE_MILLIS),
             state_dir: Some(Path::new(constants::STATE_DIR).to_path_buf()),
             whitelist: Some(vec![PathBuf::new()]),
             metadata_whitelist: Some(vec![PathBuf::new()]),
@@ -93,6 +151,35 @@ pub fn parse_config_file(globals: &mut Globals) -> io::Result<()> {
     Ok(())
 }
 
+/// The subsystems that cache files, each of which may use its own prefetch strategy
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PrefetchSource {
+    /// Replay of I/O traces of programs that are being started
+    IOTraceReplay,
+    /// Statically whitelisted files and programs
+    Whitelist,
+    /// Frequently used programs
+    HotApplications,
+    /// The I/O trace log files themselves
+    LogCache,
+}
+
+/// Returns the prefetch strategy configured for `source`, or the global prefetch strategy
+pub fn get_prefetch_strategy(globals: &Globals, source: PrefetchSource) -> util::PrefetchStrategy {
+    let config_file = globals.get_config_file();
+
+    let strategy = match source {
+        PrefetchSource::IOTraceReplay => config_file.prefetch_strategy_iotrace,
+        PrefetchSource::Whitelist => config_file.prefetch_strategy_whitelist,
+        PrefetchSource::HotApplications => config_file.prefetch_strategy_hot_applications,
+        PrefetchSource::LogCache => config_file.prefetch_strategy_log_cache,
+    };
+
+    strategy
+        .or(config_file.prefetch_strategy)
+        .unwrap_or(util::PrefetchStrategy::Mlock)
+}
+
 pub fn get_disabled_plugins(globals: &mut Globals) -> Vec<String> {
     globals
         .config
@@ -67,8 +67,20 @@ pub const WORKER_THREAD_NICENESS: i32 = 8;
 /// Thread wait time (main loop)
 pub const EVENT_THREAD_TIMEOUT_MILLIS: u64 = 1000;
 
-/// Thread wait time (fanotify loop)
-pub const FANOTIFY_THREAD_YIELD_MILLIS: u64 = 5000;
+/// Thread wait time (fanotify loop), if no fanotify events are pending
+pub const FANOTIFY_THREAD_YIELD_MILLIS: u64 = 50;
+
+/// Capacity of the queue between the fanotify thread and the fanotify worker thread
+pub const FANOTIFY_QUEUE_CAPACITY: usize = 16384;
+
+/// Maximum number of fanotify events that get processed in one batch
+pub const FANOTIFY_BATCH_SIZE: usize = 512;
+
+/// Interval in which the fanotify worker thread refreshes its snapshot of the blacklist
+pub const FANOTIFY_SNAPSHOT_REFRESH_MILLIS: u64 = 5000;
+
+/// Interval in which the /proc based tracer backend samples the traced processes
+pub const PROC_TRACER_SAMPLE_INTERVAL_MILLIS: u64 = 100;
 
 /// Time that has to elapse before we may perform housekeeping after the precached process' startup
 pub const HOUSEKEEPING_DELAY_AFTER_STARTUP_SECS: i64 = 5 * 60; // 5 Minutes
@@ -82,12 +94,53 @@ pub const METRICS_INTERVAL_MILLIS: u64 = 1000;
 /// `Ping` event timer timeout
 pub const PING_INTERVAL_MILLIS: u64 = 2500;
 
-/// Duration in seconds that we trace a process' I/O activity
-pub const IO_TRACE_TIME_SECS: u64 = 15;
+/// Duration in milliseconds without any file accesses, after which we consider
+/// the startup phase of a traced process finished, and stop tracing it
+pub const IO_TRACE_QUIET_PERIOD_MILLIS: u64 = 2000;
+
+/// Maximum duration in seconds that we trace a process' I/O activity
+pub const IO_TRACE_MAX_TIME_SECS: u64 = 60;
+
+/// Keep files in I/O trace logs, that have been opened for writing by the traced process
+pub const TRACE_WRITTEN_FILES: bool = false;
+
+/// The maximum number of entries of an I/O trace log
+pub const MAX_TRACE_LOG_ENTRIES: usize = 10_000;
+
+/// The maximum amount of data an I/O trace log may reference
+pub const MAX_TRACE_LOG_SIZE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// How many levels of the process tree we walk up, when looking for
+/// a traced ancestor of a process
+pub const MAX_PROCESS_TREE_DEPTH: usize = 16;
+
+/// `FinalizeIOTraces` event timer timeout
+pub const IO_TRACE_FINALIZATION_INTERVAL_MILLIS: u64 = 1000;
 
 /// After how many days an I/O trace is flagged as expired
 pub const IO_TRACE_EXPIRY_DAYS: i64 = 14;
 
+/// The number of runs of a program that get traced and merged into its
+/// I/O trace log, before the I/O trace log is considered complete
+pub const IO_TRACE_MIN_RUNS: usize = 3;
+
+/// The maximum number of runs of a program that are taken into account
+/// when calculating the confidence of the files in its I/O trace log
+pub const IO_TRACE_MAX_MERGED_RUNS: usize = 10;
+
+/// The minimum confidence (ratio of runs in which a file has been accessed)
+/// an I/O trace log entry must have for it to be kept by the optimizer
+pub const IO_TRACE_MIN_CONFIDENCE: f32 = 0.5;
+
+/// Default command line normalization rules (regular expressions). Matching arguments
+/// will be removed from the command line of a process, before its I/O trace log is looked
+/// up or stored: paths to user documents, URLs and numeric IDs
+pub const CMDLINE_NORMALIZATION_RULES: &[&str] = &[
+    r"^(/home/|/tmp/|/media/|/mnt/|/run/media/|~/|\./|\.\./)",
+    r"^[a-zA-Z][a-zA-Z0-9+.-]*://",
+    r"^[0-9]+$",
+];
+
 /// The minimum length an I/O trace log must have for it to be saved/kept
 pub const MIN_TRACE_LOG_LENGTH: usize = 15;
 
@@ -109,6 +162,13 @@ pub const AVAILABLE_MEMORY_UPPER_THRESHOLD: u8 = 80;
 /// Available memory threshold (percentage available)
 pub const AVAILABLE_MEMORY_LOWER_THRESHOLD: u8 = 70;
 
+/// Memory pressure (percentage of time in which tasks stalled on memory reclaim)
+/// at and above which we signal "available memory high watermark"
+pub const MEMORY_PRESSURE_UPPER_THRESHOLD: u8 = 10;
+
+/// Memory pressure at and above which we signal "available memory critical"
+pub const MEMORY_PRESSURE_CRITICAL_THRESHOLD: u8 = 40;
+
 /// Time in seconds that has to elapse before we signal "recovery from swap"
 pub const SWAP_RECOVERY_WINDOW: u64 = 5;
 
@@ -127,6 +187,51 @@ pub const IDLE_PERIOD_WINDOW: u64 = 10;
 /// The size of the prefetcher thread pool
 pub const NUM_PREFETCHER_THREADS: usize = 4;
 
+/// Replay I/O traces ordered by the time offsets of their entries (timeline-paced)
+pub const PREFETCH_TIMELINE_REPLAY: bool = true;
+
+/// Time in milliseconds by which timeline-paced prefetching runs ahead of the program
+pub const PREFETCH_TIMELINE_LEAD_TIME_MILLIS: u64 = 1000;
+
+/// Prefetch files on rotational devices sequentially, ordered by their physical location on disk
+pub const PREFETCH_ROTATIONAL_MODE: bool = true;
+
+/// Merge the pending entries of several I/O traces into a single ordered batch on rotational devices
+pub const PREFETCH_ROTATIONAL_BATCHING: bool = true;
+
+/// The maximum number of I/O trace log entries a prefetcher thread processes at once
+pub const PREFETCH_CHUNK_SIZE: usize = 64;
+
+/// Width in milliseconds of a time slot of timeline-paced prefetching;
+/// entries within the same time slot are submitted together
+pub const PREFETCH_TIMELINE_SLOT_MILLIS: u64 = 250;
+
+/// I/O pressure (percentage of time in which tasks stalled on I/O) above which
+/// prefetching with less than interactive priority will be throttled
+pub const IO_PRESSURE_UPPER_THRESHOLD: u8 = 20;
+
+/// Utilization of the busiest block device in percent, above which
+/// prefetching with less than interactive priority will be throttled
+pub const DISK_UTILIZATION_UPPER_THRESHOLD: u8 = 80;
+
+/// Maximum time in milliseconds a prefetcher thread waits for the I/O load to
+/// drop, before it gives up on the chunk it is currently processing
+pub const IO_THROTTLE_MAX_PAUSE_MILLIS: u64 = 30_000;
+
+/// Initial time in milliseconds a throttled prefetcher thread backs off;
+/// doubled each time the I/O load is still too high
+pub const IO_THROTTLE_INITIAL_BACKOFF_MILLIS: u64 = 250;
+
+/// Maximum time in milliseconds a throttled prefetcher thread backs off at once
+pub const IO_THROTTLE_MAX_BACKOFF_MILLIS: u64 = 4000;
+
+/// Minimum time in milliseconds between two samples of the I/O load
+pub const IO_LOAD_SAMPLE_INTERVAL_MILLIS: u64 = 250;
+
+/// Time in seconds after which a file that has been cached without retaining a mapping
+/// may be prefetched again, since its pages may have been evicted in the meantime
+pub const PREFETCH_UNMAPPED_EXPIRY_SECS: u64 = 5 * 60; // 5 Minutes
+
 /// Maximum allowed size of a single file we are allowed to prefetch
 pub const MAX_ALLOWED_PREFETCH_SIZE: usize = 256 * 1024 * 1024; // 256 MiB
 
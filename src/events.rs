@@ -47,6 +47,10 @@ pub enum EventType {
     InotifyEvent(EventMaskWrapper, PathBuf),
     /// advice to plugins that an I/O trace log needs to be optimized asap
     OptimizeIOTraceLog(PathBuf),
+    /// advice to the I/O tracer to finalize and save the I/O trace logs of expired tracers
+    FinalizeIOTraces,
+    /// advice to the I/O tracer to finalize and save the I/O trace log of an exited process
+    FinalizeIOTrace(libc::pid_t),
     /// high level event that gets sent after an I/O trace log file has been created
     IoTraceLogCreated(PathBuf),
     /// high level event that gets sent after an I/O trace log file has been removed
@@ -23,15 +23,17 @@ use std::collections::hash_map::Entry::{Occupied, Vacant};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
 use parking_lot::Mutex;
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
-use fanotify::safe as fan;
-use fanotify::sys::*;
+use globset::GlobSet;
 use log::{trace, debug, info, warn, error, log, LevelFilter};
 use lazy_static::lazy_static;
+use regex::Regex;
+use serde_derive::{Serialize, Deserialize};
 use crate::constants;
 use crate::events;
 use crate::events::EventType;
@@ -44,6 +46,7 @@ use crate::manager::*;
 use crate::plugins::iotrace_log_manager::IOtraceLogManager;
 use crate::plugins::static_blacklist::StaticBlacklist;
 use crate::util::tracer::*;
+use crate::util::tracer_backend::*;
 use crate::process::Process;
 use crate::procmon;
 use crate::util;
@@ -51,14 +54,59 @@ use crate::util::Contains;
 use crate::EXIT_NOW;
 
 static NAME: &str = "fanotify_logger";
-static DESCRIPTION: &str = "Trace filesystem activity of processes using fanotify";
+static DESCRIPTION: &str = "Trace filesystem activity of processes using fanotify, or by sampling /proc as a fallback";
 
 lazy_static! {
-    pub static ref FANOTIFY: fan::Fanotify = fan::Fanotify::new_nonblocking().expect("Could not initialize fanotify!");
-
     /// HashMap containing all in-flight (tracked) processes "PerTracerData"
     /// Contains metadata about the trace as well as the IOTraceLog itself
     pub static ref ACTIVE_TRACERS: Arc<Mutex<HashMap<libc::pid_t, util::PerTracerData>>> = Arc::new(Mutex::new(HashMap::new()));
+
+    /// Counters of the fanotify event pipeline
+    pub static ref PIPELINE_STATISTICS: PipelineCounters = PipelineCounters::default();
+
+    /// Serializes the finalization of I/O trace logs on the worker thread pool
+    static ref FINALIZE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Counters of the fanotify event pipeline, shared by the "fanotify thread"
+/// and the "fanotify worker thread"
+#[derive(Debug, Default)]
+pub struct PipelineCounters {
+    /// Name of the tracer backend in use
+    pub backend: Mutex<String>,
+    /// Number of events read from the fanotify file descriptor
+    pub events_received: AtomicU64,
+    /// Number of events that have been processed by the worker thread
+    pub events_processed: AtomicU64,
+    /// Number of events that have been dropped, because the queue was full
+    pub events_dropped: AtomicU64,
+    /// Number of events currently waiting in the queue
+    pub queue_depth: AtomicUsize,
+    /// Maximum number of events that have been waiting in the queue
+    pub max_queue_depth: AtomicUsize,
+}
+
+/// A snapshot of the counters of the fanotify event pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineStatistics {
+    pub backend: String,
+    pub events_received: u64,
+    pub events_processed: u64,
+    pub events_dropped: u64,
+    pub queue_depth: usize,
+    pub max_queue_depth: usize,
+}
+
+/// Returns a snapshot of the counters of the fanotify event pipeline
+pub fn get_pipeline_statistics() -> PipelineStatistics {
+    PipelineStatistics {
+        backend: PIPELINE_STATISTICS.backend.lock().clone(),
+        events_received: PIPELINE_STATISTICS.events_received.load(Ordering::Relaxed),
+        events_processed: PIPELINE_STATISTICS.events_processed.load(Ordering::Relaxed),
+        events_dropped: PIPELINE_STATISTICS.events_dropped.load(Ordering::Relaxed),
+        queue_depth: PIPELINE_STATISTICS.queue_depth.load(Ordering::Relaxed),
+        max_queue_depth: PIPELINE_STATISTICS.max_queue_depth.load(Ordering::Relaxed),
+    }
 }
 
 /// Register this hook implementation with the system
@@ -74,176 +122,320 @@ pub fn register_hook(_globals: &mut Globals, manager: &mut Manager) {
 pub struct FanotifyLogger {
     /// Holds the JoinHandle of the "fanotify thread"
     fanotify_thread: Option<thread::JoinHandle<()>>,
+    /// Holds the JoinHandle of the "fanotify worker thread"
+    fanotify_worker_thread: Option<thread::JoinHandle<()>>,
 }
 
 impl FanotifyLogger {
     pub fn new() -> Self {
-        FanotifyLogger { fanotify_thread: None }
+        FanotifyLogger {
+            fanotify_thread: None,
+            fanotify_worker_thread: None,
+        }
     }
 
-    /// Main loop of the "fanotify thread"
-    fn fanotify_event_loop(globals: &mut Globals, manager: &Manager) {
-        let mut globals_c = globals.clone();
-        let manager_c = manager.clone();
-
+    /// Main loop of the "fanotify thread": Drain the tracer backend `backend` in batches and
+    /// hand the events over to the "fanotify worker thread", using the bounded queue `queue`.
+    /// Events are dropped (and accounted for) if the worker thread does not keep up
+    fn fanotify_event_loop(backend: &mut dyn TracerBackend, queue: &SyncSender<TracerEvent>) {
         'FANOTIFY_EVENT_LOOP: loop {
-            match FANOTIFY.get_events() {
-                Ok(events) => {
-                    for event in events.iter() {
-                        if EXIT_NOW.load(Ordering::SeqCst) {
-                            debug!("Leaving the fanotify event loop...");
+            if EXIT_NOW.load(Ordering::SeqCst) {
+                debug!("Leaving the fanotify event loop...");
+                break 'FANOTIFY_EVENT_LOOP;
+            }
+
+            let events = backend.get_events();
+
+            if events.is_empty() {
+                thread::sleep(backend.get_poll_interval());
+            } else {
+                PIPELINE_STATISTICS
+                    .events_received
+                    .fetch_add(events.len() as u64, Ordering::Relaxed);
+
+                for event in events {
+                    match queue.try_send(event) {
+                        Ok(()) => {
+                            let queue_depth = PIPELINE_STATISTICS.queue_depth.fetch_add(1, Ordering::Relaxed) + 1;
+
+                            // this is the only thread that updates the maximum queue depth
+                            if queue_depth > PIPELINE_STATISTICS.max_queue_depth.load(Ordering::Relaxed) {
+                                PIPELINE_STATISTICS.max_queue_depth.store(queue_depth, Ordering::Relaxed);
+                            }
+                        }
+
+                        Err(TrySendError::Full(_)) => {
+                            PIPELINE_STATISTICS.events_dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+
+                        Err(TrySendError::Disconnected(_)) => {
+                            error!("The fanotify worker thread vanished!");
                             break 'FANOTIFY_EVENT_LOOP;
                         }
+                    }
+                }
+            }
+        }
 
-                        let hm = manager.hook_manager.read();
+        info!("Fanotify thread terminating now!");
+    }
 
-                        match hm.get_hook_by_name(&String::from("process_tracker")) {
-                            None => {
-                                error!("Hook not loaded: 'process_tracker', skipped");
-                            }
+    /// Main loop of the "fanotify worker thread": Receive fanotify events from the
+    /// "fanotify thread" and process them in batches of up to `FANOTIFY_BATCH_SIZE` events
+    fn fanotify_worker_loop(queue: &Receiver<TracerEvent>, globals: &mut Globals, manager: &Manager) {
+        let mut blacklist = Self::get_file_blacklist_snapshot(manager);
+        let mut host_mounts = util::parse_own_mountinfo().unwrap_or_default();
+        let mut last_refresh = Instant::now();
+
+        'FANOTIFY_WORKER_LOOP: loop {
+            // block until at least one event is available, then drain the queue up to the batch size
+            let mut batch = match queue.recv_timeout(Duration::from_millis(constants::EVENT_THREAD_TIMEOUT_MILLIS)) {
+                Ok(event) => vec![event],
+
+                Err(RecvTimeoutError::Timeout) => {
+                    if EXIT_NOW.load(Ordering::SeqCst) {
+                        break 'FANOTIFY_WORKER_LOOP;
+                    }
 
-                            Some(h) => {
-                                let h = h.read();
-                                let process_tracker = h.as_any().downcast_ref::<ProcessTracker>().unwrap();
-
-                                // comm is only used for the syslog output
-                                let mut comm = None;
-
-                                if let Some(process) = process_tracker.get_process(event.pid).clone() {
-                                    comm = Some(process.comm.clone());
-                                } else if let Ok(process) = Process::new(event.pid) {
-                                    comm = process.get_comm().ok();
-                                } /* else {
-                                      warn!(
-                                          "Could not get process information for pid: {}, maybe inaccessible procfs?",
-                                          event.pid
-                                      );
-                                  }*/
-
-                                let mut active_tracers = ACTIVE_TRACERS.lock();
-                                let mut add_tracer = false;
-
-                                match active_tracers.entry(event.pid) {
-                                    Occupied(mut tracer_data) => {
-                                        // trace!("Found tracer_data");
-
-                                        if let Some(process) = process_tracker.get_process(event.pid) {
-                                            if let Some(mountinfo) = process.mountinfo.as_ref() {
-                                                // find the canonical path of the event.filename, e.g. if the process runs
-                                                // in a different mount namespace the paths (event and canonical) will be differing
-                                                if let Some(canonical_path) =
-                                                    util::find_source_path(&mountinfo, &PathBuf::from(event.filename.clone()))
-                                                {
-                                                    // We successfully found tracer data for process `pid`
-                                                    // Add an event record to the I/O trace log of that process
-                                                    let iotrace_log = &mut tracer_data.get_mut().trace_log;
-
-                                                    trace!(
-                                                        "Process: '{}' with pid {} opened file: {:?}",
-                                                        comm.clone().unwrap_or_else(|| String::from("<not available>")),
-                                                        event.pid,
-                                                        &canonical_path,
-                                                    );
-
-                                                    if !Self::is_file_blacklisted(
-                                                        &PathBuf::from(canonical_path.clone()),
-                                                        &mut globals_c,
-                                                        &manager_c,
-                                                    ) {
-                                                        iotrace_log
-                                                            .add_event(IOOperation::Open(PathBuf::from(canonical_path.clone())));
-                                                    } else {
-                                                        // trace!("File is blacklisted!");
-                                                    }
-                                                } else {
-                                                    error!("Could not get the canonical file name of: {}", &event.filename);
-                                                }
-                                            } else {
-                                                error!("Could not get mount info of process with pid: {}", &event.pid);
-                                            }
-                                        } else {
-                                            trace!("Could not get process' status of pid: {}", &event.pid);
-                                        }
-                                    }
+                    continue;
+                }
+
+                Err(RecvTimeoutError::Disconnected) => {
+                    break 'FANOTIFY_WORKER_LOOP;
+                }
+            };
 
-                                    Vacant(_k) => {
-                                        // Our HashMap does not currently contain a "PerTracerData" for the
-                                        // process `pid`. That means that we didn't track this process from
-                                        // the beginning. Either we lost a process creation event, or maybe
-                                        // it was started before our daemon was running
+            while batch.len() < constants::FANOTIFY_BATCH_SIZE {
+                match queue.try_recv() {
+                    Ok(event) => batch.push(event),
+                    Err(_) => break,
+                }
+            }
 
-                                        debug!(
-                                            "Spurious fanotify event for untracked process '{}' with pid {} processed!",
-                                            comm.clone().unwrap_or_else(|| String::from("<not available>")),
-                                            event.pid
-                                        );
+            PIPELINE_STATISTICS.queue_depth.fetch_sub(batch.len(), Ordering::Relaxed);
+
+            if EXIT_NOW.load(Ordering::SeqCst) {
+                break 'FANOTIFY_WORKER_LOOP;
+            }
 
-                                        add_tracer = true;
+            // pick up changes of the blacklist, e.g. after the configuration has been reloaded,
+            // and of our mounts, which are used to map file names from other mount namespaces
+            if last_refresh.elapsed() > Duration::from_millis(constants::FANOTIFY_SNAPSHOT_REFRESH_MILLIS) {
+                blacklist = Self::get_file_blacklist_snapshot(manager);
+                host_mounts = util::parse_own_mountinfo().unwrap_or_default();
+                last_refresh = Instant::now();
+            }
+
+            Self::process_batch(&batch, blacklist.as_ref(), &host_mounts, globals, manager);
+
+            PIPELINE_STATISTICS
+                .events_processed
+                .fetch_add(batch.len() as u64, Ordering::Relaxed);
+        }
+
+        info!("Fanotify worker thread terminating now!");
+    }
+
+    /// Attribute a batch of fanotify events to the tracers of the originating processes.
+    /// The shared data structures are locked only once per batch, and processes are
+    /// classified only once per batch, using the blacklist snapshot `blacklist`. `host_mounts`
+    /// are the mounts of our own mount namespace, that file names are canonicalized against
+    fn process_batch(
+        batch: &[TracerEvent],
+        blacklist: Option<&GlobSet>,
+        host_mounts: &[util::MountInfo],
+        globals: &mut Globals,
+        manager: &Manager,
+    ) {
+        let hm = manager.hook_manager.read();
+
+        let h = match hm.get_hook_by_name(&String::from("process_tracker")) {
+            None => {
+                error!("Hook not loaded: 'process_tracker', skipped");
+                return;
+            }
+
+            Some(h) => h,
+        };
+
+        let h = h.read();
+        let process_tracker = h.as_any().downcast_ref::<ProcessTracker>().unwrap();
+
+        // Per batch caches: The processes that caused the events, looked up before taking the lock,
+        // and the pid of the tracer that events of a process are attributed to. Descendants are not
+        // necessarily being tracked by the process tracker, e.g. if they have been forked but did
+        // not exec() another program
+        let mut processes: HashMap<libc::pid_t, Option<Process>> = HashMap::new();
+
+        for event in batch.iter() {
+            processes.entry(event.pid).or_insert_with(|| {
+                process_tracker
+                    .get_process(event.pid)
+                    .cloned()
+                    .or_else(|| Process::new(event.pid).ok())
+            });
+        }
+
+        let mut tracer_pids: HashMap<libc::pid_t, Option<libc::pid_t>> = HashMap::new();
+        let mut untracked_pids = vec![];
+
+        let mut active_tracers = ACTIVE_TRACERS.lock();
+
+        for event in batch.iter() {
+            // Attribute the I/O activity of descendants of a traced process,
+            // e.g. of the child processes of multi-process applications, to the
+            // I/O trace log of that process, as long as it is still being traced
+            let tracer_pid = *tracer_pids.entry(event.pid).or_insert_with(|| {
+                if active_tracers.contains_key(&event.pid) {
+                    Some(event.pid)
+                } else {
+                    process_tracker.find_ancestor(event.pid, |pid| active_tracers.get(&pid).map_or(false, |t| t.is_active()))
+                }
+            });
+
+            let process = &processes[&event.pid];
+
+            // comm is only used for the syslog output, and to tag events of descendants
+            let comm = process.as_ref().map(|p| p.comm.clone());
+
+            match tracer_pid.and_then(|pid| active_tracers.get_mut(&pid)) {
+                Some(tracer_data) => {
+                    if let Some(process) = process {
+                        if let Some(mountinfo) = process.mountinfo.as_ref() {
+                            // find the canonical path of the event.filename, e.g. if the process runs
+                            // in a different mount namespace the paths (event and canonical) will be differing
+                            if let Some(canonical_path) =
+                                util::canonicalize_path(event.pid, &mountinfo, host_mounts, &event.filename)
+                            {
+                                // We successfully found tracer data for process `pid`
+                                // Add an event record to the I/O trace log of that process
+                                trace!(
+                                    "Process: '{}' with pid {} (traced by pid {}) opened file: {:?}",
+                                    comm.clone().unwrap_or_else(|| String::from("<not available>")),
+                                    event.pid,
+                                    tracer_pid.unwrap_or(event.pid),
+                                    &canonical_path,
+                                );
+
+                                let blacklisted = blacklist.map_or(false, |b| b.is_match(&canonical_path));
+
+                                if !blacklisted {
+                                    let origin = if tracer_pid != Some(event.pid) { comm } else { None };
+
+                                    if tracer_pid != Some(event.pid) {
+                                        tracer_data.descendants.insert(event.pid);
                                     }
-                                }
 
-                                // Late-add tracers for processes for which we somehow
-                                // missed their creation event, e.g. when precached daemon
-                                // was started after the creation of the process
-                                if add_tracer {
-                                    // // Add the previously untracked process
-                                    if let Ok(_result) = Self::shall_new_tracelog_be_created(event.pid, &mut globals_c, manager) {
-                                        // Begin tracing the process `pid`.
-                                        // Construct the "PerTracerData" and a companion IOTraceLog
-                                        match iotrace::IOTraceLog::new(event.pid) {
-                                            Err(e) => {
-                                                info!("Process vanished during tracing! {}", e);
-                                            }
-
-                                            Ok(iotrace_log) => {
-                                                let tracer_data = util::PerTracerData::new(iotrace_log);
-                                                active_tracers.insert(event.pid, tracer_data);
-                                            }
+                                    if event.mask & (FAN_OPEN_EVENT | FAN_OPEN_EXEC_EVENT) != 0 {
+                                        if let Some(op) =
+                                            Self::map_event_to_io_operation(event.mask, PathBuf::from(canonical_path.clone()))
+                                        {
+                                            tracer_data.trace_log.add_event(op, origin);
                                         }
+                                    }
 
-                                        info!(
-                                            "Added previously untracked process '{}' with pid: {}",
-                                            comm.unwrap_or_else(|| String::from("<not available>")),
-                                            event.pid
-                                        );
-                                    } else {
-                                        debug!("Could not add tracking entry for process with pid: {}", event.pid);
+                                    // the file has been opened for writing, so it is most
+                                    // probably a lock file, a journal or a cache of the program
+                                    if event.mask & FAN_CLOSE_WRITE_EVENT != 0 {
+                                        tracer_data.trace_log.add_write_access(Path::new(&canonical_path));
                                     }
+
+                                    tracer_data.touch();
+                                } else {
+                                    // trace!("File is blacklisted!");
                                 }
+                            } else {
+                                // e.g. files on a private tmpfs of a sandbox, which are not reachable from our mount namespace
+                                debug!("Could not get the canonical file name of: {:?}", &event.filename);
+                            }
+                        } else {
+                            error!("Could not get mount info of process with pid: {}", &event.pid);
+                        }
+                    } else {
+                        trace!("Could not get process' status of pid: {}", &event.pid);
+                    }
+                }
+
+                None => {
+                    // Our HashMap does not currently contain a "PerTracerData" for the
+                    // process `pid`. That means that we didn't track this process from
+                    // the beginning. Either we lost a process creation event, or maybe
+                    // it was started before our daemon was running
+                    if !untracked_pids.contains(&event.pid) {
+                        debug!(
+                            "Spurious fanotify event for untracked process '{}' with pid {} processed!",
+                            comm.unwrap_or_else(|| String::from("<not available>")),
+                            event.pid
+                        );
 
-                                let iotrace_dir = globals
-                                    .get_config_file()
-                                    .state_dir
-                                    .clone()
-                                    .unwrap_or_else(|| Path::new(constants::STATE_DIR).to_path_buf());
+                        untracked_pids.push(event.pid);
+                    }
+                }
+            }
+        }
 
-                                let min_len = globals
-                                    .get_config_file()
-                                    .min_trace_log_length
-                                    .unwrap_or(constants::MIN_TRACE_LOG_LENGTH);
+        // creating the tracers below reads I/O trace logs and procfs, so do not block other threads
+        drop(active_tracers);
 
-                                let min_prefetch_size = globals
-                                    .get_config_file()
-                                    .min_trace_log_prefetch_size
-                                    .unwrap_or(constants::MIN_TRACE_LOG_PREFETCH_SIZE_BYTES);
+        // Late-add tracers for processes for which we somehow
+        // missed their creation event, e.g. when precached daemon
+        // was started after the creation of the process
+        let mut new_tracers = vec![];
 
-                                check_expired_tracers(&mut active_tracers, &iotrace_dir, min_len, min_prefetch_size, globals);
-                            }
-                        }
+        for pid in untracked_pids {
+            // // Add the previously untracked process
+            if let Ok(_result) = Self::shall_new_tracelog_be_created(pid, globals, manager) {
+                // Begin tracing the process `pid`.
+                // Construct the "PerTracerData" and a companion IOTraceLog
+                let rules = Self::get_normalization_rules(manager);
+
+                match iotrace::IOTraceLog::new(pid, &rules) {
+                    Err(e) => {
+                        info!("Process vanished during tracing! {}", e);
                     }
-                }
 
-                Err(_e) => {
-                    thread::sleep(Duration::from_millis(constants::FANOTIFY_THREAD_YIELD_MILLIS));
+                    Ok(iotrace_log) => {
+                        info!("Added previously untracked process '{}' with pid: {}", iotrace_log.comm, pid);
+
+                        let (quiet_period, max_duration) = get_trace_durations(&iotrace_log.exe, globals);
+                        let tracer_data = util::PerTracerData::new(iotrace_log, quiet_period, max_duration);
+                        new_tracers.push((pid, tracer_data));
+                    }
                 }
+            } else {
+                debug!("Could not add tracking entry for process with pid: {}", pid);
             }
         }
 
-        info!("Fanotify thread terminating now!");
+        if !new_tracers.is_empty() {
+            let mut active_tracers = ACTIVE_TRACERS.lock();
+
+            for (pid, tracer_data) in new_tracers {
+                // the process may have been added in the meantime, e.g. by its exec event
+                active_tracers.entry(pid).or_insert(tracer_data);
+            }
+        }
     }
 
-    fn is_file_blacklisted(filename: &Path, _globals: &mut Globals, manager: &Manager) -> bool {
-        let mut result = false;
+    /// Map a fanotify event with the event mask `mask` on the file `filename` to an `IOOperation`
+    /// Opened files that have no page cache backed contents, like device nodes, FIFOs or sockets,
+    /// are not recorded at all, since there is nothing to prefetch for them
+    fn map_event_to_io_operation(mask: u64, filename: PathBuf) -> Option<IOOperation> {
+        if mask & FAN_OPEN_EXEC_EVENT != 0 {
+            Some(IOOperation::Exec(filename))
+        } else if mask & FAN_ONDIR_EVENT != 0 || filename.is_dir() {
+            Some(IOOperation::OpenDir(filename))
+        } else if filename.is_file() {
+            Some(IOOperation::Open(filename))
+        } else {
+            None
+        }
+    }
+
+    /// Returns a snapshot of the file blacklist, so that the fanotify worker
+    /// thread does not have to query the plugin for every single event
+    fn get_file_blacklist_snapshot(manager: &Manager) -> Option<GlobSet> {
+        let mut result = None;
 
         let pm = manager.plugin_manager.read();
 
@@ -256,7 +448,30 @@ impl FanotifyLogger {
                 let p = p.read();
                 let static_blacklist_plugin = p.as_any().downcast_ref::<StaticBlacklist>().unwrap();
 
-                result = static_blacklist_plugin.is_file_blacklisted(filename);
+                result = Some(static_blacklist_plugin.get_file_blacklist_glob_set());
+            }
+        }
+
+        result
+    }
+
+    /// Returns the command line normalization rules, used to derive the
+    /// hash value of newly created I/O trace logs
+    fn get_normalization_rules(manager: &Manager) -> Vec<Regex> {
+        let mut result = vec![];
+
+        let pm = manager.plugin_manager.read();
+
+        match pm.get_plugin_by_name(&String::from("iotrace_log_manager")) {
+            None => {
+                trace!("Plugin not loaded: 'iotrace_log_manager', skipped");
+            }
+
+            Some(p) => {
+                let p = p.read();
+                let iotrace_log_manager_plugin = p.as_any().downcast_ref::<IOtraceLogManager>().unwrap();
+
+                result = iotrace_log_manager_plugin.get_normalization_rules().to_vec();
             }
         }
 
@@ -318,26 +533,55 @@ impl FanotifyLogger {
                     }
                 }
 
-                let mut active_tracers = ACTIVE_TRACERS.lock();
-                if active_tracers.contains_key(&event.pid) {
-                    // We received a trace request multiple times for process `event.pid`.
-                    // It is already being traced by us.
-                    // warn!(
-                    //     "Spurious request received, to trace process '{}' with pid {} that is already being traced!",
-                    //     comm, event.pid
-                    // );
-                } else if let Ok(result) = Self::shall_new_tracelog_be_created(event.pid, globals, manager) {
+                let is_traced = {
+                    let active_tracers = ACTIVE_TRACERS.lock();
+
+                    if active_tracers.contains_key(&event.pid) {
+                        // We received a trace request multiple times for process `event.pid`.
+                        // It is already being traced by us.
+                        // warn!(
+                        //     "Spurious request received, to trace process '{}' with pid {} that is already being traced!",
+                        //     comm, event.pid
+                        // );
+                        true
+                    } else if let Some(ancestor) =
+                        process_tracker.find_ancestor(event.pid, |pid| active_tracers.get(&pid).map_or(false, |t| t.is_active()))
+                    {
+                        // The I/O activity of process `event.pid` will be recorded
+                        // in the I/O trace log of its traced ancestor
+                        debug!(
+                            "Process with pid {} is a descendant of traced process with pid {}, not tracing it separately",
+                            event.pid, ancestor
+                        );
+
+                        true
+                    } else {
+                        false
+                    }
+                };
+
+                if is_traced {
+                    return;
+                }
+
+                // creating the tracer reads I/O trace logs and procfs, so do not hold the lock
+                if let Ok(result) = Self::shall_new_tracelog_be_created(event.pid, globals, manager) {
                     if result {
                         // Begin tracing the process `event.pid`.
                         // Construct the "PerTracerData" and a companion IOTraceLog
-                        match iotrace::IOTraceLog::new(event.pid) {
+                        let rules = Self::get_normalization_rules(manager);
+
+                        match iotrace::IOTraceLog::new(event.pid, &rules) {
                             Err(e) => {
                                 info!("Process vanished during tracing! {}", e);
                             }
 
                             Ok(iotrace_log) => {
-                                let tracer_data = util::PerTracerData::new(iotrace_log);
-                                active_tracers.insert(event.pid, tracer_data);
+                                let (quiet_period, max_duration) = get_trace_durations(&iotrace_log.exe, globals);
+                                let tracer_data = util::PerTracerData::new(iotrace_log, quiet_period, max_duration);
+
+                                // the process may have been added in the meantime, e.g. by a late-add
+                                ACTIVE_TRACERS.lock().entry(event.pid).or_insert(tracer_data);
                             }
                         }
                     } else {
@@ -349,7 +593,8 @@ impl FanotifyLogger {
     }
 
     /// Returns `true` if we need to re-trace a program, e.g.
-    /// because of the binary being newer than the trace, or the trace being older than n days
+    /// because of the binary being newer than the trace, or the trace being older than n days,
+    /// or if not enough runs of the program have been merged into the trace yet
     fn shall_new_tracelog_be_created(pid: libc::pid_t, globals: &mut Globals, manager: &Manager) -> Result<bool, ()> {
         let pm = manager.plugin_manager.read();
 
@@ -369,16 +614,22 @@ impl FanotifyLogger {
                             match iotrace_log_manager_plugin.get_trace_log(&exe, cmdline, globals) {
                                 Err(_e) => Ok(true),
 
-                                Ok(io_trace) => {
+                                Ok((io_trace, match_key)) => {
                                     if io_trace.blacklisted {
                                         // do not overwrite a dynamically blacklisted I/O trace log
                                         Ok(false)
+                                    } else if match_key == iotrace::TraceMatchKey::ExeOnly
+                                        || match_key == iotrace::TraceMatchKey::BuildId
+                                    {
+                                        // only a fallback I/O trace log of the executable is available
+                                        Ok(true)
                                     } else {
                                         let (flags, err, _) = util::get_io_trace_flags_and_err(&io_trace);
 
                                         if err
                                             || flags.contains(&iotrace::IOTraceLogFlag::Expired)
                                             || flags.contains(&iotrace::IOTraceLogFlag::Outdated)
+                                            || io_trace.num_runs < constants::IO_TRACE_MIN_RUNS
                                         {
                                             Ok(true)
                                         } else {
@@ -403,8 +654,121 @@ impl FanotifyLogger {
         }
     }
 
+    /// Merge the memory mapped files of the traced process `pid`, and of its descendants, into the
+    /// I/O trace log of `tracer_data`, once its trace window has closed. This catches files that have
+    /// been mapped by the dynamic loader or accessed through inherited file descriptors, which the
+    /// tracer backend may have missed. `host_mounts` are the mounts of our own mount namespace
+    fn merge_mapped_files(pid: libc::pid_t, tracer_data: &mut PerTracerData, host_mounts: &[util::MountInfo]) {
+        let mut files = vec![];
+
+        // the address space of an exited process is gone already
+        if !tracer_data.process_exited {
+            files.append(&mut get_mapped_files(pid, host_mounts));
+        }
+
+        for descendant in tracer_data.descendants.iter() {
+            files.append(&mut get_mapped_files(*descendant, host_mounts));
+        }
+
+        let count = tracer_data.trace_log.add_mapped_files(&files);
+
+        if count > 0 {
+            trace!(
+                "Merged {} memory mapped files into the I/O trace log of process with pid: {}",
+                count,
+                pid
+            );
+        }
+    }
+
+    /// Finalize and save the I/O trace logs of all expired tracers, and of all tracers whose
+    /// process has exited, and remove them from `ACTIVE_TRACERS`. If `pid` is specified, only
+    /// the tracer of the exited process `pid` is finalized. The tracers are finalized on the
+    /// worker thread pool, so that the main loop is not blocked while the I/O trace logs are saved
+    fn finalize_tracers(pid: Option<libc::pid_t>, globals: &Globals) {
+        // take the tracers out of the shared map first, so that the fanotify
+        // thread is not blocked while the I/O trace logs are being saved
+        let expired_tracers = {
+            let mut active_tracers = ACTIVE_TRACERS.lock();
+
+            match pid {
+                None => take_expired_tracers(&mut active_tracers),
+
+                Some(pid) => take_exited_tracer(&mut active_tracers, pid)
+                    .map(|tracer_data| vec![(pid, tracer_data)])
+                    .unwrap_or_default(),
+            }
+        };
+
+        if expired_tracers.is_empty() {
+            return;
+        }
+
+        let iotrace_dir = globals
+            .get_config_file()
+            .state_dir
+            .clone()
+            .unwrap_or_else(|| Path::new(constants::STATE_DIR).to_path_buf());
+
+        let min_len = globals
+            .get_config_file()
+            .min_trace_log_length
+            .unwrap_or(constants::MIN_TRACE_LOG_LENGTH);
+
+        let min_prefetch_size = globals
+            .get_config_file()
+            .min_trace_log_prefetch_size
+            .unwrap_or(constants::MIN_TRACE_LOG_PREFETCH_SIZE_BYTES);
+
+        let keep_written_files = globals
+            .get_config_file()
+            .trace_written_files
+            .unwrap_or(constants::TRACE_WRITTEN_FILES);
+
+        let max_entries = globals
+            .get_config_file()
+            .trace_max_entries
+            .unwrap_or(constants::MAX_TRACE_LOG_ENTRIES);
+
+        let max_size = globals
+            .get_config_file()
+            .trace_max_size
+            .unwrap_or(constants::MAX_TRACE_LOG_SIZE_BYTES);
+
+        let policy = globals
+            .get_config_file()
+            .trace_truncation_policy
+            .unwrap_or(TruncationPolicy::Earliest);
+
+        let thread_pool = util::POOL.lock();
+        thread_pool.submit_work(move || {
+            // I/O trace logs of the same program are merged on disk,
+            // so they have to be saved one after another
+            let _guard = FINALIZE_LOCK.lock();
+
+            // the mounts are read once for all tracers
+            let host_mounts = util::parse_own_mountinfo().unwrap_or_default();
+
+            for (pid, mut tracer_data) in expired_tracers {
+                Self::merge_mapped_files(pid, &mut tracer_data, &host_mounts);
+
+                finalize_tracer(
+                    pid,
+                    tracer_data,
+                    &iotrace_dir,
+                    min_len,
+                    min_prefetch_size,
+                    keep_written_files,
+                    max_entries,
+                    max_size,
+                    policy,
+                );
+            }
+        });
+    }
+
     /// Remove process `event.pid` from the list of traced processes
-    pub fn notify_process_exit(&self, event: &procmon::Event, _globals: &mut Globals, manager: &Manager) {
+    pub fn notify_process_exit(&self, event: &procmon::Event, globals: &mut Globals, manager: &Manager) {
         let hm = manager.hook_manager.read();
 
         match hm.get_hook_by_name(&String::from("process_tracker")) {
@@ -436,10 +800,13 @@ impl FanotifyLogger {
                         // We are currently tracing the process `event.pid`
                         match active_tracers.entry(event.pid) {
                             Occupied(mut tracer_data) => {
-                                // Set `process_exited` flag in "PerTracerData" for that specific process
+                                // Set `process_exited` flag in "PerTracerData" for that specific process,
+                                // and finalize its I/O trace log right away
                                 tracer_data.get_mut().process_exited = true;
+                                events::queue_internal_event(EventType::FinalizeIOTrace(event.pid), globals);
+
                                 trace!(
-                                    "Sent notification about termination of process '{}' with pid {} to the fanotify thread",
+                                    "Requested finalization of the I/O trace log of process '{}' with pid {}",
                                     comm,
                                     event.pid
                                 );
@@ -486,17 +853,28 @@ impl hook::Hook for FanotifyLogger {
                 let mut globals_c = globals.clone();
                 let manager_c = manager.clone();
 
+                let (sender, receiver) = sync_channel(constants::FANOTIFY_QUEUE_CAPACITY);
+
+                self.fanotify_worker_thread = Some(
+                    thread::Builder::new()
+                        .name(String::from("fanotify-worker"))
+                        .spawn(move || {
+                            util::set_nice_level(constants::FANOTIFY_THREAD_NICENESS);
+
+                            Self::fanotify_worker_loop(&receiver, &mut globals_c, &manager_c);
+                        })
+                        .unwrap(),
+                );
+
                 self.fanotify_thread = Some(
                     thread::Builder::new()
                         .name(String::from("fanotify"))
                         .spawn(move || {
-                            // register fanotify watch on root fs
-                            FANOTIFY
-                                .add_filesystem((FAN_OPEN).into(), "/".to_string())
-                                .unwrap_or_else(|e| {
-                                    error!("Could not add fanotify root filesystem watch: {}", e);
-                                    panic!();
-                                });
+                            // use fanotify if available, otherwise fall back to sampling /proc
+                            let mut backend = util::select_tracer_backend();
+
+                            info!("Using tracer backend: '{}'", backend.get_name());
+                            *PIPELINE_STATISTICS.backend.lock() = String::from(backend.get_name());
 
                             util::set_cpu_affinity(0).unwrap_or_else(|_| {
                                 error!("Could not set CPU affinity!");
@@ -505,7 +883,7 @@ impl hook::Hook for FanotifyLogger {
                             util::set_nice_level(constants::FANOTIFY_THREAD_NICENESS);
 
                             'FANOTIFY_LOOP: loop {
-                                Self::fanotify_event_loop(&mut globals_c, &manager_c);
+                                Self::fanotify_event_loop(backend.as_mut(), &sender);
 
                                 if EXIT_NOW.load(Ordering::SeqCst) {
                                     break 'FANOTIFY_LOOP;
@@ -518,6 +896,16 @@ impl hook::Hook for FanotifyLogger {
                 );
             }
 
+            events::EventType::FinalizeIOTraces => {
+                Self::finalize_tracers(None, globals);
+            }
+
+            events::EventType::FinalizeIOTrace(pid) => {
+                debug!("Finalizing the I/O trace log of exited process with pid: {}", pid);
+
+                Self::finalize_tracers(Some(pid), globals);
+            }
+
             events::EventType::Shutdown => {}
             _ => { /* Ignore other events */ }
         }
@@ -20,15 +20,21 @@
 
 use std::any::Any;
 use std::collections::hash_map::Entry::{Occupied, Vacant};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
-use parking_lot::{Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use parking_lot::{Condvar, Mutex, MutexGuard, RwLock};
 use lockfree::map::Map;
 use lazy_static::lazy_static;
 use log::{trace, debug, info, warn, error, log, LevelFilter};
 use serde_derive::{Serialize, Deserialize};
+use crate::config_file;
 use crate::constants;
 use crate::events;
 use crate::events::EventType;
@@ -52,6 +58,68 @@ static DESCRIPTION: &str = "Replay file operations previously recorded by an I/O
 
 lazy_static! {
     pub static ref MAPPED_FILES: Map<PathBuf, util::MemoryMapping> = Map::new();
+    /// The pending prefetch requests of all clients of the prefetcher
+    pub static ref PREFETCH_QUEUE: Mutex<util::PrefetchQueue<Arc<PrefetchContext>>> = Mutex::new(util::PrefetchQueue::new());
+
+    /// Wakes up the prefetch scheduler, when chunks have been queued or a prefetcher thread became idle
+    static ref PREFETCH_QUEUE_CHANGED: Condvar = Condvar::new();
+
+    /// Counters of the I/O load based throttling of the prefetcher threads
+    pub static ref THROTTLE_STATISTICS: ThrottleCounters = ThrottleCounters::default();
+
+    /// Serializes the processing of chunks of rotational devices, to avoid seeking
+    static ref ROTATIONAL_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Counters of the I/O load based throttling of the prefetcher threads
+#[derive(Debug, Default)]
+pub struct ThrottleCounters {
+    /// Number of times a prefetcher thread backed off, because of high I/O load
+    pub throttle_events: AtomicU64,
+    /// Total time in milliseconds prefetcher threads spent backing off
+    pub throttled_millis: AtomicU64,
+    /// Number of chunks that have been given up, because the I/O load stayed high
+    pub aborted_chunks: AtomicU64,
+}
+
+/// A snapshot of the counters of the I/O load based throttling, along with the current I/O load
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThrottleStatistics {
+    pub throttle_events: u64,
+    pub throttled_millis: u64,
+    pub aborted_chunks: u64,
+    pub io_pressure: Option<f32>,
+    pub disk_utilization: Option<f32>,
+}
+
+/// Returns a snapshot of the counters of the I/O load based throttling
+pub fn get_throttle_statistics() -> ThrottleStatistics {
+    let load = util::get_io_load();
+
+    ThrottleStatistics {
+        throttle_events: THROTTLE_STATISTICS.throttle_events.load(Ordering::Relaxed),
+        throttled_millis: THROTTLE_STATISTICS.throttled_millis.load(Ordering::Relaxed),
+        aborted_chunks: THROTTLE_STATISTICS.aborted_chunks.load(Ordering::Relaxed),
+        io_pressure: load.pressure,
+        disk_utilization: load.disk_utilization,
+    }
+}
+
+/// Index of the thread state that will be updated by the next prefetcher job
+static NEXT_THREAD_STATE: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of chunks that have been handed out to the prefetcher threads, and that are not processed yet.
+/// Only modified while holding the lock of `PREFETCH_QUEUE`
+static ACTIVE_CHUNKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Data shared by all chunks of a prefetch request
+pub struct PrefetchContext {
+    prefetched_programs: Vec<String>,
+    static_blacklist: Vec<PathBuf>,
+    static_whitelist: HashMap<PathBuf, util::MemoryMapping>,
+    strategy: util::PrefetchStrategy,
+    globals: Globals,
+    manager: Manager,
 }
 
 /// The states a prefetcher thread can be in
@@ -63,6 +131,7 @@ pub enum ThreadState {
     PrefetchedFile(PathBuf),
     PrefetchedFileMetadata(PathBuf),
     UnmappedFile(PathBuf),
+    Throttled,
 }
 
 /// Register this hook implementation with the system
@@ -96,12 +165,15 @@ impl IOtracePrefetcher {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn prefetch_data(
         io_trace: &[iotrace::TraceLogEntry],
         prefetched_programs: &[String],
         // system_mapped_files: &HashMap<String, util::MemoryMapping>,
         static_blacklist: &[PathBuf],
         static_whitelist: &HashMap<PathBuf, util::MemoryMapping>,
+        strategy: util::PrefetchStrategy,
+        throttle: Option<&util::IOThrottle>,
         thread_state: &mut Arc<RwLock<ThreadState>>,
         globals: &Globals,
         manager: &Manager,
@@ -110,55 +182,80 @@ impl IOtracePrefetcher {
         already_prefetched.reserve(io_trace.len());
 
         for entry in io_trace {
-            match entry.operation {
-                iotrace::IOOperation::Open(ref file) => {
-                    if !Self::check_available_memory(&globals, &manager) {
-                        debug!("Low memory, skipped: {:?}", file);
-                        return None;
-                    }
+            if let Some(throttle) = throttle {
+                if !Self::wait_for_io_load(throttle, thread_state) {
+                    debug!("High I/O load, skipped: {:?}", entry.operation.get_filename());
+                    return None;
+                }
+            }
 
-                    trace!("Prefetching: {:?}", file);
-
-                    // mmap and mlock file, if it is not contained in the blacklist
-                    // and if it was not already mapped by some of the plugins
-                    if Self::shall_we_map_file(
-                        file,
-                        static_blacklist,
-                        prefetched_programs,
-                        // &system_mapped_files,
-                        static_whitelist,
-                    ) {
-                        match util::cache_file(file, true) {
-                            Err(e) => {
-                                // I/O trace log maybe needs to be optimized.
-                                info!("Could not prefetch file: {:?}: {}", file, e);
-
-                                // inhibit further prefetching of that file
-                                already_prefetched.insert(file.clone(), None);
-
-                                {
-                                    *(thread_state.write()) = ThreadState::Error(file.clone());
-                                }
+            let (file, ranges) = match entry.operation {
+                iotrace::IOOperation::Open(ref file) | iotrace::IOOperation::Exec(ref file) => (file, None),
+                iotrace::IOOperation::OpenRanges(ref file, ref ranges) => (file, Some(ranges)),
 
-                                MAPPED_FILES.remove(&file.to_path_buf());
-                                statistics::MAPPED_FILES.remove(&file.to_path_buf());
-                            }
+                iotrace::IOOperation::OpenDir(_) => {
+                    // metadata operations are replayed through the dentry and inode caches
+                    Self::prefetch_metadata(&entry.operation, static_blacklist, thread_state);
+                    continue;
+                }
+            };
 
-                            Ok(mapping) => {
-                                trace!("Successfully prefetched file: {:?}", file);
+            if !Self::check_available_memory(&globals, &manager) {
+                debug!("Low memory, skipped: {:?}", file);
+                return None;
+            }
 
-                                already_prefetched.insert(file.clone(), Some(mapping.clone()));
+            trace!("Prefetching: {:?}", file);
+
+            // mmap and mlock file, if it is not contained in the blacklist
+            // and if it was not already mapped by some of the plugins
+            if Self::shall_we_map_file(
+                file,
+                static_blacklist,
+                prefetched_programs,
+                // &system_mapped_files,
+                static_whitelist,
+            ) {
+                // only prefetch the recorded page ranges, if available
+                let result = match ranges {
+                    None => util::cache_file(file, strategy),
+                    Some(ranges) => util::cache_file_ranges(file, ranges, strategy),
+                };
+
+                match result {
+                    Err(e) => {
+                        // I/O trace log maybe needs to be optimized.
+                        info!("Could not prefetch file: {:?}: {}", file, e);
+
+                        // inhibit further prefetching of that file
+                        already_prefetched.insert(file.clone(), None);
+
+                        {
+                            *(thread_state.write()) = ThreadState::Error(file.clone());
+                        }
 
-                                {
-                                    *(thread_state.write()) = ThreadState::PrefetchedFile(file.clone());
-                                }
+                        MAPPED_FILES.remove(&file.to_path_buf());
+                        statistics::MAPPED_FILES.remove(&file.to_path_buf());
+                    }
 
-                                MAPPED_FILES.insert(file.to_path_buf(), mapping);
-                                statistics::MAPPED_FILES
-                                    .insert(file.to_path_buf())
-                                    .unwrap_or_else(|e| trace!("Element already in set: {:?}", e));
-                            }
+                    Ok(mapping) => {
+                        trace!("Successfully prefetched file: {:?}", file);
+
+                        already_prefetched.insert(file.clone(), Some(mapping.clone()));
+
+                        {
+                            *(thread_state.write()) = ThreadState::PrefetchedFile(file.clone());
                         }
+
+                        // files cached without a mapping are recorded too, so that they will not be prefetched
+                        // again until they expire; only retained mappings occupy memory, and have to be unmapped
+                        if mapping.is_mapped() {
+                            statistics::MAPPED_FILES
+                                .insert(file.to_path_buf())
+                                .unwrap_or_else(|e| trace!("Element already in set: {:?}", e));
+                        }
+
+                        MAPPED_FILES.insert(file.to_path_buf(), mapping);
                     }
                 }
             }
@@ -172,12 +269,15 @@ impl IOtracePrefetcher {
 
         for entry in io_trace {
             match entry.operation {
-                // TODO: Fix this when using a finer granularity for Prefetching
-                iotrace::IOOperation::Open(ref file) => {
+                iotrace::IOOperation::Open(ref file)
+                | iotrace::IOOperation::OpenRanges(ref file, _)
+                | iotrace::IOOperation::Exec(ref file) => {
                     trace!("Unmapping: {:?}", file);
 
                     if let Some(mapping) = MAPPED_FILES.get(file) {
-                        if util::free_mapping(&mapping.1) {
+                        if !mapping.1.is_mapped() {
+                            // the file was cached without retaining a mapping, only forget about it
+                        } else if util::free_mapping(&mapping.1) {
                             info!("Successfully unmapped file: {:?}", file);
                             result.push(mapping.0.clone());
 
@@ -194,7 +294,9 @@ impl IOtracePrefetcher {
 
                     MAPPED_FILES.remove(&file.to_path_buf());
                     statistics::MAPPED_FILES.remove(&file.to_path_buf());
-                } // _ => { /* Do nothing */ }
+                }
+
+                _ => { /* Do nothing */ }
             }
         }
 
@@ -204,32 +306,56 @@ impl IOtracePrefetcher {
     fn prefetch_statx_metadata(
         io_trace: &[iotrace::TraceLogEntry],
         static_blacklist: &[PathBuf],
+        throttle: Option<&util::IOThrottle>,
         thread_state: &mut Arc<RwLock<ThreadState>>,
     ) {
         for entry in io_trace {
-            match entry.operation {
-                iotrace::IOOperation::Open(ref file) => {
-                    trace!("Prefetching metadata for: {:?}", file);
+            if let Some(throttle) = throttle {
+                if !Self::wait_for_io_load(throttle, thread_state) {
+                    debug!("High I/O load, skipped: {:?}", entry.operation.get_filename());
+                    return;
+                }
+            }
 
-                    // Check if filename is valid
-                    if !util::is_filename_valid(file) {
-                        continue;
-                    }
+            Self::prefetch_metadata(&entry.operation, static_blacklist, thread_state);
+        }
+    }
 
-                    // Check if filename matches a blacklist rule
-                    if util::is_file_blacklisted(file, static_blacklist) {
-                        continue;
-                    }
+    /// Replay the I/O operation `operation` through the kernel's dentry and inode caches.
+    /// Directories will additionally have their entries enumerated
+    fn prefetch_metadata(
+        operation: &iotrace::IOOperation,
+        static_blacklist: &[PathBuf],
+        thread_state: &mut Arc<RwLock<ThreadState>>,
+    ) {
+        let file = operation.get_filename();
 
-                    let path = Path::new(file);
-                    let _metadata = path.metadata();
+        trace!("Prefetching metadata for: {:?}", file);
 
-                    {
-                        *(thread_state.write()) = ThreadState::PrefetchedFileMetadata(file.clone());
-                    }
-                } // _ => { /* Do nothing */ }
+        // Check if filename is valid
+        if !util::is_filename_valid(file) {
+            return;
+        }
+
+        // Check if filename matches a blacklist rule
+        if util::is_file_blacklisted(file, static_blacklist) {
+            return;
+        }
+
+        match operation {
+            iotrace::IOOperation::OpenDir(_) => {
+                util::prime_directory_cache(file)
+                    .unwrap_or_else(|e| trace!("Could not prefetch directory entries of: {:?}: {}", file, e));
+            }
+
+            _ => {
+                let _metadata = file.metadata();
             }
         }
+
+        {
+            *(thread_state.write()) = ThreadState::PrefetchedFileMetadata(file.to_path_buf());
+        }
     }
 
     /// Helper function, that decides if we should subsequently mmap() and mlock() the
@@ -258,8 +384,8 @@ impl IOtracePrefetcher {
             return false;
         }
 
-        // Have we already mapped this file?
-        if MAPPED_FILES.get(&PathBuf::from(filename)).is_some() {
+        // Have we already mapped this file, or recently cached it without a mapping?
+        if MAPPED_FILES.get(filename).map_or(false, |mapping| mapping.1.is_cached()) {
             return false;
         }
 
@@ -269,7 +395,7 @@ impl IOtracePrefetcher {
         }
 
         // Have others already mapped this file?
-        if static_whitelist.contains_key(&PathBuf::from(filename)) {
+        if static_whitelist.get(filename).map_or(false, |mapping| mapping.is_cached()) {
             return false;
         }
 
@@ -277,6 +403,375 @@ impl IOtracePrefetcher {
         true
     }
 
+    /// Group the entries of the I/O trace log `io_trace` into time slots of `slot_millis` milliseconds,
+    /// ordered by their offset from the start of the trace. Returns pairs of the offset of each
+    /// time slot in milliseconds and the entries it contains
+    fn build_timeline(io_trace: &iotrace::IOTraceLog, slot_millis: u64) -> Vec<(u64, Vec<iotrace::TraceLogEntry>)> {
+        let mut timeline = BTreeMap::new();
+
+        for entry in io_trace.trace_log.iter() {
+            let offset = (entry.timestamp - io_trace.created_at).num_milliseconds().max(0) as u64;
+            let slot = offset / slot_millis * slot_millis;
+
+            timeline.entry(slot).or_insert_with(Vec::new).push(entry.clone());
+        }
+
+        timeline.into_iter().collect()
+    }
+
+    /// Returns the entries of the I/O trace log `io_trace` as time slots, along with the point in time
+    /// each time slot is due. If `timeline_replay` is set, the entries are ordered by their offset from
+    /// the start of the trace, and if `lead_time` is specified, each time slot will be due `lead_time`
+    /// ahead of the time the program is expected to need it. Otherwise all entries are due at once
+    fn build_slots(
+        io_trace: &iotrace::IOTraceLog,
+        timeline_replay: bool,
+        lead_time: Option<Duration>,
+    ) -> Vec<(Option<Instant>, Vec<iotrace::TraceLogEntry>)> {
+        if !timeline_replay {
+            return vec![(None, io_trace.trace_log.clone())];
+        }
+
+        let start = Instant::now();
+
+        Self::build_timeline(io_trace, constants::PREFETCH_TIMELINE_SLOT_MILLIS)
+            .into_iter()
+            .map(|(offset, trace_log)| {
+                let deadline = lead_time.map(|t| start + Duration::from_millis(offset).checked_sub(t).unwrap_or_default());
+
+                (deadline, trace_log)
+            })
+            .collect()
+    }
+
+    /// Queue the time slots `slots` of the I/O trace log of the program `name` with priority `priority`,
+    /// and wake up the prefetch scheduler, that hands out the chunks to the prefetcher threads as soon
+    /// as they are due. In rotational mode, the entries of files on rotational devices are queued
+    /// separately, to be processed in on-disk order
+    fn schedule(
+        &self,
+        name: &str,
+        priority: util::PrefetchPriority,
+        kind: util::PrefetchKind,
+        slots: Vec<(Option<Instant>, Vec<iotrace::TraceLogEntry>)>,
+        context: PrefetchContext,
+    ) {
+        let rotational_mode = kind == util::PrefetchKind::Data
+            && context
+                .globals
+                .get_config_file()
+                .prefetch_rotational_mode
+                .unwrap_or(constants::PREFETCH_ROTATIONAL_MODE);
+
+        let (ordered_slots, slots) = if rotational_mode {
+            Self::split_rotational_slots(slots)
+        } else {
+            (vec![], slots)
+        };
+
+        let context = Arc::new(context);
+
+        let mut queue = PREFETCH_QUEUE.lock();
+
+        let count = queue.push(name, priority, kind, slots, context.clone())
+            + queue.push_ordered(name, priority, kind, ordered_slots, context);
+
+        if count < 1 {
+            debug!("All files of '{}' are already queued for prefetching", name);
+            return;
+        }
+
+        trace!("Queued {} chunks of '{}' with priority: {:?}", count, name, priority);
+
+        PREFETCH_QUEUE_CHANGED.notify_one();
+    }
+
+    /// The main loop of the prefetch scheduler thread: Hands out the due chunk of the highest priority
+    /// to the next idle prefetcher thread, so that concurrent requests are served in order of their
+    /// priority, instead of in order of their submission. Chunks that are not due yet are left in
+    /// the queue, and the scheduler sleeps until the earliest of them is due
+    fn prefetch_scheduler_loop(thread_states: &[Arc<RwLock<ThreadState>>]) {
+        let mut queue = PREFETCH_QUEUE.lock();
+
+        loop {
+            let deadline = if ACTIVE_CHUNKS.load(Ordering::SeqCst) >= constants::NUM_PREFETCHER_THREADS {
+                // wait until a prefetcher thread becomes idle
+                None
+            } else {
+                match queue.pop(Instant::now()) {
+                    util::PopResult::Chunk(chunk) => {
+                        ACTIVE_CHUNKS.fetch_add(1, Ordering::SeqCst);
+
+                        let thread_states = thread_states.to_vec();
+
+                        MutexGuard::unlocked(&mut queue, || {
+                            util::PREFETCH_POOL.lock().execute(move || {
+                                Self::process_chunk(chunk, &thread_states);
+                            })
+                        });
+
+                        continue;
+                    }
+
+                    util::PopResult::Pending(deadline) => Some(deadline),
+                    util::PopResult::Empty => None,
+                }
+            };
+
+            match deadline {
+                Some(deadline) => {
+                    PREFETCH_QUEUE_CHANGED.wait_until(&mut queue, deadline);
+                }
+
+                None => PREFETCH_QUEUE_CHANGED.wait(&mut queue),
+            }
+        }
+    }
+
+    /// Split the time slots `slots` into the entries of files residing on rotational devices,
+    /// and all other entries. Returns a pair of the rotational and the remaining time slots
+    #[allow(clippy::type_complexity)]
+    fn split_rotational_slots(
+        slots: Vec<(Option<Instant>, Vec<iotrace::TraceLogEntry>)>,
+    ) -> (
+        Vec<(Option<Instant>, Vec<iotrace::TraceLogEntry>)>,
+        Vec<(Option<Instant>, Vec<iotrace::TraceLogEntry>)>,
+    ) {
+        let mounts = match util::parse_own_mountinfo() {
+            Err(e) => {
+                warn!("Could not determine the block devices of prefetched files: {}", e);
+                return (vec![], slots);
+            }
+
+            Ok(mounts) => mounts,
+        };
+
+        let mut rotational_slots = vec![];
+        let mut other_slots = vec![];
+
+        for (not_before, entries) in slots {
+            // metadata operations are not affected by the order of data on disk
+            let (rotational, other): (Vec<iotrace::TraceLogEntry>, Vec<iotrace::TraceLogEntry>) =
+                entries.into_iter().partition(|e| match e.operation {
+                    iotrace::IOOperation::OpenDir(_) => false,
+                    _ => util::is_on_rotational_device(&mounts, e.operation.get_filename()),
+                });
+
+            if !rotational.is_empty() {
+                rotational_slots.push((not_before, rotational));
+            }
+
+            if !other.is_empty() {
+                other_slots.push((not_before, other));
+            }
+        }
+
+        (rotational_slots, other_slots)
+    }
+
+    /// Returns the key by which the entries of rotational devices are ordered:
+    /// The device, and the physical offset of the first accessed byte on that device
+    fn get_block_order_key(entry: &iotrace::TraceLogEntry) -> (u64, u64) {
+        let filename = entry.operation.get_filename();
+
+        let offset = match entry.operation {
+            iotrace::IOOperation::OpenRanges(_, ref ranges) => ranges.first().map_or(0, |r| r.0),
+            _ => 0,
+        };
+
+        let dev = fs::metadata(filename).map(|m| m.dev()).unwrap_or(0);
+
+        // files without physical extents, e.g. empty or inline files, are sorted last
+        let physical_offset = util::get_physical_offset(filename, offset).unwrap_or(u64::max_value());
+
+        (dev, physical_offset)
+    }
+
+    /// Serve the chunk `chunk`, that has been handed out by the prefetch scheduler
+    fn process_chunk(chunk: util::PrefetchChunk<Arc<PrefetchContext>>, thread_states: &[Arc<RwLock<ThreadState>>]) {
+        let index = NEXT_THREAD_STATE.fetch_add(1, Ordering::Relaxed) % thread_states.len();
+        let mut thread_state = thread_states[index].clone();
+
+        // prefetching for programs that are being started right now is never throttled
+        let throttle = if chunk.priority < util::PrefetchPriority::Interactive {
+            Some(Self::get_io_throttle(&chunk.context.globals))
+        } else {
+            None
+        };
+
+        if chunk.ordered {
+            Self::process_ordered_chunks(chunk, throttle.as_ref(), &mut thread_state);
+        } else {
+            Self::process_entries(&chunk, &chunk.entries, throttle.as_ref(), &mut thread_state);
+
+            PREFETCH_QUEUE.lock().complete(&chunk);
+        }
+
+        // this prefetcher thread is idle again
+        let _queue = PREFETCH_QUEUE.lock();
+
+        ACTIVE_CHUNKS.fetch_sub(1, Ordering::SeqCst);
+        PREFETCH_QUEUE_CHANGED.notify_one();
+    }
+
+    /// Process the chunk `chunk` of a rotational device, along with all other pending chunks of
+    /// rotational devices of the same priority, if batching is enabled. Only one thread at a time
+    /// processes chunks of rotational devices, ordered by the physical location of their entries
+    fn process_ordered_chunks(
+        chunk: util::PrefetchChunk<Arc<PrefetchContext>>,
+        throttle: Option<&util::IOThrottle>,
+        thread_state: &mut Arc<RwLock<ThreadState>>,
+    ) {
+        let _guard = ROTATIONAL_LOCK.lock();
+
+        let batching = chunk
+            .context
+            .globals
+            .get_config_file()
+            .prefetch_rotational_batching
+            .unwrap_or(constants::PREFETCH_ROTATIONAL_BATCHING);
+
+        let mut chunks = vec![];
+
+        if batching {
+            chunks = PREFETCH_QUEUE.lock().pop_ordered(chunk.priority, chunk.kind, Instant::now());
+        }
+
+        chunks.insert(0, chunk);
+
+        // remember the chunk each entry belongs to, for its context
+        let mut entries: Vec<(usize, &iotrace::TraceLogEntry)> = chunks
+            .iter()
+            .enumerate()
+            .flat_map(|(index, chunk)| chunk.entries.iter().map(move |e| (index, e)))
+            .collect();
+
+        entries.sort_by_cached_key(|(_index, e)| Self::get_block_order_key(e));
+
+        trace!("Prefetching {} entries in on-disk order", entries.len());
+
+        // process runs of consecutive entries that belong to the same chunk
+        let mut start = 0;
+
+        while start < entries.len() {
+            let index = entries[start].0;
+
+            let end = entries[start..]
+                .iter()
+                .position(|(i, _e)| *i != index)
+                .map_or(entries.len(), |n| start + n);
+
+            let run: Vec<iotrace::TraceLogEntry> = entries[start..end].iter().map(|(_i, e)| (*e).clone()).collect();
+
+            Self::process_entries(&chunks[index], &run, throttle, thread_state);
+
+            start = end;
+        }
+
+        let mut queue = PREFETCH_QUEUE.lock();
+
+        for chunk in chunks.iter() {
+            queue.complete(chunk);
+        }
+    }
+
+    /// Prefetch the entries `entries`, using the kind and the context of the chunk `chunk`
+    fn process_entries(
+        chunk: &util::PrefetchChunk<Arc<PrefetchContext>>,
+        entries: &[iotrace::TraceLogEntry],
+        throttle: Option<&util::IOThrottle>,
+        thread_state: &mut Arc<RwLock<ThreadState>>,
+    ) {
+        let context = &chunk.context;
+
+        match chunk.kind {
+            util::PrefetchKind::Data => {
+                Self::prefetch_data(
+                    entries,
+                    &context.prefetched_programs,
+                    &context.static_blacklist,
+                    &context.static_whitelist,
+                    context.strategy,
+                    throttle,
+                    thread_state,
+                    &context.globals,
+                    &context.manager,
+                );
+            }
+
+            util::PrefetchKind::Metadata => {
+                Self::prefetch_statx_metadata(entries, &context.static_blacklist, throttle, thread_state);
+            }
+        }
+    }
+
+    /// Returns the configured thresholds of the I/O load, above which prefetching will be throttled
+    fn get_io_throttle(globals: &Globals) -> util::IOThrottle {
+        let config_file = globals.get_config_file();
+
+        util::IOThrottle {
+            max_pressure: f32::from(
+                config_file
+                    .io_pressure_upper_threshold
+                    .unwrap_or(constants::IO_PRESSURE_UPPER_THRESHOLD),
+            ),
+            max_disk_utilization: f32::from(
+                config_file
+                    .disk_utilization_upper_threshold
+                    .unwrap_or(constants::DISK_UTILIZATION_UPPER_THRESHOLD),
+            ),
+            max_pause: Duration::from_millis(
+                config_file
+                    .io_throttle_max_pause
+                    .unwrap_or(constants::IO_THROTTLE_MAX_PAUSE_MILLIS),
+            ),
+        }
+    }
+
+    /// Wait until the I/O load of the system drops below the thresholds of `throttle`, backing
+    /// off exponentially. Returns `false` if the I/O load stays above the thresholds for longer
+    /// than the maximum pause, in which case the caller shall give up on its current work
+    fn wait_for_io_load(throttle: &util::IOThrottle, thread_state: &mut Arc<RwLock<ThreadState>>) -> bool {
+        let mut load = util::get_io_load();
+
+        if !throttle.is_exceeded_by(&load) {
+            return true;
+        }
+
+        debug!("High I/O load, throttling prefetching: {:?}", load);
+
+        THROTTLE_STATISTICS.throttle_events.fetch_add(1, Ordering::Relaxed);
+
+        {
+            *(thread_state.write()) = ThreadState::Throttled;
+        }
+
+        let start = Instant::now();
+        let mut backoff = Duration::from_millis(constants::IO_THROTTLE_INITIAL_BACKOFF_MILLIS);
+
+        let result = loop {
+            if !throttle.is_exceeded_by(&load) {
+                break true;
+            }
+
+            if start.elapsed() >= throttle.max_pause {
+                THROTTLE_STATISTICS.aborted_chunks.fetch_add(1, Ordering::Relaxed);
+                break false;
+            }
+
+            thread::sleep(backoff);
+
+            backoff = std::cmp::min(backoff * 2, Duration::from_millis(constants::IO_THROTTLE_MAX_BACKOFF_MILLIS));
+            load = util::get_io_load();
+        };
+
+        THROTTLE_STATISTICS
+            .throttled_millis
+            .fetch_add(start.elapsed().as_millis() as u64, Ordering::Relaxed);
+
+        result
+    }
+
     /// Check if we have enough available memory to perform prefetching
     fn check_available_memory(globals: &Globals, manager: &Manager) -> bool {
         let mut result = false;
@@ -289,6 +784,11 @@ impl IOtracePrefetcher {
             .available_mem_upper_threshold
             .unwrap();
 
+        let memory_pressure_upper_threshold = globals
+            .get_config_file()
+            .memory_pressure_upper_threshold
+            .unwrap_or(constants::MEMORY_PRESSURE_UPPER_THRESHOLD);
+
         let pm = manager.plugin_manager.read();
 
         match pm.get_plugin_by_name(&String::from("metrics")) {
@@ -300,7 +800,12 @@ impl IOtracePrefetcher {
                 let p = p.read();
                 let metrics_plugin = p.as_any().downcast_ref::<Metrics>().unwrap();
 
-                if metrics_plugin.get_mem_usage_percentage() <= available_mem_upper_threshold {
+                // do not add to the memory pressure, while tasks stall on memory reclaim
+                let is_memory_pressure_high = metrics_plugin
+                    .get_memory_pressure()
+                    .map_or(false, |p| p >= f32::from(memory_pressure_upper_threshold));
+
+                if metrics_plugin.get_mem_usage_percentage() <= available_mem_upper_threshold && !is_memory_pressure_high {
                     result = true;
                 }
             }
@@ -311,7 +816,14 @@ impl IOtracePrefetcher {
 
     /// Replay the I/O trace of the I/O trace for `hashval` and cache all files into memory
     /// This is used for offline prefetching, when the system is idle
-    pub fn prefetch_data_by_hash(&mut self, hashval: &str, globals: &Globals, manager: &Manager) {
+    pub fn prefetch_data_by_hash(
+        &mut self,
+        hashval: &str,
+        priority: util::PrefetchPriority,
+        strategy: util::PrefetchStrategy,
+        globals: &Globals,
+        manager: &Manager,
+    ) {
         let pm = manager.plugin_manager.read();
 
         match pm.get_plugin_by_name(&String::from("iotrace_log_manager")) {
@@ -347,42 +859,24 @@ impl IOtracePrefetcher {
 
                             let prefetched_programs = self.prefetched_programs.clone();
 
-                            // distribute prefetching work evenly across the prefetcher threads
-                            let prefetch_pool = util::PREFETCH_POOL.lock();
-                            let max = prefetch_pool.max_count();
-                            let count_total = io_trace.trace_log.len();
-
-                            for n in 0..max {
-                                let globals_c = globals.clone();
-                                let manager_c = manager.clone();
-
-                                // calculate slice bounds for each thread
-                                let low = (count_total / max) * n;
-                                let high = (count_total / max) * n + (count_total / max);
-
-                                let trace_log = io_trace.trace_log[low..high].to_vec();
-
-                                let prefetched_programs_c = prefetched_programs.clone();
-                                // let system_mapped_files_c = system_mapped_files_histogram.clone();
-                                let static_blacklist_c = static_blacklist.clone();
-                                let static_whitelist_c = static_whitelist.clone();
-
-                                let mut thread_state = self.thread_states[n].clone();
-
-                                prefetch_pool.execute(move || {
-                                    // submit prefetching work to an idle thread
-                                    Self::prefetch_data(
-                                        &trace_log,
-                                        &prefetched_programs_c,
-                                        // &system_mapped_files_c,
-                                        &static_blacklist_c,
-                                        &static_whitelist_c,
-                                        &mut thread_state,
-                                        &globals_c,
-                                        &manager_c,
-                                    );
-                                })
-                            }
+                            let timeline_replay = globals
+                                .get_config_file()
+                                .prefetch_timeline_replay
+                                .unwrap_or(constants::PREFETCH_TIMELINE_REPLAY);
+
+                            let context = PrefetchContext {
+                                prefetched_programs,
+                                static_blacklist,
+                                static_whitelist,
+                                strategy,
+                                globals: globals.clone(),
+                                manager: manager.clone(),
+                            };
+
+                            // the program is not running, so order by time offsets, but do not pace
+                            let slots = Self::build_slots(&io_trace, timeline_replay, None);
+
+                            self.schedule(&io_trace.comm, priority, util::PrefetchKind::Data, slots, context);
                         }
                     }
                 }
@@ -405,19 +899,16 @@ impl IOtracePrefetcher {
                 match iotrace_log_manager_plugin.get_trace_log_by_hash(hashval, globals) {
                     Err(e) => trace!("I/O trace '{}' not available: {}", hashval, e),
                     Ok(io_trace) => {
-                        // distribute prefetching work evenly across the prefetcher threads
+                        // distribute the work evenly across the prefetcher threads, rounding
+                        // up the size of the slices, so that all entries will be covered
                         let prefetch_pool = util::PREFETCH_POOL.lock();
                         let max = prefetch_pool.max_count();
-                        let count_total = io_trace.trace_log.len();
-
-                        for n in 0..max {
-                            // calculate slice bounds for each thread
-                            let low = (count_total / max) * n;
-                            let high = (count_total / max) * n + (count_total / max);
+                        let slice_len = std::cmp::max(1, (io_trace.trace_log.len() + max - 1) / max);
 
-                            let trace_log = io_trace.trace_log[low..high].to_vec();
+                        for (n, trace_log) in io_trace.trace_log.chunks(slice_len).enumerate() {
+                            let trace_log = trace_log.to_vec();
 
-                            let mut thread_state = self.thread_states[n].clone();
+                            let mut thread_state = self.thread_states[n % self.thread_states.len()].clone();
 
                             prefetch_pool.execute(move || {
                                 // submit memory freeing work to an idle thread
@@ -463,27 +954,25 @@ impl IOtracePrefetcher {
                                 }
                             };
 
-                            // distribute prefetching work evenly across the prefetcher threads
-                            let prefetch_pool = util::PREFETCH_POOL.lock();
-                            let max = prefetch_pool.max_count();
-                            let count_total = io_trace.trace_log.len();
-
-                            for n in 0..max {
-                                // calculate slice bounds for each thread
-                                let low = (count_total / max) * n;
-                                let high = (count_total / max) * n + (count_total / max);
-
-                                let trace_log = io_trace.trace_log[low..high].to_vec();
-
-                                let static_blacklist_c = static_blacklist.clone();
+                            let context = PrefetchContext {
+                                prefetched_programs: vec![],
+                                static_blacklist,
+                                static_whitelist: HashMap::new(),
+                                // metadata prefetching does not cache the contents of files
+                                strategy: util::PrefetchStrategy::Fadvise,
+                                globals: globals.clone(),
+                                manager: manager.clone(),
+                            };
 
-                                let mut thread_state = self.thread_states[n].clone();
+                            let slots = vec![(None, io_trace.trace_log.clone())];
 
-                                prefetch_pool.execute(move || {
-                                    // submit prefetching work to an idle thread
-                                    Self::prefetch_statx_metadata(&trace_log, &static_blacklist_c, &mut thread_state);
-                                })
-                            }
+                            self.schedule(
+                                &io_trace.comm,
+                                util::PrefetchPriority::Background,
+                                util::PrefetchKind::Metadata,
+                                slots,
+                                context,
+                            );
                         }
                     }
                 }
@@ -534,7 +1023,7 @@ impl IOtracePrefetcher {
                                     globals,
                                 ) {
                                     Err(e) => trace!("No I/O trace available: {}", e),
-                                    Ok(io_trace) => {
+                                    Ok((io_trace, match_key)) => {
                                         if io_trace.blacklisted {
                                             info!(
                                                 "Found blacklisted I/O trace log for process '{}' with pid: {}. Skipped prefetching",
@@ -542,8 +1031,8 @@ impl IOtracePrefetcher {
                                             );
                                         } else {
                                             info!(
-                                                "Found valid I/O trace log for process '{}' with pid: {}. Prefetching now...",
-                                                process_comm, event.pid
+                                                "Found valid I/O trace log for process '{}' with pid: {} (matched by: {:?}). Prefetching now...",
+                                                process_comm, event.pid, match_key
                                             );
 
                                             let mut do_perform_prefetching = true;
@@ -559,7 +1048,7 @@ impl IOtracePrefetcher {
                                                         p.as_any().downcast_ref::<HotApplications>().unwrap();
 
                                                     do_perform_prefetching =
-                                                        !hot_applications_plugin.is_exe_cached(&process_exe, &process_cmdline);
+                                                        !hot_applications_plugin.is_trace_cached(&io_trace.hash);
                                                 }
                                             };
 
@@ -595,42 +1084,40 @@ impl IOtracePrefetcher {
 
                                                 let prefetched_programs = self.prefetched_programs.clone();
 
-                                                // distribute prefetching work evenly across the prefetcher threads
-                                                let prefetch_pool = util::PREFETCH_POOL.lock();
-                                                let max = prefetch_pool.max_count();
-                                                let count_total = io_trace.trace_log.len();
-
-                                                for n in 0..max {
-                                                    let globals_c = globals.clone();
-                                                    let manager_c = manager.clone();
-
-                                                    // calculate slice bounds for each thread
-                                                    let low = (count_total / max) * n;
-                                                    let high = (count_total / max) * n + (count_total / max);
-
-                                                    let trace_log = io_trace.trace_log[low..high].to_vec();
-
-                                                    // let system_mapped_files_c = system_mapped_files_histogram.clone();
-                                                    let prefetched_programs_c = prefetched_programs.clone();
-                                                    let static_blacklist_c = static_blacklist.clone();
-                                                    let static_whitelist_c = static_whitelist.clone();
-
-                                                    let mut thread_state = self.thread_states[n].clone();
-
-                                                    // submit prefetching work to an idle thread
-                                                    prefetch_pool.execute(move || {
-                                                        Self::prefetch_data(
-                                                            &trace_log,
-                                                            &prefetched_programs_c,
-                                                            // &system_mapped_files_c,
-                                                            &static_blacklist_c,
-                                                            &static_whitelist_c,
-                                                            &mut thread_state,
-                                                            &globals_c,
-                                                            &manager_c,
-                                                        );
-                                                    })
-                                                }
+                                                let timeline_replay = globals
+                                                    .get_config_file()
+                                                    .prefetch_timeline_replay
+                                                    .unwrap_or(constants::PREFETCH_TIMELINE_REPLAY);
+
+                                                let lead_time = Duration::from_millis(
+                                                    globals
+                                                        .get_config_file()
+                                                        .prefetch_timeline_lead_time
+                                                        .unwrap_or(constants::PREFETCH_TIMELINE_LEAD_TIME_MILLIS),
+                                                );
+
+                                                let context = PrefetchContext {
+                                                    prefetched_programs,
+                                                    static_blacklist,
+                                                    static_whitelist,
+                                                    strategy: config_file::get_prefetch_strategy(
+                                                        globals,
+                                                        config_file::PrefetchSource::IOTraceReplay,
+                                                    ),
+                                                    globals: globals.clone(),
+                                                    manager: manager.clone(),
+                                                };
+
+                                                // issue each time slot ahead of the time the program will need it
+                                                let slots = Self::build_slots(&io_trace, timeline_replay, Some(lead_time));
+
+                                                self.schedule(
+                                                    &process_comm,
+                                                    util::PrefetchPriority::Interactive,
+                                                    util::PrefetchKind::Data,
+                                                    slots,
+                                                    context,
+                                                );
                                             } else {
                                                 // executable is already cached by "hot apps"
                                                 info!("Skipped prefetching, files are already cached!");
@@ -662,6 +1149,17 @@ impl hook::Hook for IOtracePrefetcher {
 
     fn internal_event(&mut self, event: &events::InternalEvent, _globals: &mut Globals, _manager: &Manager) {
         match event.event_type {
+            events::EventType::Startup => {
+                let thread_states = self.thread_states.clone();
+
+                thread::Builder::new()
+                    .name(String::from("prefetch-scheduler"))
+                    .spawn(move || {
+                        Self::prefetch_scheduler_loop(&thread_states);
+                    })
+                    .unwrap();
+            }
+
             events::EventType::EnterIdle => {
                 // when the system is idle, set thread states to idle too
                 // TODO: Verify that this is correct
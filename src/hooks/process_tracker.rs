@@ -21,11 +21,13 @@
 use std::any::Any;
 use std::collections::HashMap;
 use log::{trace, debug, info, warn, error, log, LevelFilter};
+use crate::constants;
 use crate::events;
 use crate::events::EventType;
 use crate::globals::*;
 use crate::hooks::hook;
 use crate::manager::*;
+use crate::process;
 use crate::process::Process;
 use crate::procmon;
 
@@ -65,6 +67,35 @@ impl ProcessTracker {
         &mut self.tracked_processes
     }
 
+    /// Walk up the process tree, starting at the parent of process `pid`, and
+    /// return the pid of the first ancestor that satisfies `predicate`
+    pub fn find_ancestor<F>(&self, pid: libc::pid_t, predicate: F) -> Option<libc::pid_t>
+    where
+        F: Fn(libc::pid_t) -> bool,
+    {
+        let mut current = pid;
+
+        for _ in 0..constants::MAX_PROCESS_TREE_DEPTH {
+            let ppid = match self.get_process(current) {
+                Some(process) => process.ppid,
+                None => process::get_parent_pid(current)?,
+            };
+
+            // stop at the init process
+            if ppid <= 1 {
+                return None;
+            }
+
+            if predicate(ppid) {
+                return Some(ppid);
+            }
+
+            current = ppid;
+        }
+
+        None
+    }
+
     pub fn prune_zombies(&mut self) {
         self.tracked_processes.retain(|_k, v| !v.is_dead);
     }
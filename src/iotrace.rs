@@ -20,12 +20,15 @@
 
 #![allow(unused)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::hash::Hasher;
 use std::io;
 use std::io::BufReader;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
 use serde_derive::{Serialize, Deserialize};
 use log::{trace, debug, info, warn, error, log, LevelFilter};
 use crate::constants;
@@ -34,10 +37,271 @@ use crate::process::Process;
 use crate::util;
 use crate::util::MountInfo;
 
+/// The version of the on-disk format of I/O trace log files written by this build.
+/// Bump this whenever the serialized representation of `IOTraceLog` changes, and
+/// add a matching step to `migrate_io_trace_log()`
+pub const IOTRACE_FORMAT_VERSION: u32 = 15;
+
+/// I/O trace log files written before the on-disk format was versioned
+/// do not carry a `format_version` field; treat them as version 1
+pub const IOTRACE_LEGACY_FORMAT_VERSION: u32 = 1;
+
+/// The first on-disk format version that embeds a checksum into I/O trace log files
+pub const IOTRACE_CHECKSUM_FORMAT_VERSION: u32 = 8;
+
+/// Error returned when an I/O trace log file is damaged, e.g. truncated
+/// or failing checksum verification, as opposed to an older format
+#[derive(Debug)]
+pub struct CorruptTraceLog(pub String);
+
+impl std::fmt::Display for CorruptTraceLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Corrupt I/O trace log: {}", self.0)
+    }
+}
+
+impl std::error::Error for CorruptTraceLog {}
+
+fn corrupt_error(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, CorruptTraceLog(reason))
+}
+
+/// Returns `true` if the error `e` has been caused by a corrupt I/O trace log file
+pub fn is_corrupt_error(e: &io::Error) -> bool {
+    e.get_ref().map(|inner| inner.is::<CorruptTraceLog>()).unwrap_or(false)
+}
+
 /// Represents an I/O operation in an I/O trace log entry
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum IOOperation {
+    /// The file has been opened, the whole file will be prefetched
     Open(PathBuf),
+    /// The file has been opened, and only the contained page ranges have been
+    /// resident in the page cache at the end of the trace. Ranges are stored as
+    /// `(offset, length)` pairs in bytes
+    OpenRanges(PathBuf, Vec<(u64, u64)>),
+    /// The directory has been opened, e.g. to enumerate its entries
+    OpenDir(PathBuf),
+    /// The file has been opened for execution, e.g. an executable or a shared object
+    Exec(PathBuf),
+}
+
+impl IOOperation {
+    /// Returns the path of the file or directory the I/O operation refers to
+    pub fn get_filename(&self) -> &Path {
+        match *self {
+            IOOperation::Open(ref filename)
+            | IOOperation::OpenRanges(ref filename, _)
+            | IOOperation::OpenDir(ref filename)
+            | IOOperation::Exec(ref filename) => filename,
+        }
+    }
+
+    /// Returns a copy of the I/O operation, with its path rewritten
+    /// by the path-prefix remapping rules `rules`
+    pub fn remap(&self, rules: &[(PathBuf, PathBuf)]) -> IOOperation {
+        match *self {
+            IOOperation::Open(ref filename) => IOOperation::Open(remap_path(filename, rules)),
+            IOOperation::OpenRanges(ref filename, ref ranges) => {
+                IOOperation::OpenRanges(remap_path(filename, rules), ranges.clone())
+            }
+            IOOperation::OpenDir(ref filename) => IOOperation::OpenDir(remap_path(filename, rules)),
+            IOOperation::Exec(ref filename) => IOOperation::Exec(remap_path(filename, rules)),
+        }
+    }
+}
+
+/// Rewrite `path` using the first matching rule of the path-prefix
+/// remapping rules `rules`, given as `(old prefix, new prefix)` pairs
+pub fn remap_path(path: &Path, rules: &[(PathBuf, PathBuf)]) -> PathBuf {
+    for (from, to) in rules.iter() {
+        if let Ok(suffix) = path.strip_prefix(from) {
+            if suffix.as_os_str().is_empty() {
+                return to.clone();
+            } else {
+                return to.join(suffix);
+            }
+        }
+    }
+
+    path.to_path_buf()
+}
+
+/// Returns the total amount of bytes covered by the page ranges `ranges`
+pub fn get_ranges_size(ranges: &[(u64, u64)]) -> u64 {
+    ranges.iter().map(|(_offset, len)| len).sum()
+}
+
+/// The kind of key an I/O trace log is stored and looked up by
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum TraceMatchKey {
+    /// Executable and the exact command line of the process
+    Exact,
+    /// Executable and the normalized command line of the process
+    Normalized,
+    /// The build-id of the executable, shared by identical binaries at different paths
+    BuildId,
+    /// Executable only
+    ExeOnly,
+}
+
+pub fn map_trace_match_key_to_string(key: TraceMatchKey) -> String {
+    match key {
+        TraceMatchKey::Exact => tr!("match-key-exact").to_string(),
+        TraceMatchKey::Normalized => tr!("match-key-normalized").to_string(),
+        TraceMatchKey::BuildId => tr!("match-key-build-id").to_string(),
+        TraceMatchKey::ExeOnly => tr!("match-key-exe-only").to_string(),
+    }
+}
+
+/// The reason why the tracing of a process has been stopped
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum TraceStopReason {
+    /// Not known, e.g. the trace log has been written by an older version of precached
+    Unknown,
+    /// The process did not access any files for the duration of the quiet period
+    Quiescent,
+    /// The maximum trace duration has been reached
+    TimeLimit,
+    /// The process exited while it was being traced
+    ProcessExited,
+}
+
+pub fn map_trace_stop_reason_to_string(reason: TraceStopReason) -> String {
+    match reason {
+        TraceStopReason::Unknown => tr!("stop-reason-unknown").to_string(),
+        TraceStopReason::Quiescent => tr!("stop-reason-quiescent").to_string(),
+        TraceStopReason::TimeLimit => tr!("stop-reason-time-limit").to_string(),
+        TraceStopReason::ProcessExited => tr!("stop-reason-process-exited").to_string(),
+    }
+}
+
+/// Returns the hash value of the executable `exe` and the command line `cmdline`,
+/// that is used as the name of the I/O trace log file
+pub fn compute_hash(exe: &Path, cmdline: &str) -> String {
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(&exe.to_string_lossy().into_owned().into_bytes());
+    hasher.write(cmdline.as_bytes());
+
+    format!("{}", hasher.finish())
+}
+
+/// Returns the hash value of the build-id `build_id` of an executable, that is used as the name
+/// of the I/O trace log file shared by all identical copies of the executable
+pub fn compute_build_id_hash(build_id: &str) -> String {
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(b"build-id:");
+    hasher.write(build_id.as_bytes());
+
+    format!("{}", hasher.finish())
+}
+
+/// Normalize the command line `cmdline` (arguments separated by NUL characters),
+/// by removing all arguments except the first one that match any of the `rules`
+pub fn normalize_cmdline(cmdline: &str, rules: &[Regex]) -> String {
+    cmdline
+        .split('\0')
+        .enumerate()
+        .filter(|(index, arg)| *index == 0 || !rules.iter().any(|r| r.is_match(arg)))
+        .map(|(_index, arg)| arg)
+        .collect::<Vec<&str>>()
+        .join("\0")
+}
+
+/// Compile the command line normalization rules `rules`, skipping invalid expressions
+pub fn compile_normalization_rules(rules: &[String]) -> Vec<Regex> {
+    let mut result = vec![];
+
+    for rule in rules.iter() {
+        match Regex::new(rule) {
+            Err(e) => error!("Invalid command line normalization rule '{}': {}", rule, e),
+            Ok(regex) => result.push(regex),
+        }
+    }
+
+    result
+}
+
+/// Returns the keys an I/O trace log for the executable `exe` with the build-id `build_id`
+/// and the command line `cmdline` shall be looked up by, in order of preference, as pairs
+/// of the kind of the key and the hash value. I/O trace logs are stored by the normalized
+/// command line, which is the exact command line if no normalization rule applies
+pub fn get_lookup_keys(exe: &Path, cmdline: &str, build_id: Option<&str>, rules: &[Regex]) -> Vec<(TraceMatchKey, String)> {
+    let normalized_cmdline = normalize_cmdline(cmdline, rules);
+
+    let match_key = if normalized_cmdline == cmdline {
+        TraceMatchKey::Exact
+    } else {
+        TraceMatchKey::Normalized
+    };
+
+    let mut result = vec![(match_key, compute_hash(exe, &normalized_cmdline))];
+
+    if let Some(build_id) = build_id {
+        result.push((TraceMatchKey::BuildId, compute_build_id_hash(build_id)));
+    }
+
+    result.push((TraceMatchKey::ExeOnly, compute_hash(exe, "")));
+
+    result
+}
+
+/// Identifies a specific version of a file, used to detect
+/// files that have been replaced or modified after tracing
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub struct FileIdentity {
+    /// The device the file resides on
+    pub dev: u64,
+    /// The inode number of the file
+    pub ino: u64,
+    /// The time of the last modification of the file
+    pub mtime: i64,
+    /// The size of the file in bytes
+    pub size: u64,
+}
+
+impl FileIdentity {
+    /// Returns the current identity of the file `filename`,
+    /// or `None` if the file is not accessible
+    pub fn from_file(filename: &Path) -> Option<FileIdentity> {
+        let metadata = std::fs::metadata(filename).ok()?;
+
+        Some(FileIdentity {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            mtime: metadata.mtime(),
+            size: metadata.size(),
+        })
+    }
+}
+
+/// The mode in which a file has been accessed
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum AccessMode {
+    /// The file has only been read from
+    Read,
+    /// The file has (also) been opened for writing
+    Write,
+}
+
+/// The source an I/O trace log entry has been recorded from
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum EntrySource {
+    /// The file access has been reported by the tracer backend
+    Traced,
+    /// The file has been found in the memory mappings of the traced process(es)
+    Mapped,
+}
+
+/// Specifies which entries are kept, when an I/O trace log exceeds the size limits
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum TruncationPolicy {
+    /// Keep the files that have been accessed first
+    Earliest,
+    /// Keep the largest files
+    Largest,
+    /// Keep the files that have been accessed during most runs of the program
+    Recurring,
 }
 
 /// An entry in an I/O trace log
@@ -51,14 +315,79 @@ pub struct TraceLogEntry {
     pub operation: IOOperation,
     /// The size of the I/O operation e.g.: amount of bytes read
     pub size: u64,
+    /// The identity of the referenced file at the time of tracing
+    /// (not available in I/O trace logs of older versions)
+    pub identity: Option<FileIdentity>,
+    /// Command name of the descendant process that performed the I/O
+    /// operation, or `None` if it has been performed by the traced process itself
+    pub origin: Option<String>,
+    /// The mode in which the referenced file has been accessed during the trace
+    pub access: AccessMode,
+    /// The source the entry has been recorded from
+    pub source: EntrySource,
 }
 
 impl TraceLogEntry {
     pub fn new(operation: IOOperation, size: u64) -> TraceLogEntry {
+        let identity = FileIdentity::from_file(operation.get_filename());
+
         TraceLogEntry {
             timestamp: Utc::now(),
             operation,
             size,
+            identity,
+            origin: None,
+            access: AccessMode::Read,
+            source: EntrySource::Traced,
+        }
+    }
+
+    /// Returns `true` if the referenced file has been replaced or modified since
+    /// it has been traced. Entries without a recorded identity never count as changed
+    pub fn has_changed(&self) -> bool {
+        match self.identity {
+            None => false,
+            Some(identity) => match FileIdentity::from_file(self.operation.get_filename()) {
+                None => false,
+                Some(current) => current != identity,
+            },
+        }
+    }
+
+    /// Update the entry to match the current version of the referenced file.
+    /// Recorded page ranges are discarded, since they refer to the old version
+    pub fn refresh(&mut self) {
+        let filename = self.operation.get_filename().to_path_buf();
+
+        match self.operation {
+            IOOperation::Open(_) | IOOperation::OpenRanges(_, _) => {
+                self.operation = IOOperation::Open(filename.clone());
+                self.size = util::get_file_size(&filename).unwrap_or(0);
+            }
+
+            IOOperation::Exec(_) => {
+                self.size = util::get_file_size(&filename).unwrap_or(0);
+            }
+
+            IOOperation::OpenDir(_) => { /* Do nothing */ }
+        }
+
+        self.identity = FileIdentity::from_file(&filename);
+    }
+
+    /// Bind the entry to the local version of the referenced file, e.g. after the
+    /// trace log has been imported from another system. Device and inode numbers
+    /// differ between systems, so recorded page ranges are kept as long as the
+    /// size of the file matches, otherwise the entry gets refreshed
+    pub fn rebind(&mut self) {
+        let current = FileIdentity::from_file(self.operation.get_filename());
+
+        match (self.identity, current) {
+            (Some(identity), Some(current)) if identity.size == current.size => {
+                self.identity = Some(current);
+            }
+
+            _ => self.refresh(),
         }
     }
 }
@@ -78,6 +407,10 @@ pub enum IOTraceLogEntryFlag {
     OK,
     /// The destination file is missing
     MissingFile,
+    /// The destination file has been replaced or modified since tracing
+    Changed,
+    /// The entry has been merged from the memory mappings of the traced process(es)
+    Mapped,
 }
 
 pub fn map_io_trace_log_entry_flag_to_string(flag: IOTraceLogEntryFlag) -> String {
@@ -87,6 +420,8 @@ pub fn map_io_trace_log_entry_flag_to_string(flag: IOTraceLogEntryFlag) -> Strin
         IOTraceLogEntryFlag::Invalid => tr!("invalid").to_owned(),
         IOTraceLogEntryFlag::OK => tr!("ok").to_owned(),
         IOTraceLogEntryFlag::MissingFile => tr!("missing-file").to_owned(),
+        IOTraceLogEntryFlag::Changed => tr!("changed").to_owned(),
+        IOTraceLogEntryFlag::Mapped => tr!("mapped").to_owned(),
     }
 }
 
@@ -113,6 +448,16 @@ pub enum IOTraceLogFlag {
 
     /// The binary is missing
     MissingBinary,
+    /// The build-id of the binary differs from the one recorded at the time of tracing
+    BuildIdMismatch,
+
+    /// The I/O trace log file has been written in an older on-disk format
+    OldFormat,
+    /// The I/O trace log file is damaged and can not be used
+    Corrupt,
+
+    /// The I/O trace log has been truncated, because it exceeded the size limits
+    Truncated,
 }
 
 pub fn map_io_trace_flag_to_string(flag: IOTraceLogFlag) -> String {
@@ -125,6 +470,10 @@ pub fn map_io_trace_flag_to_string(flag: IOTraceLogFlag) -> String {
         IOTraceLogFlag::Current => tr!("current").to_string(),
         IOTraceLogFlag::Outdated => tr!("binary-newer").to_string(),
         IOTraceLogFlag::MissingBinary => tr!("missing-binary").to_string(),
+        IOTraceLogFlag::BuildIdMismatch => tr!("build-id-mismatch").to_string(),
+        IOTraceLogFlag::OldFormat => tr!("old-format").to_string(),
+        IOTraceLogFlag::Corrupt => tr!("corrupt").to_string(),
+        IOTraceLogFlag::Truncated => tr!("truncated").to_string(),
     }
 }
 
@@ -141,8 +490,16 @@ fn false_value() -> bool {
 /// Represents an I/O trace log `.trace` file
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IOTraceLog {
+    /// Version of the on-disk format, see `IOTRACE_FORMAT_VERSION`
+    pub format_version: u32,
+    /// Version of the on-disk format the trace log has been loaded from,
+    /// before it got migrated to the current format (not serialized)
+    #[serde(skip)]
+    pub source_format_version: u32,
     /// Hash of the `comm` of the process being traced
     pub hash: String,
+    /// The kind of key the hash has been calculated from
+    pub match_key: TraceMatchKey,
     /// Name of executable file of the process being traced
     pub exe: PathBuf,
     /// Command name of the process being traced
@@ -153,6 +510,10 @@ pub struct IOTraceLog {
     pub created_at: DateTime<Utc>,
     /// Date and Time (in UTC) this trace log was stopped
     pub trace_stopped_at: DateTime<Utc>,
+    /// The reason why the tracing has been stopped
+    pub stop_reason: TraceStopReason,
+    /// The GNU build-id of the executable at the time of tracing, if it carries one
+    pub build_id: Option<String>,
     /// Map file names to file descriptors used in trace log
     pub file_map: HashMap<PathBuf, usize>,
     /// The I/O trace log, contains all relevant I/O operations
@@ -160,15 +521,26 @@ pub struct IOTraceLog {
     pub trace_log: Vec<TraceLogEntry>,
     /// The total amount of data in bytes that the I/O trace log references
     pub accumulated_size: u64,
+    /// The number of runs of the program that have been merged into this trace log
+    pub num_runs: usize,
+    /// Maps file names to the number of runs in which they have been accessed
+    pub run_hits: HashMap<PathBuf, usize>,
     /// Specifies whether the trace log has been optimized already
     pub trace_log_optimized: bool,
     /// Specifies whether the trace log has been blacklisted
     #[serde(default = "false_value")]
     pub blacklisted: bool,
+    /// Specifies whether entries have been dropped, because the trace log exceeded the size limits
+    pub truncated: bool,
+    /// Specifies whether the trace log stands in for a damaged I/O trace log file (not serialized)
+    #[serde(skip)]
+    pub corrupt: bool,
 }
 
 impl IOTraceLog {
-    pub fn new(pid: libc::pid_t) -> Result<IOTraceLog, &'static str> {
+    /// Create a new I/O trace log for the process `pid`. The trace log will be stored by its
+    /// command line, normalized using the command line normalization rules `rules`
+    pub fn new(pid: libc::pid_t, rules: &[Regex]) -> Result<IOTraceLog, &'static str> {
         let process = Process::new(pid);
         if process.is_ok() {
             let process = process.unwrap();
@@ -177,7 +549,11 @@ impl IOTraceLog {
             if let Some(mountinfo) = process.mountinfo.as_ref() {
                 // find the canonical path of the exe, e.g. if the process runs
                 // in a different mount namespace the paths will be differing
-                if let Some(canonical_path) = util::find_source_path(&mountinfo, &PathBuf::from(process.get_exe()?)) {
+                let host_mounts = util::parse_own_mountinfo().unwrap_or_default();
+
+                if let Some(canonical_path) =
+                    util::canonicalize_path(pid, &mountinfo, &host_mounts, &PathBuf::from(process.get_exe()?))
+                {
                     exe = canonical_path;
                 } else {
                     exe = process.get_exe()?;
@@ -189,39 +565,90 @@ impl IOTraceLog {
             let comm = process.get_comm()?;
             let cmdline = process.get_cmdline()?;
 
-            let mut hasher = fnv::FnvHasher::default();
-            hasher.write(&exe.to_string_lossy().into_owned().into_bytes());
-            hasher.write(&cmdline.clone().into_bytes());
-            let hashval = hasher.finish();
+            // the executable is always accessible through procfs, even from a different mount namespace
+            let build_id = util::read_build_id(&Path::new("/proc").join(format!("{}", pid)).join("exe"));
+
+            let normalized_cmdline = normalize_cmdline(&cmdline, rules);
+            let hashval = compute_hash(&exe, &normalized_cmdline);
+
+            let match_key = if normalized_cmdline == cmdline {
+                TraceMatchKey::Exact
+            } else {
+                TraceMatchKey::Normalized
+            };
 
             // make the I/O trace contain an open and a read of the binary itself
             // since we will always miss that event in the tracer
             let mut initial_file_map = HashMap::new();
             initial_file_map.insert(exe.clone(), 1);
 
+            let mut initial_run_hits = HashMap::new();
+            initial_run_hits.insert(exe.clone(), 1);
+
             let initial_trace_log = vec![
                 TraceLogEntry::new(IOOperation::Open(exe.clone()), util::get_file_size(&exe).unwrap_or(0)),
                 // TraceLogEntry::new(IOOperation::Read(0)),
             ];
 
             Ok(IOTraceLog {
-                hash: String::from(format!("{}", hashval)),
+                format_version: IOTRACE_FORMAT_VERSION,
+                source_format_version: IOTRACE_FORMAT_VERSION,
+                hash: hashval,
+                match_key,
                 exe: exe.clone(),
                 comm,
                 cmdline,
                 created_at: Utc::now(),
                 trace_stopped_at: Utc::now(),
+                stop_reason: TraceStopReason::Unknown,
+                build_id,
                 file_map: initial_file_map,
                 trace_log: initial_trace_log,
                 accumulated_size: util::get_file_size(&exe).unwrap_or(0),
+                num_runs: 1,
+                run_hits: initial_run_hits,
                 trace_log_optimized: false,
                 blacklisted: false,
+                truncated: false,
+                corrupt: false,
             })
         } else {
             Err("Process does not exist!")
         }
     }
 
+    /// Create an empty placeholder for the damaged I/O trace log file `filename`,
+    /// so that it can be listed and selected alongside the valid I/O trace logs
+    pub fn new_corrupt(filename: &Path) -> IOTraceLog {
+        let hashval = filename
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        IOTraceLog {
+            format_version: IOTRACE_FORMAT_VERSION,
+            source_format_version: IOTRACE_FORMAT_VERSION,
+            hash: hashval,
+            match_key: TraceMatchKey::Normalized,
+            exe: filename.to_path_buf(),
+            comm: String::new(),
+            cmdline: String::new(),
+            created_at: Utc::now(),
+            trace_stopped_at: Utc::now(),
+            stop_reason: TraceStopReason::Unknown,
+            build_id: None,
+            file_map: HashMap::new(),
+            trace_log: vec![],
+            accumulated_size: 0,
+            num_runs: 0,
+            run_hits: HashMap::new(),
+            trace_log_optimized: false,
+            blacklisted: false,
+            truncated: false,
+            corrupt: true,
+        }
+    }
+
     /// De-serialize from a file
     pub fn from_file(filename: &Path) -> io::Result<IOTraceLog> {
         Self::deserialize(filename)
@@ -230,40 +657,83 @@ impl IOTraceLog {
     /// De-serialization helper function
     /// Inflate the file `filename` (that was previously compressed
     /// with the "Zstd" compressor), convert it into an Unicode UTF-8
-    /// JSON representation, and de-serialize an `IOTraceLog` from
-    /// that JSON representation.
+    /// JSON representation, upgrade that JSON representation to the
+    /// current on-disk format, and de-serialize an `IOTraceLog` from it.
+    /// Only damaged file contents are reported as `CorruptTraceLog`,
+    /// errors while reading the file are passed through unchanged
     fn deserialize(filename: &Path) -> io::Result<IOTraceLog> {
-        let text = util::read_compressed_text_file(filename)?;
+        let compressed = fs::read(filename)?;
+
+        let decompressed = zstd::decode_all(compressed.as_slice()).map_err(|e| corrupt_error(format!("{}", e)))?;
+        let text = String::from_utf8(decompressed).map_err(|e| corrupt_error(format!("{}", e)))?;
 
         let reader = BufReader::new(text.as_bytes());
-        let deserialized = serde_json::from_reader::<_, IOTraceLog>(reader)?;
+        let value = serde_json::from_reader::<_, serde_json::Value>(reader).map_err(|e| corrupt_error(format!("{}", e)))?;
+
+        Self::from_value(value)
+    }
+
+    /// De-serialize from the JSON representation `value`, which may
+    /// have been written in an older on-disk format
+    pub fn from_value(mut value: serde_json::Value) -> io::Result<IOTraceLog> {
+        let source_format_version = get_format_version(&value);
+
+        if source_format_version >= IOTRACE_CHECKSUM_FORMAT_VERSION
+            && source_format_version <= IOTRACE_FORMAT_VERSION
+            && !verify_checksum(&mut value)?
+        {
+            return Err(corrupt_error(String::from("Checksum mismatch")));
+        }
+        migrate_io_trace_log(&mut value, source_format_version)?;
+
+        let mut deserialized = serde_json::from_value::<IOTraceLog>(value)?;
+        deserialized.source_format_version = source_format_version;
 
         Ok(deserialized)
     }
 
-    /// Write the I/O trace log to disk
-    pub fn save(&self, filename: &Path, min_len: usize, min_prefetch_size: u64, allow_truncate: bool) -> io::Result<()> {
+    /// Returns `true` if the trace log has been loaded from a file
+    /// that was written in an older on-disk format
+    pub fn is_format_outdated(&self) -> bool {
+        self.source_format_version < IOTRACE_FORMAT_VERSION
+    }
+
+    /// Serialize to the JSON representation of the current on-disk format, including a checksum
+    pub fn to_value(&self) -> io::Result<serde_json::Value> {
+        let mut value = serde_json::to_value(&self)?;
+        embed_checksum(&mut value)?;
+
+        Ok(value)
+    }
+
+    /// Write the I/O trace log to disk. Returns `false` if nothing has been written,
+    /// because the trace log does not meet the minimum length or size criteria
+    pub fn save(&self, filename: &Path, min_len: usize, min_prefetch_size: u64, allow_truncate: bool) -> io::Result<bool> {
         if (self.trace_log.len() >= min_len && self.accumulated_size >= min_prefetch_size) || allow_truncate {
-            let serialized = serde_json::to_string_pretty(&self)?;
+            let serialized = serde_json::to_string_pretty(&self.to_value()?)?;
             util::write_text_file(filename, &serialized)?;
 
-            Ok(())
+            Ok(true)
         } else {
             info!(
                 "The I/O trace log for process '{}' does not meet the minimum length or size criteria! Nothing will be saved.",
                 self.comm
             );
 
-            Ok(())
+            Ok(false)
         }
     }
 
-    /// Add an I/O operation to the trace log
+    /// Add an I/O operation to the trace log, performed by the descendant process `origin`,
+    /// or by the traced process itself, if `origin` is `None`.
     /// Perform necessary mapping of file descriptors to file name
-    pub fn add_event(&mut self, op: IOOperation) {
+    pub fn add_event(&mut self, op: IOOperation, origin: Option<String>) {
         let operation = op.clone();
         let mut size = 0;
 
+        // count every file only once per run
+        self.run_hits.entry(op.get_filename().to_path_buf()).or_insert(1);
+
         // do we have to add a file map entry?
         match op {
             IOOperation::Open(filename) => {
@@ -274,12 +744,774 @@ impl IOTraceLog {
                 let val = self.file_map.entry(filename).or_insert(0);
                 *val += 1;
             }
-            _ => { /* Do nothing */ }
+
+            IOOperation::OpenRanges(filename, ranges) => {
+                size = get_ranges_size(&ranges);
+
+                let val = self.file_map.entry(filename).or_insert(0);
+                *val += 1;
+            }
+
+            IOOperation::Exec(filename) => {
+                size = util::get_file_size(&filename).unwrap_or(0);
+
+                let val = self.file_map.entry(filename).or_insert(0);
+                *val += 1;
+            }
+
+            IOOperation::OpenDir(_) => {
+                // metadata operations do not reference any file contents
+            }
         }
 
         // append log entry to our log
-        let entry = TraceLogEntry::new(operation, size);
+        let mut entry = TraceLogEntry::new(operation, size);
+        entry.origin = origin;
+
         self.trace_log.push(entry);
         self.accumulated_size += size;
     }
+
+    /// Add the memory mapped files `files` of the traced process(es), that are not part of the
+    /// trace log yet, e.g. shared objects mapped by the dynamic loader. They are inserted right
+    /// after the executable, since they are required early on. Returns the number of added files
+    pub fn add_mapped_files(&mut self, files: &[PathBuf]) -> usize {
+        let mut entries = vec![];
+
+        for filename in files.iter() {
+            if self.file_map.contains_key(filename) {
+                continue;
+            }
+
+            let size = util::get_file_size(filename).unwrap_or(0);
+
+            let mut entry = TraceLogEntry::new(IOOperation::Open(filename.clone()), size);
+            entry.timestamp = self.created_at;
+            entry.source = EntrySource::Mapped;
+
+            self.file_map.insert(filename.clone(), 1);
+            self.run_hits.entry(filename.clone()).or_insert(1);
+            self.accumulated_size += size;
+
+            entries.push(entry);
+        }
+
+        let count = entries.len();
+        let index = std::cmp::min(1, self.trace_log.len());
+
+        self.trace_log.splice(index..index, entries);
+
+        count
+    }
+
+    /// Record that the file `filename` has been opened for writing, e.g. when a
+    /// `FAN_CLOSE_WRITE` event has been received. Flags all entries referencing it
+    pub fn add_write_access(&mut self, filename: &Path) {
+        for entry in self.trace_log.iter_mut().filter(|e| e.operation.get_filename() == filename) {
+            entry.access = AccessMode::Write;
+        }
+    }
+
+    /// Remove all entries referencing files that have been opened for writing, like lock
+    /// files, journals or caches. Returns the number of removed entries
+    pub fn remove_written_files(&mut self) -> usize {
+        let written_files: HashSet<PathBuf> = self
+            .trace_log
+            .iter()
+            .filter(|e| e.access == AccessMode::Write)
+            .map(|e| e.operation.get_filename().to_path_buf())
+            .collect();
+
+        let count = self.trace_log.len();
+
+        self.trace_log.retain(|e| !written_files.contains(e.operation.get_filename()));
+
+        for filename in written_files.iter() {
+            self.file_map.remove(filename);
+            self.run_hits.remove(filename);
+        }
+
+        self.accumulated_size = self.trace_log.iter().map(|e| e.size).sum();
+
+        count - self.trace_log.len()
+    }
+
+    /// Truncate the trace log to at most `max_entries` entries, referencing at most `max_size`
+    /// bytes of data. The entries to keep are chosen according to `policy`, the executable is
+    /// always kept. Returns the number of removed entries
+    pub fn truncate(&mut self, max_entries: usize, max_size: u64, policy: TruncationPolicy) -> usize {
+        if self.trace_log.is_empty() || (self.trace_log.len() <= max_entries && self.accumulated_size <= max_size) {
+            return 0;
+        }
+
+        // rank the entries by the order in which they should be kept
+        let mut ranking: Vec<usize> = (1..self.trace_log.len()).collect();
+
+        match policy {
+            TruncationPolicy::Earliest => {}
+            TruncationPolicy::Largest => ranking.sort_by(|a, b| self.trace_log[*b].size.cmp(&self.trace_log[*a].size)),
+            TruncationPolicy::Recurring => ranking.sort_by_key(|i| {
+                let filename = self.trace_log[*i].operation.get_filename();
+                std::cmp::Reverse(self.run_hits.get(filename).cloned().unwrap_or(0))
+            }),
+        }
+
+        // the first entry always references the executable
+        ranking.insert(0, 0);
+
+        let mut keep = vec![false; self.trace_log.len()];
+        let mut count = 0;
+        let mut size = 0;
+
+        for index in ranking {
+            if count >= max_entries {
+                break;
+            }
+
+            let entry_size = self.trace_log[index].size;
+
+            // skip files that would exceed the size limit, smaller ones may still fit
+            if index > 0 && size + entry_size > max_size {
+                continue;
+            }
+
+            keep[index] = true;
+            count += 1;
+            size += entry_size;
+        }
+
+        let len = self.trace_log.len();
+
+        let mut index = 0;
+        self.trace_log.retain(|_e| {
+            index += 1;
+            keep[index - 1]
+        });
+
+        let files: HashSet<PathBuf> = self
+            .trace_log
+            .iter()
+            .map(|e| e.operation.get_filename().to_path_buf())
+            .collect();
+
+        self.file_map.retain(|k, _v| files.contains(k));
+        self.run_hits.retain(|k, _v| files.contains(k));
+
+        self.accumulated_size = self.trace_log.iter().map(|e| e.size).sum();
+        self.truncated = true;
+
+        len - self.trace_log.len()
+    }
+
+    /// Returns a copy of this trace log, stored by the executable only. Used as
+    /// a fallback for invocations of the program with unknown command lines
+    pub fn to_exe_only(&self) -> IOTraceLog {
+        let mut result = self.clone();
+
+        result.hash = compute_hash(&self.exe, "");
+        result.match_key = TraceMatchKey::ExeOnly;
+
+        result
+    }
+
+    /// Returns a copy of this trace log, stored by the build-id of the executable only, or `None`
+    /// if the executable carries no build-id. Used for identical copies of the executable
+    pub fn to_build_id_only(&self) -> Option<IOTraceLog> {
+        let build_id = self.build_id.as_ref()?;
+
+        let mut result = self.clone();
+
+        result.hash = compute_build_id_hash(build_id);
+        result.match_key = TraceMatchKey::BuildId;
+
+        Some(result)
+    }
+
+    /// Recompute the hash value the trace log is stored by, e.g. after its
+    /// executable or command line have been changed. `rules` are the command
+    /// line normalization rules, used for trace logs with a `Normalized` key
+    pub fn rehash(&mut self, rules: &[Regex]) {
+        self.hash = match self.match_key {
+            TraceMatchKey::Exact => compute_hash(&self.exe, &self.cmdline),
+            TraceMatchKey::Normalized => compute_hash(&self.exe, &normalize_cmdline(&self.cmdline, rules)),
+            TraceMatchKey::BuildId => match self.build_id {
+                Some(ref build_id) => compute_build_id_hash(build_id),
+                None => compute_hash(&self.exe, ""),
+            },
+            TraceMatchKey::ExeOnly => compute_hash(&self.exe, ""),
+        };
+    }
+
+    /// Rewrite all paths referenced by the trace log using the path-prefix
+    /// remapping rules `rules`, and recompute the hash value afterwards
+    pub fn remap_paths(&mut self, rules: &[(PathBuf, PathBuf)], normalization_rules: &[Regex]) {
+        self.exe = remap_path(&self.exe, rules);
+
+        self.cmdline = self
+            .cmdline
+            .split('\0')
+            .map(|arg| {
+                if arg.starts_with('/') {
+                    remap_path(Path::new(arg), rules).to_string_lossy().into_owned()
+                } else {
+                    String::from(arg)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\0");
+
+        let mut file_map = HashMap::new();
+        for (filename, count) in self.file_map.drain() {
+            let val = file_map.entry(remap_path(&filename, rules)).or_insert(0);
+            *val += count;
+        }
+        self.file_map = file_map;
+
+        let mut run_hits = HashMap::new();
+        for (filename, hits) in self.run_hits.drain() {
+            let val = run_hits.entry(remap_path(&filename, rules)).or_insert(0);
+            *val = std::cmp::max(*val, hits);
+        }
+        self.run_hits = run_hits;
+
+        for entry in self.trace_log.iter_mut() {
+            entry.operation = entry.operation.remap(rules);
+        }
+
+        self.rehash(normalization_rules);
+    }
+
+    /// Move the trace log to the point in time `created_at`,
+    /// keeping the time offsets of all entries
+    pub fn rebase_timestamps(&mut self, created_at: DateTime<Utc>) {
+        let offset = created_at - self.created_at;
+
+        for entry in self.trace_log.iter_mut() {
+            entry.timestamp = entry.timestamp + offset;
+        }
+
+        self.trace_stopped_at = self.trace_stopped_at + offset;
+        self.created_at = created_at;
+    }
+
+    /// Returns the confidence of the file `filename`, that is the ratio
+    /// of merged runs of the program in which the file has been accessed
+    pub fn get_confidence(&self, filename: &Path) -> f32 {
+        if self.num_runs < 1 {
+            return 0.0;
+        }
+
+        let hits = self.run_hits.get(filename).cloned().unwrap_or(0);
+
+        hits as f32 / self.num_runs as f32
+    }
+
+    /// Merge the I/O trace log `previous`, recorded during earlier runs of the
+    /// same program, into this trace log. The entries of the current run take
+    /// precedence, entries of files that have only been accessed during earlier
+    /// runs are appended. Hit counts of older runs decay once more than
+    /// `max_runs` runs have been merged
+    pub fn merge(&mut self, previous: &IOTraceLog, max_runs: usize) {
+        let current_files: HashSet<PathBuf> = self
+            .trace_log
+            .iter()
+            .map(|e| e.operation.get_filename().to_path_buf())
+            .collect();
+
+        for entry in previous.trace_log.iter() {
+            let filename = entry.operation.get_filename();
+
+            if !current_files.contains(filename) {
+                if let Some(count) = previous.file_map.get(filename) {
+                    self.file_map.insert(filename.to_path_buf(), *count);
+                }
+
+                // keep the time offset relative to the start of the trace
+                let mut entry = entry.clone();
+                entry.timestamp = self.created_at + (entry.timestamp - previous.created_at);
+
+                self.accumulated_size += entry.size;
+                self.trace_log.push(entry);
+            }
+        }
+
+        for (filename, hits) in previous.run_hits.iter() {
+            let val = self.run_hits.entry(filename.clone()).or_insert(0);
+            *val += hits;
+        }
+
+        self.num_runs += previous.num_runs;
+
+        if self.num_runs > max_runs {
+            let num_runs = self.num_runs;
+
+            // scale down hit counts, rounding up so that no file drops to zero hits
+            for hits in self.run_hits.values_mut() {
+                *hits = (*hits * max_runs + num_runs - 1) / num_runs;
+            }
+
+            self.num_runs = max_runs;
+        }
+
+        self.trace_log_optimized = false;
+    }
+
+    /// Narrow down the `Open` operations of the trace log to the page ranges
+    /// contained in `resident_ranges`, usually a snapshot of the pages that were
+    /// resident in the page cache at the end of the trace. Files not contained
+    /// in `resident_ranges`, or without any resident pages (e.g. empty files),
+    /// will still be prefetched as a whole
+    pub fn set_resident_ranges(&mut self, resident_ranges: &HashMap<PathBuf, Vec<(u64, u64)>>) {
+        let mut accumulated_size = 0;
+
+        for entry in self.trace_log.iter_mut() {
+            let mut operation = None;
+
+            if let IOOperation::Open(ref filename) = entry.operation {
+                if let Some(ranges) = resident_ranges.get(filename).filter(|r| !r.is_empty()) {
+                    operation = Some(IOOperation::OpenRanges(filename.clone(), ranges.clone()));
+                    entry.size = get_ranges_size(ranges);
+                }
+            }
+
+            if let Some(operation) = operation {
+                entry.operation = operation;
+            }
+
+            accumulated_size += entry.size;
+        }
+
+        self.accumulated_size = accumulated_size;
+    }
+}
+
+/// Returns the on-disk format version of the JSON representation of an I/O trace log
+pub fn get_format_version(value: &serde_json::Value) -> u32 {
+    value
+        .get("format_version")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32)
+        .unwrap_or(IOTRACE_LEGACY_FORMAT_VERSION)
+}
+
+/// Returns the checksum of the JSON representation `value` of an I/O trace log
+fn compute_checksum(value: &serde_json::Value) -> io::Result<String> {
+    let text = serde_json::to_string(value)?;
+
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(text.as_bytes());
+
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Embed a checksum, calculated over all other fields, into
+/// the JSON representation `value` of an I/O trace log
+pub fn embed_checksum(value: &mut serde_json::Value) -> io::Result<()> {
+    let checksum = compute_checksum(value)?;
+
+    value
+        .as_object_mut()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed I/O trace log"))?
+        .insert(String::from("checksum"), serde_json::Value::from(checksum));
+
+    Ok(())
+}
+
+/// Remove the embedded checksum from the JSON representation `value` of an I/O trace log,
+/// and verify it. Returns `false` if the checksum is missing or does not match
+pub fn verify_checksum(value: &mut serde_json::Value) -> io::Result<bool> {
+    let checksum = value
+        .as_object_mut()
+        .ok_or_else(|| corrupt_error(String::from("Malformed I/O trace log")))?
+        .remove("checksum");
+
+    match checksum {
+        Some(serde_json::Value::String(checksum)) => Ok(checksum == compute_checksum(value)?),
+        _ => Ok(false),
+    }
+}
+
+/// Upgrade the JSON representation of an I/O trace log from the on-disk format
+/// version `from_version` to `IOTRACE_FORMAT_VERSION`, one version at a time.
+/// Fails if the trace log has been written by a newer version of precached
+pub fn migrate_io_trace_log(value: &mut serde_json::Value, from_version: u32) -> io::Result<()> {
+    if from_version > IOTRACE_FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unsupported I/O trace log format version {} (newest supported: {})",
+                from_version, IOTRACE_FORMAT_VERSION
+            ),
+        ));
+    }
+
+    let mut version = from_version;
+
+    while version < IOTRACE_FORMAT_VERSION {
+        let obj = value
+            .as_object_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed I/O trace log"))?;
+
+        match version {
+            1 => migrate_v1_to_v2(obj),
+            2 => migrate_v2_to_v3(obj),
+            3 => migrate_v3_to_v4(obj),
+            4 => migrate_v4_to_v5(obj),
+            5 => migrate_v5_to_v6(obj),
+            6 => migrate_v6_to_v7(obj),
+            7 => migrate_v7_to_v8(obj),
+            8 => migrate_v8_to_v9(obj),
+            9 => migrate_v9_to_v10(obj),
+            10 => migrate_v10_to_v11(obj),
+            11 => migrate_v11_to_v12(obj),
+            12 => migrate_v12_to_v13(obj),
+            13 => migrate_v13_to_v14(obj),
+            14 => migrate_v14_to_v15(obj),
+
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("No migration available for I/O trace log format version {}", version),
+                ))
+            }
+        }
+
+        version += 1;
+        obj.insert(String::from("format_version"), serde_json::Value::from(version));
+    }
+
+    Ok(())
+}
+
+/// Version 1 -> 2: Introduced the `format_version` field; very old
+/// trace logs may additionally lack the `blacklisted` field
+fn migrate_v1_to_v2(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    obj.entry("blacklisted").or_insert(serde_json::Value::Bool(false));
+}
+
+/// Version 2 -> 3: Introduced the `OpenRanges` I/O operation. Existing
+/// `Open` operations remain valid, so there is nothing to convert
+fn migrate_v2_to_v3(_obj: &mut serde_json::Map<String, serde_json::Value>) {}
+
+/// Version 3 -> 4: Introduced the `Stat`, `OpenDir` and `Exec` I/O operations.
+/// Existing operations remain valid, so there is nothing to convert
+fn migrate_v3_to_v4(_obj: &mut serde_json::Map<String, serde_json::Value>) {}
+
+/// Version 4 -> 5: Introduced merging of multiple runs. Existing trace logs
+/// represent a single run, in which every referenced file has been accessed
+fn migrate_v4_to_v5(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    let mut run_hits = serde_json::Map::new();
+
+    if let Some(file_map) = obj.get("file_map").and_then(|v| v.as_object()) {
+        for filename in file_map.keys() {
+            run_hits.insert(filename.clone(), serde_json::Value::from(1));
+        }
+    }
+
+    obj.insert(String::from("num_runs"), serde_json::Value::from(1));
+    obj.insert(String::from("run_hits"), serde_json::Value::Object(run_hits));
+}
+
+/// Version 5 -> 6: Introduced per-entry file identities. The identity of files
+/// referenced by older trace logs is unknown, so leave it empty
+fn migrate_v5_to_v6(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    if let Some(trace_log) = obj.get_mut("trace_log").and_then(|v| v.as_array_mut()) {
+        for entry in trace_log.iter_mut().filter_map(|e| e.as_object_mut()) {
+            entry.entry("identity").or_insert(serde_json::Value::Null);
+        }
+    }
+}
+
+/// Version 6 -> 7: Introduced command line normalization. Older trace
+/// logs have always been stored by the exact command line
+fn migrate_v6_to_v7(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    obj.insert(String::from("match_key"), serde_json::Value::from("Exact"));
+}
+
+/// Version 7 -> 8: Introduced the embedded checksum. It is verified before
+/// migrating, and written on the next save, so there is nothing to convert
+fn migrate_v7_to_v8(_obj: &mut serde_json::Map<String, serde_json::Value>) {}
+
+/// Version 8 -> 9: Introduced adaptive trace durations. Older trace logs
+/// do not record why their tracing has been stopped
+fn migrate_v8_to_v9(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    obj.insert(String::from("stop_reason"), serde_json::Value::from("Unknown"));
+}
+
+/// Version 9 -> 10: Introduced tracing of whole process trees. All entries
+/// of older trace logs have been performed by the traced process itself
+fn migrate_v9_to_v10(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    if let Some(trace_log) = obj.get_mut("trace_log").and_then(|v| v.as_array_mut()) {
+        for entry in trace_log.iter_mut().filter_map(|e| e.as_object_mut()) {
+            entry.entry("origin").or_insert(serde_json::Value::Null);
+        }
+    }
+}
+
+/// Version 10 -> 11: Introduced the access mode of entries. The access mode of files
+/// referenced by older trace logs is unknown, so treat them as having been read
+fn migrate_v10_to_v11(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    if let Some(trace_log) = obj.get_mut("trace_log").and_then(|v| v.as_array_mut()) {
+        for entry in trace_log.iter_mut().filter_map(|e| e.as_object_mut()) {
+            entry.entry("access").or_insert(serde_json::Value::from("Read"));
+        }
+    }
+}
+
+/// Version 11 -> 12: Introduced merging of memory mapped files. All entries
+/// of older trace logs have been reported by the tracer
+fn migrate_v11_to_v12(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    if let Some(trace_log) = obj.get_mut("trace_log").and_then(|v| v.as_array_mut()) {
+        for entry in trace_log.iter_mut().filter_map(|e| e.as_object_mut()) {
+            entry.entry("source").or_insert(serde_json::Value::from("Traced"));
+        }
+    }
+}
+
+/// Version 12 -> 13: Introduced size limits of trace logs
+fn migrate_v12_to_v13(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    obj.entry("truncated").or_insert(serde_json::Value::from(false));
+}
+
+/// Version 13 -> 14: Introduced the build-id of the executable. It can not
+/// be determined for older trace logs, since the binary may have changed
+fn migrate_v13_to_v14(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    obj.entry("build_id").or_insert(serde_json::Value::Null);
+}
+
+/// Version 14 -> 15: Removed the `Stat` I/O operation, since none of the tracer
+/// backends is able to record stat(2) calls. Drop the entries of older trace logs
+fn migrate_v14_to_v15(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    if let Some(trace_log) = obj.get_mut("trace_log").and_then(|v| v.as_array_mut()) {
+        trace_log.retain(|e| e.get("operation").and_then(|op| op.get("Stat")).is_none());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::iotrace::*;
+
+    #[test]
+    fn test_migrate_legacy_io_trace_log() {
+        let mut value = serde_json::json!({ "hash": "42", "trace_log_optimized": false });
+
+        let version = get_format_version(&value);
+        assert_eq!(IOTRACE_LEGACY_FORMAT_VERSION, version);
+
+        migrate_io_trace_log(&mut value, version).unwrap();
+        assert_eq!(IOTRACE_FORMAT_VERSION, get_format_version(&value));
+        assert_eq!(Some(false), value["blacklisted"].as_bool());
+    }
+
+    #[test]
+    fn test_migrate_run_hits() {
+        let mut value = serde_json::json!({ "format_version": 4, "file_map": { "/usr/bin/true": 1 } });
+
+        migrate_io_trace_log(&mut value, get_format_version(&value)).unwrap();
+        assert_eq!(Some(1), value["num_runs"].as_u64());
+        assert_eq!(Some(1), value["run_hits"]["/usr/bin/true"].as_u64());
+    }
+
+    #[test]
+    fn test_migrate_stop_reason() {
+        let mut value = serde_json::json!({ "format_version": 8, "hash": "42" });
+
+        migrate_io_trace_log(&mut value, get_format_version(&value)).unwrap();
+        assert_eq!(Some("Unknown"), value["stop_reason"].as_str());
+    }
+
+    #[test]
+    fn test_migrate_entry_source() {
+        let mut value = serde_json::json!({ "format_version": 11, "trace_log": [{ "size": 0 }] });
+
+        migrate_io_trace_log(&mut value, get_format_version(&value)).unwrap();
+        assert_eq!(Some("Traced"), value["trace_log"][0]["source"].as_str());
+    }
+
+    #[test]
+    fn test_migrate_stat_operations() {
+        let mut value = serde_json::json!({ "format_version": 14, "trace_log": [
+            { "operation": { "Stat": "/dev/null" }, "size": 0 },
+            { "operation": { "Open": "/usr/bin/true" }, "size": 4096 },
+        ] });
+
+        migrate_io_trace_log(&mut value, get_format_version(&value)).unwrap();
+
+        assert_eq!(1, value["trace_log"].as_array().unwrap().len());
+        assert_eq!("/usr/bin/true", value["trace_log"][0]["operation"]["Open"]);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut io_trace: IOTraceLog = serde_json::from_value(serde_json::json!({
+            "format_version": IOTRACE_FORMAT_VERSION, "hash": "42", "match_key": "Exact",
+            "exe": "/usr/bin/app", "comm": "app", "cmdline": "app",
+            "created_at": "2020-01-01T00:00:00Z", "trace_stopped_at": "2020-01-01T00:00:00Z",
+            "stop_reason": "Quiescent", "file_map": {}, "trace_log": [], "accumulated_size": 0,
+            "num_runs": 2, "run_hits": {}, "trace_log_optimized": false, "truncated": false
+        }))
+        .unwrap();
+
+        for (filename, size, hits) in &[("/usr/bin/app", 1, 2), ("/a", 4, 1), ("/b", 2, 2), ("/c", 8, 1)] {
+            io_trace
+                .trace_log
+                .push(TraceLogEntry::new(IOOperation::Open(PathBuf::from(filename)), *size));
+            io_trace.file_map.insert(PathBuf::from(filename), 1);
+            io_trace.run_hits.insert(PathBuf::from(filename), *hits);
+            io_trace.accumulated_size += size;
+        }
+
+        let files = |io_trace: &IOTraceLog| -> Vec<PathBuf> {
+            io_trace
+                .trace_log
+                .iter()
+                .map(|e| e.operation.get_filename().to_path_buf())
+                .collect()
+        };
+
+        assert_eq!(0, io_trace.clone().truncate(4, 15, TruncationPolicy::Earliest));
+
+        let mut earliest = io_trace.clone();
+        assert_eq!(2, earliest.truncate(2, 15, TruncationPolicy::Earliest));
+        assert_eq!(vec![PathBuf::from("/usr/bin/app"), PathBuf::from("/a")], files(&earliest));
+        assert!(earliest.truncated);
+
+        let mut largest = io_trace.clone();
+        assert_eq!(1, largest.truncate(4, 13, TruncationPolicy::Largest));
+        assert_eq!(
+            vec![PathBuf::from("/usr/bin/app"), PathBuf::from("/a"), PathBuf::from("/c")],
+            files(&largest)
+        );
+        assert_eq!(13, largest.accumulated_size);
+
+        let mut recurring = io_trace.clone();
+        assert_eq!(2, recurring.truncate(2, 15, TruncationPolicy::Recurring));
+        assert_eq!(vec![PathBuf::from("/usr/bin/app"), PathBuf::from("/b")], files(&recurring));
+        assert_eq!(2, recurring.file_map.len());
+    }
+
+    #[test]
+    fn test_get_lookup_keys() {
+        let keys = get_lookup_keys(Path::new("/usr/bin/app"), "app", Some("deadbeef"), &[]);
+        let match_keys: Vec<TraceMatchKey> = keys.iter().map(|(k, _h)| *k).collect();
+
+        assert_eq!(
+            vec![TraceMatchKey::Exact, TraceMatchKey::BuildId, TraceMatchKey::ExeOnly],
+            match_keys
+        );
+        assert_eq!(compute_build_id_hash("deadbeef"), keys[1].1);
+
+        assert_eq!(2, get_lookup_keys(Path::new("/usr/bin/app"), "app", None, &[]).len());
+
+        let rules = vec![Regex::new(r"^/home/").unwrap()];
+        let keys = get_lookup_keys(Path::new("/usr/bin/evince"), "evince\0/home/user/foo.pdf", None, &rules);
+
+        assert_eq!(TraceMatchKey::Normalized, keys[0].0);
+        assert_eq!(compute_hash(Path::new("/usr/bin/evince"), "evince"), keys[0].1);
+    }
+
+    #[test]
+    fn test_normalize_cmdline() {
+        let rules = vec![Regex::new(r"^/home/").unwrap(), Regex::new(r"^[0-9]+$").unwrap()];
+
+        assert_eq!("evince", normalize_cmdline("evince\0/home/user/foo.pdf", &rules));
+        assert_eq!(
+            "/home/user/bin/app\0--verbose",
+            normalize_cmdline("/home/user/bin/app\0--verbose\042", &rules)
+        );
+        assert_eq!("evince\0--fullscreen", normalize_cmdline("evince\0--fullscreen", &rules));
+    }
+
+    #[test]
+    fn test_remap_path() {
+        let rules = vec![
+            (PathBuf::from("/opt/app-1.0"), PathBuf::from("/opt/app")),
+            (PathBuf::from("/home/build"), PathBuf::from("/home/user")),
+        ];
+
+        assert_eq!(
+            PathBuf::from("/opt/app/bin/app"),
+            remap_path(Path::new("/opt/app-1.0/bin/app"), &rules)
+        );
+        assert_eq!(PathBuf::from("/opt/app"), remap_path(Path::new("/opt/app-1.0"), &rules));
+        assert_eq!(
+            PathBuf::from("/opt/app-1.01/lib"),
+            remap_path(Path::new("/opt/app-1.01/lib"), &rules)
+        );
+        assert_eq!(
+            PathBuf::from("/home/user/.config"),
+            remap_path(Path::new("/home/build/.config"), &rules)
+        );
+    }
+
+    #[test]
+    fn test_get_ranges_size() {
+        assert_eq!(0, get_ranges_size(&[]));
+        assert_eq!(12288, get_ranges_size(&[(0, 4096), (16384, 8192)]));
+    }
+
+    #[test]
+    fn test_set_resident_ranges() {
+        let mut io_trace = IOTraceLog::new_corrupt(Path::new("42.trace"));
+
+        for (filename, size) in &[("/a", 16384), ("/b", 4096), ("/c", 0)] {
+            io_trace
+                .trace_log
+                .push(TraceLogEntry::new(IOOperation::Open(PathBuf::from(filename)), *size));
+        }
+
+        let mut resident_ranges = HashMap::new();
+        resident_ranges.insert(PathBuf::from("/a"), vec![(0, 4096), (8192, 4096)]);
+        resident_ranges.insert(PathBuf::from("/c"), vec![]);
+
+        io_trace.set_resident_ranges(&resident_ranges);
+
+        match io_trace.trace_log[0].operation {
+            IOOperation::OpenRanges(_, ref ranges) => assert_eq!(&vec![(0, 4096), (8192, 4096)], ranges),
+            _ => panic!("Expected an OpenRanges operation"),
+        }
+
+        match io_trace.trace_log[2].operation {
+            IOOperation::Open(_) => {}
+            _ => panic!("Expected an Open operation"),
+        }
+
+        assert_eq!(8192, io_trace.trace_log[0].size);
+        assert_eq!(8192 + 4096, io_trace.accumulated_size);
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let mut value = serde_json::json!({ "format_version": IOTRACE_FORMAT_VERSION, "hash": "42" });
+
+        embed_checksum(&mut value).unwrap();
+        assert!(verify_checksum(&mut value.clone()).unwrap());
+
+        let mut tampered = value.clone();
+        tampered["hash"] = serde_json::Value::from("43");
+        assert!(!verify_checksum(&mut tampered).unwrap());
+
+        value.as_object_mut().unwrap().remove("checksum");
+        assert!(!verify_checksum(&mut value).unwrap());
+    }
+
+    #[test]
+    fn test_reject_newer_io_trace_log_format() {
+        let mut value = serde_json::json!({ "format_version": IOTRACE_FORMAT_VERSION + 1 });
+
+        assert!(migrate_io_trace_log(&mut value, get_format_version(&value)).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_io_trace_log() {
+        let mut value = serde_json::json!({ "format_version": IOTRACE_FORMAT_VERSION, "hash": "42" });
+        embed_checksum(&mut value).unwrap();
+        value["hash"] = serde_json::Value::from("43");
+
+        assert!(is_corrupt_error(&IOTraceLog::from_value(value).unwrap_err()));
+
+        let io_trace = IOTraceLog::new_corrupt(Path::new("/var/lib/precached/iotrace/42.trace"));
+        assert!(io_trace.corrupt);
+        assert_eq!("42", io_trace.hash);
+    }
 }
@@ -38,7 +38,7 @@ use crate::constants;
 use crate::events;
 use crate::globals::*;
 use crate::hooks::fanotify_logger::ACTIVE_TRACERS;
-use crate::hooks::iotrace_prefetcher::{IOtracePrefetcher, ThreadState};
+use crate::hooks::iotrace_prefetcher::{IOtracePrefetcher, ThreadState, PREFETCH_QUEUE};
 use crate::hooks::process_tracker::ProcessTracker;
 use crate::manager::*;
 use crate::plugins;
@@ -47,6 +47,7 @@ use crate::plugins::introspection::InternalState;
 use crate::plugins::statistics;
 use crate::plugins::statistics::GlobalStatistics;
 use crate::process;
+use crate::util;
 use crate::EXIT_NOW;
 
 /// Represents a process
@@ -70,6 +71,7 @@ pub struct TracerEntry {
 pub struct PrefetchStats {
     pub datetime: DateTime<Utc>,
     pub thread_states: Vec<ThreadState>,
+    pub queue: Vec<util::PrefetchQueueEntry>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -397,6 +399,7 @@ impl IpcServer {
                 let stats = PrefetchStats {
                     datetime: Utc::now(),
                     thread_states: v,
+                    queue: PREFETCH_QUEUE.lock().get_state(),
                 };
 
                 let cmd = IpcMessage::new(IpcCommand::SendPrefetchStatus(stats));
@@ -404,6 +404,7 @@ fn run() -> Result<(), failure::Error> {
 
     let mut last_metrics = Instant::now();
     let mut last_ping = Instant::now();
+    let mut last_finalization = Instant::now();
 
     events::queue_internal_event(EventType::Startup, &mut globals);
     // events::queue_internal_event(EventType::PrimeCaches, &mut globals);
@@ -521,6 +522,14 @@ fn run() -> Result<(), failure::Error> {
             events::queue_internal_event(EventType::Ping, &mut globals);
         }
 
+        // Finalize expired I/O traces every n seconds, even if the
+        // traced processes do not perform any I/O activity anymore
+        if last_finalization.elapsed() > Duration::from_millis(constants::IO_TRACE_FINALIZATION_INTERVAL_MILLIS) {
+            last_finalization = Instant::now();
+
+            events::queue_internal_event(EventType::FinalizeIOTraces, &mut globals);
+        }
+
         // Dispatch procmon events
         match event {
             Some(e) => process_procmon_event(&e, &mut globals, &mut manager)?,
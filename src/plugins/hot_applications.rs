@@ -44,6 +44,7 @@ use crate::globals::*;
 use crate::hooks::iotrace_prefetcher::IOtracePrefetcher;
 use crate::iotrace;
 use crate::manager::*;
+use crate::plugins::iotrace_log_manager::IOtraceLogManager;
 use crate::plugins::metrics::Metrics;
 use crate::plugins::plugin::{Plugin, PluginDescription};
 use crate::plugins::profiles::Profiles;
@@ -85,14 +86,9 @@ impl HotApplications {
         }
     }
 
-    /// Query whether we already do have cached the executable file `exe_name`
-    pub fn is_exe_cached(&self, exe_name: &Path, cmdline: &String) -> bool {
-        let mut hasher = fnv::FnvHasher::default();
-        hasher.write(&(exe_name.to_string_lossy().into_owned().into_bytes()));
-        hasher.write(&cmdline.clone().into_bytes());
-        let hashval = hasher.finish();
-
-        CACHED_APPS.get(&format!("{}", hashval)).is_some()
+    /// Query whether we already do have cached the files of the I/O trace log `hashval`
+    pub fn is_trace_cached(&self, hashval: &str) -> bool {
+        CACHED_APPS.get(hashval).is_some()
     }
 
     /// Returns an ordered Vector of (&hash, &count) tuples in descending order of importance
@@ -148,7 +144,13 @@ impl HotApplications {
                                 let hash_c = (*hash).clone();
 
                                 info!("Prefetching files for hash: '{}'", hash);
-                                iotrace_prefetcher_hook.prefetch_data_by_hash(hash, &globals_c, &manager_c);
+                                iotrace_prefetcher_hook.prefetch_data_by_hash(
+                                    hash,
+                                    util::PrefetchPriority::HotApplication,
+                                    config_file::get_prefetch_strategy(&globals_c, config_file::PrefetchSource::HotApplications),
+                                    &globals_c,
+                                    &manager_c,
+                                );
 
                                 CACHED_APPS
                                     .insert(hash_c)
@@ -267,7 +269,7 @@ impl HotApplications {
     }
 
     /// Increments the execution counter of an application
-    pub fn application_executed(&mut self, pid: libc::pid_t) {
+    pub fn application_executed(&mut self, pid: libc::pid_t, manager: &Manager) {
         match Process::new(pid) {
             Err(e) => debug!(
                 "Process vanished while updating hot applications histogram for pid {}: {}",
@@ -277,12 +279,24 @@ impl HotApplications {
             Ok(process) => {
                 if let Ok(exe) = process.get_exe() {
                     if let Ok(cmdline) = process.get_cmdline() {
-                        let mut hasher = fnv::FnvHasher::default();
-                        hasher.write(&exe.to_string_lossy().into_owned().into_bytes());
-                        hasher.write(&cmdline.clone().into_bytes());
-                        let hashval = hasher.finish();
+                        // use the same hash value the I/O trace log will be stored by
+                        let pm = manager.plugin_manager.read();
+
+                        let hashval = match pm.get_plugin_by_name(&String::from("iotrace_log_manager")) {
+                            None => {
+                                trace!("Plugin not loaded: 'iotrace_log_manager', using the exact command line");
+                                iotrace::compute_hash(&exe, &cmdline)
+                            }
+
+                            Some(p) => {
+                                let p = p.read();
+                                let iotrace_log_manager_plugin = p.as_any().downcast_ref::<IOtraceLogManager>().unwrap();
+
+                                iotrace_log_manager_plugin.get_trace_hash(&exe, &cmdline)
+                            }
+                        };
 
-                        let val = self.app_histogram.entry(format!("{}", hashval)).or_insert(0);
+                        let val = self.app_histogram.entry(hashval).or_insert(0);
                         *val += 1;
                     } else {
                         // May happen for very short-lived processes
@@ -511,7 +525,7 @@ impl Plugin for HotApplications {
 
             events::EventType::TrackedProcessChanged(ref event) => {
                 if event.event_type == procmon::EventType::Exec {
-                    self.application_executed(event.pid);
+                    self.application_executed(event.pid, manager);
                 }
             }
 
@@ -83,6 +83,8 @@ impl IOtraceLogCache {
         let iotrace_dir = iotrace_dir.join(constants::IOTRACE_DIR);
         let abs_path = iotrace_dir.join(&filename);
 
+        let strategy = config_file::get_prefetch_strategy(globals, config_file::PrefetchSource::LogCache);
+
         thread_pool.submit_work(move || {
             if !Self::check_available_memory(&globals_c, &manager_c) {
                 info!("Available memory exhausted, stopping prefetching!");
@@ -92,17 +94,22 @@ impl IOtraceLogCache {
             // mmap and mlock file, if it is not contained in the blacklist
             // and if it was not already mapped by some of the plugins
             if Self::shall_we_map_file(&abs_path) {
-                match util::cache_file(&abs_path, true) {
+                match util::cache_file(&abs_path, strategy) {
                     Err(s) => {
                         error!("Could not cache file {:?}: {}", filename, s);
                     }
                     Ok(r) => {
                         trace!("Successfully cached file {:?}", filename);
-                        MAPPED_FILES.insert(abs_path.to_path_buf(), r);
 
-                        statistics::MAPPED_FILES
-                            .insert(abs_path.to_path_buf())
-                            .unwrap_or_else(|e| trace!("Element already in set: {:?}", e));
+                        // files cached without a mapping are recorded too, so that they will not be cached
+                        // again until they expire; only retained mappings occupy memory, and have to be unmapped
+                        if r.is_mapped() {
+                            statistics::MAPPED_FILES
+                                .insert(abs_path.to_path_buf())
+                                .unwrap_or_else(|e| trace!("Element already in set: {:?}", e));
+                        }
+
+                        MAPPED_FILES.insert(abs_path.to_path_buf(), r);
                     }
                 }
             }
@@ -136,12 +143,16 @@ impl IOtraceLogCache {
                 statistics::MAPPED_FILES.remove(&abs_path);
             }
         }
+
+        MAPPED_FILES.remove(&abs_path);
     }
 
     /// Enumerate all existing I/O trace log files and cache and mlock() them
     pub fn cache_iotrace_log_files(&mut self, globals: &Globals, manager: &Manager) {
         info!("Started caching of I/O trace log files...");
 
+        let strategy = config_file::get_prefetch_strategy(globals, config_file::PrefetchSource::LogCache);
+
         let thread_pool = util::PREFETCH_POOL.lock();
         let globals_c = globals.clone();
         let manager_c = manager.clone();
@@ -158,7 +169,7 @@ impl IOtraceLogCache {
                 // mmap and mlock file, if it is not contained in the blacklist
                 // and if it was not already mapped by some of the plugins
                 if Self::shall_we_map_file(path) {
-                    match util::cache_file(path, true) {
+                    match util::cache_file(path, strategy) {
                         Err(s) => {
                             error!("Could not cache file {:?}: {}", path, s);
 
@@ -167,11 +178,14 @@ impl IOtraceLogCache {
 
                         Ok(r) => {
                             trace!("Successfully cached file {:?}", path);
-                            MAPPED_FILES.insert(path.to_path_buf(), r);
 
-                            statistics::MAPPED_FILES
-                                .insert(path.to_path_buf())
-                                .unwrap_or_else(|e| trace!("Element already in set: {:?}", e));
+                            if r.is_mapped() {
+                                statistics::MAPPED_FILES
+                                    .insert(path.to_path_buf())
+                                    .unwrap_or_else(|e| trace!("Element already in set: {:?}", e));
+                            }
+
+                            MAPPED_FILES.insert(path.to_path_buf(), r);
                         }
                     }
                 }
@@ -193,8 +207,8 @@ impl IOtraceLogCache {
             return false;
         }
 
-        // Have we already mapped this file?
-        if MAPPED_FILES.get(filename).is_some() {
+        // Have we already mapped this file, or recently cached it without a mapping?
+        if MAPPED_FILES.get(filename).map_or(false, |mapping| mapping.1.is_cached()) {
             return false;
         }
 
@@ -25,6 +25,7 @@ use std::io::BufReader;
 use std::io::Result;
 use std::path::{Path, PathBuf};
 use log::{trace, debug, info, warn, error, log, LevelFilter};
+use regex::Regex;
 use crate::constants;
 use crate::events;
 use crate::events::EventType;
@@ -42,7 +43,7 @@ static DESCRIPTION: &str = "Manage I/O activity trace log files";
 /// Register this plugin implementation with the system
 pub fn register_plugin(globals: &mut Globals, manager: &mut Manager) {
     if !config_file::get_disabled_plugins(globals).contains(&String::from(NAME)) {
-        let plugin = Box::new(IOtraceLogManager::new());
+        let plugin = Box::new(IOtraceLogManager::new(globals));
 
         let m = manager.plugin_manager.read();
 
@@ -51,11 +52,43 @@ pub fn register_plugin(globals: &mut Globals, manager: &mut Manager) {
 }
 
 #[derive(Debug, Clone)]
-pub struct IOtraceLogManager {}
+pub struct IOtraceLogManager {
+    /// Compiled command line normalization rules
+    pub normalization_rules: Vec<Regex>,
+}
 
 impl IOtraceLogManager {
-    pub fn new() -> Self {
-        IOtraceLogManager {}
+    pub fn new(globals: &Globals) -> Self {
+        IOtraceLogManager {
+            normalization_rules: Self::compile_normalization_rules(globals),
+        }
+    }
+
+    /// Compile the command line normalization rules specified in the configuration file
+    fn compile_normalization_rules(globals: &Globals) -> Vec<Regex> {
+        let rules = globals
+            .get_config_file()
+            .cmdline_normalization_rules
+            .clone()
+            .unwrap_or_else(|| {
+                constants::CMDLINE_NORMALIZATION_RULES
+                    .iter()
+                    .map(|r| String::from(*r))
+                    .collect()
+            });
+
+        iotrace::compile_normalization_rules(&rules)
+    }
+
+    /// Returns the command line normalization rules
+    pub fn get_normalization_rules(&self) -> &[Regex] {
+        &self.normalization_rules
+    }
+
+    /// Returns the hash value an I/O trace log of the executable `exe_name`
+    /// with the command line `cmdline` will be stored by
+    pub fn get_trace_hash(&self, exe_name: &Path, cmdline: &str) -> String {
+        iotrace::compute_hash(exe_name, &iotrace::normalize_cmdline(cmdline, &self.normalization_rules))
     }
 
     // Returns the most recent I/O trace log for `hashval`.
@@ -76,26 +109,65 @@ impl IOtraceLogManager {
         Ok(result)
     }
 
-    // Returns the most recent I/O trace log for the executable `exe_name`.
-    pub fn get_trace_log(&self, exe_name: &Path, cmdline: String, globals: &Globals) -> Result<iotrace::IOTraceLog> {
-        let mut hasher = fnv::FnvHasher::default();
-        hasher.write(&(exe_name.to_string_lossy().into_owned().into_bytes()));
-        hasher.write(&cmdline.into_bytes());
-        let hashval = hasher.finish();
-
+    // Returns the most recent I/O trace log for the executable `exe_name`, and the kind of key
+    // it has been matched by. Tries the normalized command line first, then the build-id of the
+    // executable, and finally falls back to the executable only. I/O trace logs that have been
+    // recorded for a different build of the executable are skipped.
+    pub fn get_trace_log(
+        &self,
+        exe_name: &Path,
+        cmdline: String,
+        globals: &Globals,
+    ) -> Result<(iotrace::IOTraceLog, iotrace::TraceMatchKey)> {
         let iotrace_dir = globals
             .get_config_file()
             .state_dir
             .clone()
             .unwrap_or_else(|| Path::new(constants::STATE_DIR).to_path_buf());
 
-        let filename = iotrace_dir
-            .join(constants::IOTRACE_DIR)
-            .join(Path::new(&format!("{}.trace", hashval)));
+        let mut result = Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "No I/O trace log available",
+        ));
 
-        let result = iotrace::IOTraceLog::from_file(&filename)?;
+        let build_id = util::read_build_id(exe_name);
 
-        Ok(result)
+        for (match_key, hashval) in iotrace::get_lookup_keys(
+            exe_name,
+            &cmdline,
+            build_id.as_ref().map(|b| b.as_str()),
+            &self.normalization_rules,
+        ) {
+            let filename = iotrace_dir
+                .join(constants::IOTRACE_DIR)
+                .join(Path::new(&format!("{}.trace", hashval)));
+
+            match iotrace::IOTraceLog::from_file(&filename) {
+                Err(e) => {
+                    result = Err(e);
+                }
+
+                Ok(io_trace) => {
+                    // the binary has been rebuilt since it has been traced, so the
+                    // I/O trace log is stale, and the program shall be re-traced
+                    if build_id.is_some() && io_trace.build_id.is_some() && io_trace.build_id != build_id {
+                        debug!("Skipped stale I/O trace log for {:?}: build-id mismatch", exe_name);
+
+                        result = Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "The build-id of the I/O trace log does not match the executable",
+                        ));
+
+                        continue;
+                    }
+
+                    trace!("I/O trace log for {:?} matched by key: {:?}", exe_name, match_key);
+                    return Ok((io_trace, match_key));
+                }
+            }
+        }
+
+        result
     }
 
     pub fn enumerate_all_trace_logs(&self, state_dir: &Path) -> Result<HashMap<PathBuf, iotrace::IOTraceLog>> {
@@ -104,9 +176,23 @@ impl IOtraceLogManager {
         let traces_path = state_dir.join(constants::IOTRACE_DIR);
 
         util::walk_directories(&[traces_path], &mut |path| {
-            let io_trace_log = iotrace::IOTraceLog::from_file(path).unwrap();
+            if util::is_temporary_file(path) {
+                return;
+            }
+
+            match iotrace::IOTraceLog::from_file(path) {
+                Err(e) => {
+                    if iotrace::is_corrupt_error(&e) {
+                        warn!("Skipped corrupt I/O trace file {:?}: {}", path, e);
+                    } else {
+                        error!("Skipped invalid I/O trace file, file not readable: {}", e);
+                    }
+                }
 
-            result.insert(PathBuf::from(path), io_trace_log);
+                Ok(io_trace_log) => {
+                    result.insert(PathBuf::from(path), io_trace_log);
+                }
+            }
         })?;
 
         Ok(result)
@@ -151,10 +237,34 @@ impl IOtraceLogManager {
         let mut errors = 0;
 
         match util::walk_directories(&[traces_path], &mut |path| {
+            // remove leftovers of interrupted writes
+            if util::is_temporary_file(path) {
+                debug!("Removing stale temporary file: {:?}", path);
+
+                util::remove_file(path, false).unwrap_or_else(|_| {
+                    error!("Could not remove a file!");
+                });
+
+                return;
+            }
+
             match iotrace::IOTraceLog::from_file(path) {
                 Err(e) => {
-                    error!("Skipped invalid I/O trace file, file not readable: {}", e);
-                    errors += 1;
+                    if iotrace::is_corrupt_error(&e) {
+                        // a corrupt I/O trace log will never become usable again, so remove
+                        // it and let the program be re-traced. I/O trace logs that are
+                        // unreadable for other reasons, e.g. a newer format, are kept
+                        warn!("Pruning corrupt I/O trace log: {:?}: {}", path, e);
+
+                        util::remove_file(path, false).unwrap_or_else(|_| {
+                            error!("Could not remove a file!");
+                        });
+
+                        pruned += 1;
+                    } else {
+                        error!("Skipped invalid I/O trace file, file not readable: {}", e);
+                        errors += 1;
+                    }
                 }
 
                 Ok(io_trace) => {
@@ -195,8 +305,10 @@ impl IOtraceLogManager {
             }
 
             Ok(mut io_trace) => {
-                // Only optimize if the trace log is not optimized already
-                if !io_trace.trace_log_optimized {
+                // Only optimize if the trace log is not optimized already,
+                // or if it needs to be written back in the current on-disk format,
+                // or if it references files that have changed since tracing
+                if !io_trace.trace_log_optimized || io_trace.is_format_outdated() || util::has_changed_entries(&io_trace) {
                     match util::optimize_io_trace_log(filename, &mut io_trace, min_len, min_prefetch_size, false) {
                         Err(e) => {
                             error!("Could not optimize I/O trace log for {:?}: {}", io_trace.exe, e);
@@ -223,6 +335,10 @@ impl IOtraceLogManager {
         let mut errors = 0;
 
         match util::walk_directories(&[traces_path], &mut |path| {
+            if util::is_temporary_file(path) {
+                return;
+            }
+
             match iotrace::IOTraceLog::from_file(path) {
                 Err(e) => {
                     error!("Skipped invalid I/O trace file, file not readable: {}", e);
@@ -230,8 +346,10 @@ impl IOtraceLogManager {
                 }
 
                 Ok(mut io_trace) => {
-                    // Only optimize if the trace log is not optimized already
-                    if !io_trace.trace_log_optimized {
+                    // Only optimize if the trace log is not optimized already,
+                    // or if it needs to be written back in the current on-disk format,
+                    // or if it references files that have changed since tracing
+                    if !io_trace.trace_log_optimized || io_trace.is_format_outdated() || util::has_changed_entries(&io_trace) {
                         match util::optimize_io_trace_log(path, &mut io_trace, min_len, min_prefetch_size, false) {
                             Err(e) => {
                                 error!("Could not optimize I/O trace log for {:?}: {}", io_trace.exe, e);
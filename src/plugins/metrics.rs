@@ -31,6 +31,7 @@ use crate::globals::*;
 use crate::manager::*;
 use crate::plugins::plugin::Plugin;
 use crate::plugins::plugin::PluginDescription;
+use crate::util;
 
 static NAME: &str = "metrics";
 static DESCRIPTION: &str = "Gather global performance metrics and make them available to other plugins";
@@ -104,13 +105,26 @@ impl Metrics {
         }
     }
 
+    /// Returns the percentage of memory in use. If the daemon is contained in a memory
+    /// limited cgroup, the usage relative to that limit is taken into account as well
     pub fn get_mem_usage_percentage(&self) -> u8 {
         let mem_info = sys_info::mem_info().expect("Could not fetch memory status information!");
 
         let mem_used = (mem_info.total - mem_info.avail) + (mem_info.swap_total - mem_info.swap_free);
         let mem_total = mem_info.total + mem_info.swap_total;
 
-        (mem_used * 100 / mem_total) as u8
+        let percentage = (mem_used * 100 / mem_total) as u8;
+
+        match util::get_cgroup_memory() {
+            None => percentage,
+            Some(cgroup_memory) => std::cmp::max(percentage, cgroup_memory.get_usage_percentage()),
+        }
+    }
+
+    /// Returns the memory pressure, i.e. the percentage of time in which tasks stalled
+    /// on memory reclaim. Returns `None` if the kernel lacks support for PSI
+    pub fn get_memory_pressure(&self) -> Option<f32> {
+        util::read_pressure("memory")
     }
 
     // pub fn get_free_mem_percentage(&self) -> u8 {
@@ -129,6 +143,16 @@ impl Metrics {
 
         let available_mem_upper_threshold = globals.get_config_file().available_mem_upper_threshold.unwrap();
 
+        let memory_pressure_critical_threshold = globals
+            .get_config_file()
+            .memory_pressure_critical_threshold
+            .unwrap_or(constants::MEMORY_PRESSURE_CRITICAL_THRESHOLD);
+
+        let memory_pressure_upper_threshold = globals
+            .get_config_file()
+            .memory_pressure_upper_threshold
+            .unwrap_or(constants::MEMORY_PRESSURE_UPPER_THRESHOLD);
+
         // let available_mem_lower_threshold = globals.get_config_file().available_mem_lower_threshold.unwrap();
 
         // *free* memory events
@@ -152,11 +176,36 @@ impl Metrics {
         // *available* memory events
         let mem_used = (mem_info.total - mem_info.avail) + (mem_info.swap_total - mem_info.swap_free);
         let mem_total = mem_info.total + mem_info.swap_total;
-        let percentage = (mem_used * 100 / mem_total) as u8;
+        let mut percentage = (mem_used * 100 / mem_total) as u8;
 
         info!("Mem: {}%, {} KiB/{} KiB", percentage, mem_used, mem_total);
 
-        if percentage >= available_mem_upper_threshold {
+        // a cgroup memory limit may be more restrictive than the memory of the system
+        if let Some(cgroup_memory) = util::get_cgroup_memory() {
+            info!(
+                "Cgroup mem: {}%, {} KiB/{} KiB",
+                cgroup_memory.get_usage_percentage(),
+                cgroup_memory.current / 1024,
+                cgroup_memory.max / 1024
+            );
+
+            percentage = std::cmp::max(percentage, cgroup_memory.get_usage_percentage());
+        }
+
+        // tasks stalling on memory reclaim are treated like exceeding the thresholds
+        let memory_pressure = self.get_memory_pressure();
+
+        if let Some(memory_pressure) = memory_pressure {
+            info!("Mem pressure: {:.2}%", memory_pressure);
+        }
+
+        let is_above_upper_threshold = percentage >= available_mem_upper_threshold
+            || memory_pressure.map_or(false, |p| p >= f32::from(memory_pressure_upper_threshold));
+
+        let is_above_critical_threshold = percentage >= available_mem_critical_threshold
+            || memory_pressure.map_or(false, |p| p >= f32::from(memory_pressure_critical_threshold));
+
+        if is_above_upper_threshold || is_above_critical_threshold {
             if self.available_mem_high_watermark_event_sent == false {
                 events::queue_internal_event(EventType::AvailableMemoryHighWatermark, globals);
                 self.available_mem_high_watermark_event_sent = true;
@@ -167,7 +216,7 @@ impl Metrics {
             }
 
             // check if we have exhausted available memory and notify if applicable
-            if percentage >= available_mem_critical_threshold {
+            if is_above_critical_threshold {
                 if self.available_mem_critical_event_sent == false {
                     events::queue_internal_event(EventType::AvailableMemoryCritical, globals);
                     self.available_mem_critical_event_sent = true;
@@ -179,7 +228,7 @@ impl Metrics {
             } else {
                 self.available_mem_critical_event_sent = false;
             }
-        } else if percentage <= available_mem_upper_threshold {
+        } else if !is_above_upper_threshold {
             if self.available_mem_low_watermark_event_sent == false && self.system_was_idle_at_least_once {
                 events::queue_internal_event(EventType::AvailableMemoryLowWatermark, globals);
                 self.available_mem_low_watermark_event_sent = true;
@@ -93,6 +93,10 @@ impl RuleEventBridge {
                 Self::rule_engine_fire_event(&rules::Event::OptimizeIOTraceLog(Some(path)), globals, manager);
             }
 
+            events::EventType::FinalizeIOTraces | events::EventType::FinalizeIOTrace(_) => {
+                // Internal events of the I/O tracer, not exposed to the rule engine
+            }
+
             events::EventType::IoTraceLogCreated(path) => {
                 Self::rule_engine_fire_event(&rules::Event::IoTraceLogCreated(Some(path)), globals, manager);
             }
@@ -102,6 +102,17 @@ impl StaticBlacklist {
         &self.blacklist
     }
 
+    /// Returns a glob set matching all blacklisted files, e.g. to be used as a
+    /// snapshot of the blacklist by threads that shall not query this plugin
+    pub fn get_file_blacklist_glob_set(&self) -> globset::GlobSet {
+        let mut builder = GlobSetBuilder::new();
+        for p in self.get_blacklist().iter() {
+            builder.add(Glob::new(p.to_string_lossy().into_owned().as_str()).unwrap());
+        }
+
+        builder.build().unwrap()
+    }
+
     pub fn is_file_blacklisted(&self, filename: &Path) -> bool {
         match GLOB_SET_FILES.lock() {
             Err(e) => {
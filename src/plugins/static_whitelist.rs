@@ -120,6 +120,8 @@ impl StaticWhitelist {
         let (sender, receiver): (Sender<HashMap<PathBuf, util::MemoryMapping>>, _) = channel();
         let sc = Mutex::new(sender.clone());
 
+        let strategy = config_file::get_prefetch_strategy(globals, config_file::PrefetchSource::Whitelist);
+
         let thread_pool = util::PREFETCH_POOL.lock();
         let globals_c = globals.clone();
         let manager_c = manager.clone();
@@ -136,12 +138,15 @@ impl StaticWhitelist {
                 // mmap and mlock file, if it is not contained in the blacklist
                 // and if it was not already mapped by some of the plugins
                 if Self::shall_we_map_file(path, &static_blacklist, &our_mapped_files) {
-                    match util::cache_file(path, true) {
+                    match util::cache_file(path, strategy) {
                         Err(s) => {
                             error!("Could not cache file {:?}: {}", path, s);
                         }
                         Ok(r) => {
                             trace!("Successfully cached file {:?}", path);
+
+                            // files cached without a mapping are recorded too, so that they will not be cached
+                            // again until they expire
                             mapped_files.insert(path.to_path_buf(), r);
                         }
                     }
@@ -180,7 +185,13 @@ impl StaticWhitelist {
                         break;
                     }
 
-                    iotrace_prefetcher_hook.prefetch_data_by_hash(hashval, globals, manager);
+                    iotrace_prefetcher_hook.prefetch_data_by_hash(
+                        hashval,
+                        util::PrefetchPriority::Background,
+                        config_file::get_prefetch_strategy(globals, config_file::PrefetchSource::Whitelist),
+                        globals,
+                        manager,
+                    );
                 }
 
                 info!("Finished prefetching of statically whitelisted programs");
@@ -208,8 +219,8 @@ impl StaticWhitelist {
             return false;
         }
 
-        // Have we already mapped this file?
-        if our_mapped_files.contains_key(filename) {
+        // Have we already mapped this file, or recently cached it without a mapping?
+        if our_mapped_files.get(filename).map_or(false, |mapping| mapping.is_cached()) {
             return false;
         }
 
@@ -31,6 +31,10 @@ use crate::constants;
 use crate::events;
 use crate::config_file;
 use crate::globals::*;
+use crate::hooks::fanotify_logger;
+use crate::hooks::fanotify_logger::PipelineStatistics;
+use crate::hooks::iotrace_prefetcher;
+use crate::hooks::iotrace_prefetcher::ThrottleStatistics;
 use crate::manager::*;
 use crate::plugins::plugin::Plugin;
 use crate::plugins::plugin::PluginDescription;
@@ -66,6 +70,9 @@ pub struct GlobalStatistics {
     pub static_whitelist_mapped_files_count: Option<usize>,
     pub static_whitelist_whitelist_entries_count: Option<usize>,
     pub static_whitelist_program_whitelist_entries_count: Option<usize>,
+
+    pub fanotify_pipeline: Option<PipelineStatistics>,
+    pub prefetch_throttling: Option<ThrottleStatistics>,
 }
 
 impl Statistics {
@@ -105,11 +112,16 @@ impl Statistics {
             }
         };
 
+        let fanotify_pipeline = Some(fanotify_logger::get_pipeline_statistics());
+        let prefetch_throttling = Some(iotrace_prefetcher::get_throttle_statistics());
+
         // produce final report
         GlobalStatistics {
             static_whitelist_mapped_files_count,
             static_whitelist_whitelist_entries_count,
             static_whitelist_program_whitelist_entries_count,
+            fanotify_pipeline,
+            prefetch_throttling,
         }
     }
 }
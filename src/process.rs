@@ -48,6 +48,11 @@ pub struct Process {
     /// Holds the `pid` of the process
     pub pid: libc::pid_t,
 
+    /// Holds the `pid` of the parent process
+    ///
+    /// NOTE: This is not dynamically fetched (see below)
+    pub ppid: libc::pid_t,
+
     /// Holds the `comm` (command name) of the process
     ///
     /// NOTE: This is not dynamically fetched, it contains the comm
@@ -88,6 +93,7 @@ impl Process {
 
                 Process {
                     pid,
+                    ppid: get_parent_pid(pid).unwrap_or(0),
                     comm: comm.clone(),
                     exe_name: exe_name.clone(),
                     mountinfo,
@@ -130,7 +136,7 @@ impl Process {
                 match caps {
                     Some(c) => Some(Mapping {
                         file: PathBuf::from(&c["filename"]),
-                        flags: String::from(&c["flags"]),
+                        flags: String::from(&c["mode"]),
                         start: usize::from_str_radix(&c["start"], 16).unwrap(),
                         end: usize::from_str_radix(&c["end"], 16).unwrap(),
                     }),
@@ -183,6 +189,25 @@ impl Process {
     }
 }
 
+/// Returns the parent process id from the content `stat` of a `/proc/<pid>/stat` file
+fn parse_parent_pid(stat: &str) -> Option<libc::pid_t> {
+    // the comm field may contain spaces and parentheses, so
+    // start parsing after the last closing parenthesis
+    let fields = &stat[stat.rfind(')')? + 1..];
+
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Returns the current parent process id of the process `pid`
+pub fn get_parent_pid(pid: libc::pid_t) -> Option<libc::pid_t> {
+    let tmp = format!("/proc/{}/stat", pid);
+    let filename = Path::new(&tmp);
+
+    let stat = util::read_uncompressed_text_file(filename).ok()?;
+
+    parse_parent_pid(&stat)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::process::*;
@@ -197,4 +222,11 @@ mod tests {
 
         assert!(comm.len() > 0);
     }
+
+    #[test]
+    fn test_parse_parent_pid() {
+        assert_eq!(Some(42), parse_parent_pid("1234 (bash) S 42 1234 1234 0 -1"));
+        assert_eq!(Some(7), parse_parent_pid("1234 (Web Content) (x)) R 7 1234"));
+        assert_eq!(None, parse_parent_pid("garbage"));
+    }
 }
@@ -0,0 +1,163 @@
+/*
+    Precached - A Linux process monitor and pre-caching daemon
+    Copyright (C) 2017-2020 the precached developers
+
+    This file is part of precached.
+
+    Precached is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Precached is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Precached.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use parking_lot::Mutex;
+use lazy_static::lazy_static;
+use log::{trace, debug, info, warn, error, log, LevelFilter};
+use crate::util;
+use crate::util::MountInfo;
+
+/// ioctl request: Map the logical extents of a file to physical extents on disk
+const FS_IOC_FIEMAP: u64 = 0xC020_660B;
+
+lazy_static! {
+    /// Cache of the rotational property of block devices, keyed by `(major, minor)`
+    static ref ROTATIONAL_DEVICES: Mutex<HashMap<(i32, i32), bool>> = Mutex::new(HashMap::new());
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct FiemapExtent {
+    fe_logical: u64,
+    fe_physical: u64,
+    fe_length: u64,
+    fe_reserved64: [u64; 2],
+    fe_flags: u32,
+    fe_reserved: [u32; 3],
+}
+
+/// Header of the FIEMAP ioctl, followed by room for a single extent
+#[repr(C)]
+#[derive(Debug, Default)]
+struct Fiemap {
+    fm_start: u64,
+    fm_length: u64,
+    fm_flags: u32,
+    fm_mapped_extents: u32,
+    fm_extent_count: u32,
+    fm_reserved: u32,
+    fm_extents: [FiemapExtent; 1],
+}
+
+/// Returns `true` if the file `filename` resides on a filesystem of `mounts`, that is backed
+/// by a rotational block device, e.g. a hard disk. The result is cached per block device
+pub fn is_on_rotational_device(mounts: &[MountInfo], filename: &Path) -> bool {
+    let mount = match util::find_mount(mounts, filename) {
+        None => return false,
+        Some(mount) => mount,
+    };
+
+    let key = (mount.major, mount.minor);
+
+    if let Some(result) = ROTATIONAL_DEVICES.lock().get(&key) {
+        return *result;
+    }
+
+    let result = is_mount_rotational(mount).unwrap_or(false);
+
+    trace!("Block device {}:{} is rotational: {}", key.0, key.1, result);
+
+    ROTATIONAL_DEVICES.lock().insert(key, result);
+
+    result
+}
+
+/// Determines if the block device backing the mount `mount` is rotational, by reading
+/// the `queue/rotational` attribute of the device from sysfs
+fn is_mount_rotational(mount: &MountInfo) -> Option<bool> {
+    let mut dir = PathBuf::from(format!("/sys/dev/block/{}:{}", mount.major, mount.minor));
+
+    // filesystems like btrfs report anonymous device numbers,
+    // use the device the filesystem has been mounted from instead
+    if !dir.exists() {
+        let rdev = fs::metadata(&mount.mount_source).ok()?.rdev();
+
+        dir = PathBuf::from(format!("/sys/dev/block/{}:{}", get_major(rdev), get_minor(rdev)));
+    }
+
+    let dir = fs::canonicalize(dir).ok()?;
+
+    // partitions share the request queue of their parent device
+    let queue_dir = if dir.join("partition").exists() {
+        dir.parent()?.join("queue")
+    } else {
+        dir.join("queue")
+    };
+
+    let text = util::read_uncompressed_text_file(&queue_dir.join("rotational")).ok()?;
+
+    Some(text.trim() == "1")
+}
+
+/// Returns the major number of the device number `dev`
+fn get_major(dev: u64) -> u64 {
+    ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff)
+}
+
+/// Returns the minor number of the device number `dev`
+fn get_minor(dev: u64) -> u64 {
+    (dev & 0xff) | ((dev >> 12) & !0xff)
+}
+
+/// Returns the physical offset on disk of the byte at offset `offset` of the file `filename`,
+/// as reported by the FIEMAP ioctl. Returns `None` if the filesystem does not support FIEMAP,
+/// or if there is no data at the given offset
+pub fn get_physical_offset(filename: &Path, offset: u64) -> Option<u64> {
+    let file = fs::File::open(filename).ok()?;
+
+    let mut fiemap = Fiemap {
+        fm_start: offset,
+        fm_length: u64::max_value() - offset,
+        fm_extent_count: 1,
+        ..Default::default()
+    };
+
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut fiemap as *mut Fiemap) };
+
+    if result < 0 || fiemap.fm_mapped_extents < 1 {
+        return None;
+    }
+
+    let extent = &fiemap.fm_extents[0];
+
+    Some(extent.fe_physical + offset.saturating_sub(extent.fe_logical))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::block_device::*;
+
+    #[test]
+    fn test_get_major_minor() {
+        // 8:1, e.g. `/dev/sda1`
+        assert_eq!(8, get_major(0x0801));
+        assert_eq!(1, get_minor(0x0801));
+
+        // 259:65536, using the extended encoding of minor numbers
+        let dev = (259 << 8) | (65536 << 12);
+        assert_eq!(259, get_major(dev));
+        assert_eq!(65536, get_minor(dev));
+    }
+}
@@ -0,0 +1,144 @@
+/*
+    Precached - A Linux process monitor and pre-caching daemon
+    Copyright (C) 2017-2020 the precached developers
+
+    This file is part of precached.
+
+    Precached is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Precached is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Precached.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::path::{Path, PathBuf};
+use lazy_static::lazy_static;
+use log::{trace, debug, info, warn, error, log, LevelFilter};
+use crate::util;
+
+lazy_static! {
+    /// The directory of the cgroup v2 of the daemon, if any
+    static ref OWN_CGROUP_DIR: Option<PathBuf> = find_own_cgroup_dir();
+}
+
+/// The memory usage and the memory limit of a cgroup
+#[derive(Debug, Clone)]
+pub struct CgroupMemory {
+    /// The directory of the cgroup in the cgroup v2 filesystem
+    pub path: PathBuf,
+    /// The memory currently in use by the cgroup and its descendants, in bytes
+    pub current: u64,
+    /// The memory limit of the cgroup, in bytes
+    pub max: u64,
+}
+
+impl CgroupMemory {
+    pub fn get_usage_percentage(&self) -> u8 {
+        std::cmp::min(100, self.current.saturating_mul(100) / std::cmp::max(1, self.max)) as u8
+    }
+}
+
+/// Returns the memory usage of the memory limited cgroup v2 the daemon is contained in,
+/// that is closest to its limit. Since the limits of all ancestors apply, this is the
+/// effective memory limit of the daemon. Returns `None` if the daemon is not contained
+/// in a memory limited cgroup, or if the system does not use cgroup v2
+pub fn get_cgroup_memory() -> Option<CgroupMemory> {
+    let dir = OWN_CGROUP_DIR.as_ref()?;
+
+    let mut result: Option<CgroupMemory> = None;
+
+    for path in dir.ancestors() {
+        // the root cgroup has no memory limit, and the memory controller
+        // may not be enabled for all cgroups of the hierarchy
+        let max = match util::read_uncompressed_text_file(&path.join("memory.max")) {
+            Err(_e) => continue,
+            Ok(text) => parse_memory_max(&text),
+        };
+
+        let current = util::read_uncompressed_text_file(&path.join("memory.current"))
+            .ok()
+            .and_then(|text| text.trim().parse().ok());
+
+        if let (Some(max), Some(current)) = (max, current) {
+            let memory = CgroupMemory {
+                path: path.to_path_buf(),
+                current,
+                max,
+            };
+
+            if result
+                .as_ref()
+                .map_or(true, |r| memory.get_usage_percentage() > r.get_usage_percentage())
+            {
+                result = Some(memory);
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns the directory of the cgroup v2 of the daemon, by joining the mount point
+/// of the cgroup v2 filesystem with the path listed in `/proc/self/cgroup`
+fn find_own_cgroup_dir() -> Option<PathBuf> {
+    let mounts = util::parse_own_mountinfo().ok()?;
+    let mount = mounts.iter().find(|m| m.fs_type == "cgroup2")?;
+
+    let text = util::read_uncompressed_text_file(Path::new("/proc/self/cgroup")).ok()?;
+    let path = parse_cgroup_v2_path(&text)?;
+
+    let result = mount.dest.join(path.trim_start_matches('/'));
+
+    debug!("Using cgroup v2: {:?}", result);
+
+    Some(result)
+}
+
+/// Returns the path of the cgroup v2 from the content `text` of a `/proc/<pid>/cgroup` file
+fn parse_cgroup_v2_path(text: &str) -> Option<String> {
+    text.lines()
+        .find(|l| l.starts_with("0::"))
+        .map(|l| String::from(&l["0::".len()..]))
+}
+
+/// Parses the content `text` of a `memory.max` file; returns `None` if the memory is unlimited
+fn parse_memory_max(text: &str) -> Option<u64> {
+    text.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::cgroup::*;
+
+    #[test]
+    fn test_parse_cgroup_v2_path() {
+        let text = "12:pids:/system.slice/precached.service\n0::/system.slice/precached.service\n";
+
+        assert_eq!(
+            Some(String::from("/system.slice/precached.service")),
+            parse_cgroup_v2_path(text)
+        );
+        assert_eq!(None, parse_cgroup_v2_path("1:name=systemd:/init.scope\n"));
+    }
+
+    #[test]
+    fn test_parse_memory_max() {
+        assert_eq!(Some(536870912), parse_memory_max("536870912\n"));
+        assert_eq!(None, parse_memory_max("max\n"));
+
+        let memory = CgroupMemory {
+            path: PathBuf::from("/sys/fs/cgroup/system.slice"),
+            current: 384 * 1024 * 1024,
+            max: 512 * 1024 * 1024,
+        };
+
+        assert_eq!(75, memory.get_usage_percentage());
+    }
+}
@@ -0,0 +1,221 @@
+/*
+    Precached - A Linux process monitor and pre-caching daemon
+    Copyright (C) 2017-2020 the precached developers
+
+    This file is part of precached.
+
+    Precached is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Precached is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Precached.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Program header type of segments containing notes
+const PT_NOTE: u32 = 4;
+
+/// Note type of the GNU build-id note
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Upper bound for the size of the segments we are going to read
+const MAX_SEGMENT_SIZE: u64 = 64 * 1024;
+
+/// Reads integers from the ELF file, honoring its byte order
+#[derive(Debug, Copy, Clone)]
+struct Endianness {
+    big_endian: bool,
+}
+
+impl Endianness {
+    fn u16(self, buf: &[u8]) -> u16 {
+        let bytes = buf[..2].try_into().unwrap();
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32(self, buf: &[u8]) -> u32 {
+        let bytes = buf[..4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn u64(self, buf: &[u8]) -> u64 {
+        let bytes = buf[..8].try_into().unwrap();
+        if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        }
+    }
+}
+
+/// Returns the GNU build-id (`.note.gnu.build-id`) of the ELF file `filename` as a
+/// hex string, or `None` if the file is not an ELF file or carries no build-id
+pub fn read_build_id(filename: &Path) -> Option<String> {
+    let mut file = File::open(filename).ok()?;
+
+    let mut header = [0u8; 64];
+    file.read_exact(&mut header[..52]).ok()?;
+
+    if header[0..4] != *b"\x7fELF" {
+        return None;
+    }
+
+    let is_64bit = header[4] == 2;
+    let endianness = Endianness {
+        big_endian: header[5] == 2,
+    };
+
+    if is_64bit {
+        file.read_exact(&mut header[52..64]).ok()?;
+    }
+
+    // offset, size and number of the program headers
+    let (ph_offset, ph_entry_size, ph_num) = if is_64bit {
+        (
+            endianness.u64(&header[32..]),
+            endianness.u16(&header[54..]),
+            endianness.u16(&header[56..]),
+        )
+    } else {
+        (
+            u64::from(endianness.u32(&header[28..])),
+            endianness.u16(&header[42..]),
+            endianness.u16(&header[44..]),
+        )
+    };
+
+    if usize::from(ph_entry_size) < if is_64bit { 56 } else { 32 } {
+        return None;
+    }
+
+    if usize::from(ph_entry_size) * usize::from(ph_num) > MAX_SEGMENT_SIZE as usize {
+        return None;
+    }
+
+    let mut program_headers = vec![0u8; usize::from(ph_entry_size) * usize::from(ph_num)];
+    file.seek(SeekFrom::Start(ph_offset)).ok()?;
+    file.read_exact(&mut program_headers).ok()?;
+
+    for program_header in program_headers.chunks(usize::from(ph_entry_size)) {
+        if endianness.u32(program_header) != PT_NOTE {
+            continue;
+        }
+
+        let (offset, size) = if is_64bit {
+            (endianness.u64(&program_header[8..]), endianness.u64(&program_header[32..]))
+        } else {
+            (
+                u64::from(endianness.u32(&program_header[4..])),
+                u64::from(endianness.u32(&program_header[16..])),
+            )
+        };
+
+        if size > MAX_SEGMENT_SIZE {
+            continue;
+        }
+
+        let mut notes = vec![0u8; size as usize];
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut notes).ok()?;
+
+        if let Some(build_id) = find_build_id_note(&notes, endianness) {
+            return Some(build_id);
+        }
+    }
+
+    None
+}
+
+/// Search the notes contained in `notes` for the GNU build-id note, and return its
+/// content as a hex string. Name and descriptor of each note are 4 byte aligned.
+/// The sizes are taken from the file, so all offsets are checked for overflows
+fn find_build_id_note(notes: &[u8], endianness: Endianness) -> Option<String> {
+    let align = |n: usize| n.checked_add(3).map(|n| n & !3);
+
+    let mut pos: usize = 0;
+
+    while pos.checked_add(12)? <= notes.len() {
+        let name_size = endianness.u32(&notes[pos..]) as usize;
+        let desc_size = endianness.u32(&notes[pos + 4..]) as usize;
+        let note_type = endianness.u32(&notes[pos + 8..]);
+
+        let name_start = pos + 12;
+        let name_end = name_start.checked_add(name_size)?;
+        let desc_start = name_start.checked_add(align(name_size)?)?;
+        let desc_end = desc_start.checked_add(desc_size)?;
+
+        if name_end > notes.len() || desc_end > notes.len() {
+            break;
+        }
+
+        if note_type == NT_GNU_BUILD_ID && &notes[name_start..name_end] == b"GNU\0" {
+            let build_id: Vec<String> = notes[desc_start..desc_end].iter().map(|b| format!("{:02x}", b)).collect();
+
+            return Some(build_id.join(""));
+        }
+
+        pos = desc_start.checked_add(align(desc_size)?)?;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::elf::*;
+
+    #[test]
+    fn test_find_build_id_note() {
+        let endianness = Endianness { big_endian: false };
+
+        // an unrelated note, followed by a build-id note
+        let mut notes = vec![];
+        notes.extend_from_slice(&[5, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0]);
+        notes.extend_from_slice(b"Test\0\0\0\0");
+        notes.extend_from_slice(&[0, 0, 0, 0]);
+        notes.extend_from_slice(&[4, 0, 0, 0, 4, 0, 0, 0, 3, 0, 0, 0]);
+        notes.extend_from_slice(b"GNU\0");
+        notes.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+
+        assert_eq!(Some(String::from("deadbeef")), find_build_id_note(&notes, endianness));
+        assert_eq!(None, find_build_id_note(&notes[..20], endianness));
+    }
+
+    #[test]
+    fn test_find_build_id_note_malformed() {
+        let endianness = Endianness { big_endian: false };
+
+        // the name exceeds the notes
+        let mut notes = vec![];
+        notes.extend_from_slice(&[64, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0]);
+        notes.extend_from_slice(b"GNU\0");
+
+        assert_eq!(None, find_build_id_note(&notes, endianness));
+
+        // oversized name and descriptor
+        let mut notes = vec![];
+        notes.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 3, 0, 0, 0]);
+        notes.extend_from_slice(b"GNU\0");
+
+        assert_eq!(None, find_build_id_note(&notes, endianness));
+    }
+}
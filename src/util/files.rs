@@ -97,17 +97,61 @@ pub fn read_uncompressed_text_file(filename: &Path) -> io::Result<String> {
 }
 
 /// Write `text` to the compressed file `filename`.
-/// This function transparently compresses the file using Zstd compression
+/// This function transparently compresses the file using Zstd compression.
+/// The file is replaced atomically: The data is written to a temporary file
+/// first, that is synced to disk and then renamed to `filename`, so that an
+/// interrupted write never leaves a truncated file behind
 pub fn write_text_file(filename: &Path, text: &str) -> io::Result<()> {
+    let compressed = zstd::encode_all(BufReader::new(text.as_bytes()), constants::ZSTD_COMPRESSION_RATIO)?;
+
+    let tmp_filename = get_temporary_filename(filename);
+
+    let result = write_and_sync(&tmp_filename, &compressed).and_then(|_| fs::rename(&tmp_filename, filename));
+
+    if result.is_err() {
+        fs::remove_file(&tmp_filename).unwrap_or_else(|_| {
+            warn!("Could not remove temporary file {:?}", tmp_filename);
+        });
+    }
+
+    result?;
+
+    // make the rename itself durable
+    if let Some(dirname) = filename.parent() {
+        if let Ok(dir) = OpenOptions::new().read(true).open(dirname) {
+            dir.sync_all().unwrap_or_else(|e| {
+                warn!("Could not sync directory {:?}: {}", dirname, e);
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Write `data` to the file `filename` and sync it to disk
+fn write_and_sync(filename: &Path, data: &[u8]) -> io::Result<()> {
     let mut file = OpenOptions::new().write(true).truncate(true).create(true).open(filename)?;
 
-    let compressed = zstd::encode_all(BufReader::new(text.as_bytes()), constants::ZSTD_COMPRESSION_RATIO)?;
-    file.write_all(&compressed)?;
-    // file.sync_data()?;
+    file.write_all(data)?;
+    file.sync_all()?;
 
     Ok(())
 }
 
+/// Returns the name of the temporary file used to atomically replace the file `filename`
+pub fn get_temporary_filename(filename: &Path) -> PathBuf {
+    let mut result = filename.as_os_str().to_os_string();
+    result.push(".tmp");
+
+    PathBuf::from(result)
+}
+
+/// Returns `true` if `filename` is a temporary file, that may have been
+/// left behind by an interrupted invocation of `write_text_file()`
+pub fn is_temporary_file(filename: &Path) -> bool {
+    filename.extension().map(|e| e == "tmp").unwrap_or(false)
+}
+
 // Create directory `dirname`
 pub fn mkdir(dirname: &Path) -> io::Result<()> {
     fs::create_dir_all(dirname)?;
@@ -328,4 +372,14 @@ mod tests {
         let result = ellipsize_filename(filename, 10).unwrap();
         assert_eq!("/12…23/123", result);
     }
+
+    #[test]
+    fn test_temporary_filename() {
+        let filename = Path::new("/var/lib/precached/iotrace/42.trace");
+
+        let result = get_temporary_filename(filename);
+        assert_eq!(Path::new("/var/lib/precached/iotrace/42.trace.tmp"), result);
+        assert!(is_temporary_file(&result));
+        assert!(!is_temporary_file(filename));
+    }
 }
@@ -0,0 +1,314 @@
+/*
+    Precached - A Linux process monitor and pre-caching daemon
+    Copyright (C) 2017-2020 the precached developers
+
+    This file is part of precached.
+
+    Precached is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Precached is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Precached.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use parking_lot::Mutex;
+use lazy_static::lazy_static;
+use log::{trace, debug, info, warn, error, log, LevelFilter};
+use serde_derive::{Serialize, Deserialize};
+use crate::constants;
+use crate::util;
+
+lazy_static! {
+    /// The most recent sample of the I/O load of the system
+    static ref IO_LOAD_MONITOR: Mutex<IOLoadMonitor> = Mutex::new(IOLoadMonitor::new());
+}
+
+/// The I/O load of the system, caused by other processes than precached itself. Both values are
+/// scaled down by the share of the I/O of the busiest block device that precached performed, so
+/// that prefetching does not throttle itself
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+pub struct IOLoad {
+    /// Percentage of time in which at least one task was stalled on I/O, averaged
+    /// over the last 10 seconds. Not available if the kernel lacks support for PSI
+    pub pressure: Option<f32>,
+    /// Utilization in percent of the busiest block device, since the previous sample
+    pub disk_utilization: Option<f32>,
+}
+
+/// The I/O counters of a block device, as found in `/proc/diskstats`
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+struct DiskStats {
+    /// Number of sectors (512 bytes each) read and written
+    sectors: u64,
+    /// Time in milliseconds spent doing I/O
+    io_ticks: u64,
+}
+
+/// Thresholds of the I/O load, above which prefetching will be throttled
+#[derive(Debug, Copy, Clone)]
+pub struct IOThrottle {
+    pub max_pressure: f32,
+    pub max_disk_utilization: f32,
+    pub max_pause: Duration,
+}
+
+impl IOThrottle {
+    /// Returns `true` if the I/O load `load` exceeds any of the thresholds
+    pub fn is_exceeded_by(&self, load: &IOLoad) -> bool {
+        load.pressure.map_or(false, |p| p > self.max_pressure)
+            || load.disk_utilization.map_or(false, |u| u > self.max_disk_utilization)
+    }
+}
+
+/// Samples `/proc/pressure/io`, `/proc/diskstats` and `/proc/self/io`, and keeps the
+/// counters required to compute the utilization of block devices caused by other processes
+struct IOLoadMonitor {
+    last_sample: Option<Instant>,
+    disk_stats: HashMap<String, DiskStats>,
+    own_read_bytes: u64,
+    load: IOLoad,
+}
+
+impl IOLoadMonitor {
+    fn new() -> IOLoadMonitor {
+        IOLoadMonitor {
+            last_sample: None,
+            disk_stats: HashMap::new(),
+            own_read_bytes: 0,
+            load: IOLoad::default(),
+        }
+    }
+
+    fn sample(&mut self) {
+        let now = Instant::now();
+
+        let pressure = util::read_pressure("io");
+
+        let disk_stats = util::read_uncompressed_text_file(Path::new("/proc/diskstats"))
+            .map(|text| parse_diskstats(&text))
+            .unwrap_or_default();
+
+        // bytes read by precached, including the readahead of the prefetcher threads
+        let own_read_bytes = util::read_uncompressed_text_file(Path::new("/proc/self/io"))
+            .ok()
+            .and_then(|text| parse_read_bytes(&text))
+            .unwrap_or(self.own_read_bytes);
+
+        self.load = match self.last_sample {
+            None => IOLoad {
+                pressure,
+                disk_utilization: None,
+            },
+
+            Some(last_sample) => compute_io_load(
+                pressure,
+                &self.disk_stats,
+                &disk_stats,
+                own_read_bytes.saturating_sub(self.own_read_bytes),
+                now - last_sample,
+            ),
+        };
+
+        self.disk_stats = disk_stats;
+        self.own_read_bytes = own_read_bytes;
+        self.last_sample = Some(now);
+
+        trace!("Sampled I/O load: {:?}", self.load);
+    }
+}
+
+/// Returns the current I/O load of the system. The load is sampled at most
+/// once per `IO_LOAD_SAMPLE_INTERVAL_MILLIS`, so this may be called often
+pub fn get_io_load() -> IOLoad {
+    let mut monitor = IO_LOAD_MONITOR.lock();
+
+    let is_due = monitor.last_sample.map_or(true, |t| {
+        t.elapsed() >= Duration::from_millis(constants::IO_LOAD_SAMPLE_INTERVAL_MILLIS)
+    });
+
+    if is_due {
+        monitor.sample();
+    }
+
+    monitor.load
+}
+
+/// Returns the I/O counters of each block device, from the content `text`
+/// of `/proc/diskstats`. Loop devices and RAM disks are skipped
+fn parse_diskstats(text: &str) -> HashMap<String, DiskStats> {
+    let mut result = HashMap::new();
+
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        if fields.len() < 13 {
+            continue;
+        }
+
+        let name = fields[2];
+
+        if name.starts_with("loop") || name.starts_with("ram") || name.starts_with("zram") {
+            continue;
+        }
+
+        let sectors_read: Result<u64, _> = fields[5].parse();
+        let sectors_written: Result<u64, _> = fields[9].parse();
+        let io_ticks: Result<u64, _> = fields[12].parse();
+
+        if let (Ok(sectors_read), Ok(sectors_written), Ok(io_ticks)) = (sectors_read, sectors_written, io_ticks) {
+            let stats = DiskStats {
+                sectors: sectors_read + sectors_written,
+                io_ticks,
+            };
+
+            result.insert(String::from(name), stats);
+        }
+    }
+
+    result
+}
+
+/// Returns the number of bytes the process has caused to be read from storage,
+/// from the content `text` of `/proc/<pid>/io`
+fn parse_read_bytes(text: &str) -> Option<u64> {
+    text.lines()
+        .find(|l| l.starts_with("read_bytes:"))
+        .and_then(|l| l["read_bytes:".len()..].trim().parse().ok())
+}
+
+/// Returns the I/O load caused by other processes than precached, from the I/O pressure `pressure`
+/// and from two samples of the I/O counters of block devices `previous` and `current`, taken `elapsed`
+/// apart, during which precached itself has read `own_bytes` bytes. The I/O of precached is assumed
+/// to have hit the busiest block device, and its share of the transferred bytes is discounted
+fn compute_io_load(
+    pressure: Option<f32>,
+    previous: &HashMap<String, DiskStats>,
+    current: &HashMap<String, DiskStats>,
+    own_bytes: u64,
+    elapsed: Duration,
+) -> IOLoad {
+    let elapsed_millis = elapsed.as_millis() as f32;
+
+    if elapsed_millis <= 0.0 {
+        return IOLoad {
+            pressure,
+            disk_utilization: None,
+        };
+    }
+
+    // the busiest block device, along with its busy time and the number of bytes transferred
+    let busiest = current
+        .iter()
+        .filter_map(|(name, stats)| {
+            previous.get(name).map(|p| {
+                let busy_millis = stats.io_ticks.saturating_sub(p.io_ticks);
+                let bytes = stats.sectors.saturating_sub(p.sectors) * 512;
+
+                (busy_millis, bytes)
+            })
+        })
+        .max_by_key(|(busy_millis, _bytes)| *busy_millis);
+
+    match busiest {
+        None => IOLoad {
+            pressure,
+            disk_utilization: None,
+        },
+
+        Some((busy_millis, bytes)) => {
+            let foreground_share = if bytes > 0 {
+                bytes.saturating_sub(own_bytes) as f32 / bytes as f32
+            } else {
+                1.0
+            };
+
+            let utilization = (busy_millis as f32 * 100.0 / elapsed_millis).min(100.0);
+
+            IOLoad {
+                pressure: pressure.map(|p| p * foreground_share),
+                disk_utilization: Some(utilization * foreground_share),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::io_pressure::*;
+
+    fn get_disk_utilization(
+        previous: &HashMap<String, DiskStats>,
+        current: &HashMap<String, DiskStats>,
+        own_bytes: u64,
+        millis: u64,
+    ) -> Option<f32> {
+        compute_io_load(None, previous, current, own_bytes, Duration::from_millis(millis)).disk_utilization
+    }
+
+    #[test]
+    fn test_compute_disk_utilization() {
+        let previous = parse_diskstats(
+            "   8       0 sda 100 0 800 50 10 0 80 5 0 1000 55 0 0 0 0\n\
+             8       1 sda1 90 0 700 40 10 0 80 5 0 900 45 0 0 0 0\n\
+             7       0 loop0 1 0 8 0 0 0 0 0 0 5 0\n",
+        );
+        let current = parse_diskstats(
+            "   8       0 sda 200 0 1600 90 10 0 80 5 0 1250 95 0 0 0 0\n\
+             8       1 sda1 180 0 1400 80 10 0 80 5 0 1100 85 0 0 0 0\n\
+             7       0 loop0 1 0 8 0 0 0 0 0 0 905 0\n",
+        );
+
+        assert!(!current.contains_key("loop0"));
+        assert_eq!(Some(50.0), get_disk_utilization(&previous, &current, 0, 500));
+        assert_eq!(None, get_disk_utilization(&previous, &current, 0, 0));
+    }
+
+    #[test]
+    fn test_discount_own_io() {
+        // sda: 800 sectors (409600 bytes) transferred, busy for 450 of 500 milliseconds
+        let previous = parse_diskstats("   8       0 sda 100 0 800 50 10 0 80 5 0 1000 55 0 0 0 0\n");
+        let current = parse_diskstats("   8       0 sda 200 0 1600 90 10 0 80 5 0 1450 95 0 0 0 0\n");
+
+        let throttle = IOThrottle {
+            max_pressure: 20.0,
+            max_disk_utilization: 80.0,
+            max_pause: Duration::from_millis(1000),
+        };
+
+        // the disk has been saturated by the prefetcher alone
+        let load = compute_io_load(Some(40.0), &previous, &current, 409_600, Duration::from_millis(500));
+        assert_eq!(Some(0.0), load.disk_utilization);
+        assert_eq!(Some(0.0), load.pressure);
+        assert!(!throttle.is_exceeded_by(&load));
+
+        // other processes caused half of the I/O
+        let load = compute_io_load(Some(40.0), &previous, &current, 204_800, Duration::from_millis(500));
+        assert_eq!(Some(45.0), load.disk_utilization);
+        assert_eq!(Some(20.0), load.pressure);
+        assert!(!throttle.is_exceeded_by(&load));
+
+        // other processes caused all of the I/O
+        let load = compute_io_load(Some(40.0), &previous, &current, 0, Duration::from_millis(500));
+        assert_eq!(Some(90.0), load.disk_utilization);
+        assert!(throttle.is_exceeded_by(&load));
+    }
+
+    #[test]
+    fn test_parse_read_bytes() {
+        let text = "rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\n\
+                    read_bytes: 4096\nwrite_bytes: 323932160\ncancelled_write_bytes: 0\n";
+
+        assert_eq!(Some(4096), parse_read_bytes(text));
+        assert_eq!(None, parse_read_bytes(""));
+    }
+}
@@ -26,13 +26,14 @@ use term::color::*;
 use term::Attr;
 use chrono::{DateTime, NaiveDateTime, Duration, Utc, offset::TimeZone};
 use log::{trace, debug, info, warn, error, log, LevelFilter};
-use crate::iotrace::{IOTraceLog, IOTraceLogFlag, TraceLogEntry, IOTraceLogEntryFlag, IOOperation};
+use crate::iotrace::{IOTraceLog, IOTraceLogFlag, TraceLogEntry, IOTraceLogEntryFlag, IOOperation, EntrySource};
 use crate::constants;
 use crate::util;
 
 /// Optimizes an I/O trace log. Keep only valid trace log entries that actually
 /// contribute to a faster program startup time. Remove trace log entries that
-/// are invalid, duplicate, or which referenced files do not exist anymore
+/// are invalid, duplicate, which referenced files do not exist anymore, or which
+/// have only been accessed in too few of the merged runs of the program
 pub fn optimize_io_trace_log(
     filename: &Path,
     io_trace: &mut IOTraceLog,
@@ -42,24 +43,46 @@ pub fn optimize_io_trace_log(
 ) -> Result<()> {
     trace!("Optimizing I/O trace log...");
 
+    if io_trace.corrupt {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Corrupt I/O trace log"));
+    }
+
     let mut optimized_trace_log = Vec::<TraceLogEntry>::new();
     let mut optimized_file_map = HashMap::<PathBuf, usize>::new();
     let mut size = 0;
 
     let mut already_opened = vec![];
+    let mut already_visited = vec![];
 
     for e in &io_trace.trace_log {
-        let entry = e.clone();
+        let mut entry = e.clone();
         let current_file;
 
+        // Refresh entries referencing files that have been replaced or modified since tracing
+        if entry.has_changed() {
+            trace!("Refreshing changed I/O trace log entry: {:?}", entry.operation.get_filename());
+            entry.refresh();
+        }
+
+        // Check if the file has been accessed in enough runs of the program
+        if io_trace.get_confidence(e.operation.get_filename()) < constants::IO_TRACE_MIN_CONFIDENCE {
+            continue;
+        }
+
         match e.operation {
-            IOOperation::Open(ref filename) => {
+            IOOperation::Open(ref filename) | IOOperation::OpenRanges(ref filename, _) | IOOperation::Exec(ref filename) => {
                 // Check if filename is a (valid) file
                 if !util::is_file(filename) {
                     // error!("Not a valid file!");
                     continue;
                 }
 
+                // Skip files that the program created itself, e.g. temporary files
+                if was_created_during_trace(filename, io_trace) {
+                    trace!("Skipping file that has been created during tracing: {:?}", filename);
+                    continue;
+                }
+
                 // Check if filename is already on the list
                 if already_opened.contains(filename) {
                     continue;
@@ -68,7 +91,23 @@ pub fn optimize_io_trace_log(
                 }
 
                 current_file = Some(PathBuf::from(filename));
-            } // _ => { /* Ignore others */ }
+            }
+
+            IOOperation::OpenDir(ref dirname) => {
+                // Check if dirname is a (valid) directory
+                if !util::is_directory(dirname) {
+                    continue;
+                }
+
+                // Check if dirname is already on the list
+                if already_visited.contains(dirname) {
+                    continue;
+                } else {
+                    already_visited.push(dirname.clone());
+                }
+
+                current_file = None;
+            }
         }
 
         // All tests passed successfully, append `e` to the optimized trace log
@@ -87,6 +126,9 @@ pub fn optimize_io_trace_log(
     // io_trace.file_map.clear();
     io_trace.file_map = optimized_file_map;
 
+    // forget about hits of files that do not exist anymore
+    io_trace.run_hits.retain(|filename, _hits| util::is_file_accessible(filename));
+
     io_trace.accumulated_size = size;
     io_trace.trace_log_optimized = true;
 
@@ -95,6 +137,20 @@ pub fn optimize_io_trace_log(
     Ok(())
 }
 
+/// Returns `true` if the file `filename` has been created while the I/O trace log
+/// `io_trace` has been recorded. Requires support for file creation times
+fn was_created_during_trace(filename: &Path, io_trace: &IOTraceLog) -> bool {
+    match fs::metadata(filename).and_then(|m| m.created()) {
+        Err(_) => false,
+
+        Ok(created) => {
+            let created = system_time_to_date_time(created);
+
+            created >= io_trace.created_at && created <= io_trace.trace_stopped_at
+        }
+    }
+}
+
 pub fn blacklist_io_trace_log(filename: &Path, io_trace: &mut IOTraceLog, blacklist: bool, dry_run: bool) -> Result<()> {
     trace!("Blacklisting I/O trace log...");
 
@@ -113,10 +169,29 @@ pub fn get_io_trace_flags_and_err(io_trace: &IOTraceLog) -> (Vec<IOTraceLogFlag>
     let mut err = false;
     let mut color = RED;
 
+    // a damaged I/O trace log file carries no information to check
+    if io_trace.corrupt {
+        return (vec![IOTraceLogFlag::Invalid, IOTraceLogFlag::Corrupt], true, color);
+    }
+
     if !util::is_file_accessible(&io_trace.exe) {
         flags.push(IOTraceLogFlag::MissingBinary);
         err = true;
         color = RED;
+    } else if let Some(build_id) = io_trace.build_id.as_ref() {
+        // the build-id identifies the binary reliably, even if it has been reinstalled
+        match util::read_build_id(&io_trace.exe) {
+            Some(ref current) if current == build_id => {
+                flags.push(IOTraceLogFlag::Current);
+                color = GREEN;
+            }
+
+            _ => {
+                flags.push(IOTraceLogFlag::BuildIdMismatch);
+                color = RED;
+                err = true;
+            }
+        }
     } else {
         // check that the I/O trace is newer than the binary
         match fs::metadata(Path::new(&io_trace.exe)) {
@@ -146,6 +221,20 @@ pub fn get_io_trace_flags_and_err(io_trace: &IOTraceLog) -> (Vec<IOTraceLogFlag>
         flags.push(IOTraceLogFlag::Fresh);
     }
 
+    if io_trace.truncated {
+        flags.push(IOTraceLogFlag::Truncated);
+    }
+
+    // the I/O trace log will be converted on the next save,
+    // e.g. during the next optimization pass
+    if io_trace.is_format_outdated() {
+        flags.push(IOTraceLogFlag::OldFormat);
+
+        if !err {
+            color = YELLOW;
+        }
+    }
+
     if !err {
         flags.push(IOTraceLogFlag::Valid);
     } else {
@@ -165,17 +254,20 @@ pub fn get_io_trace_log_entry_flags_and_err(entry: &TraceLogEntry) -> (Vec<IOTra
 
     // TODO: Perform consistency checks
     //       * Timestamp of I/O operation newer than target file?
-    match entry.operation {
-        IOOperation::Open(ref filename) => {
-            if util::is_file_accessible(filename) {
-                flags.push(IOTraceLogEntryFlag::OK);
-                color = GREEN;
-            } else {
-                flags.push(IOTraceLogEntryFlag::MissingFile);
-                err = true;
-                color = RED;
-            }
-        } // _ => { /* Do nothing */ }
+    let filename = entry.operation.get_filename();
+
+    if util::is_file_accessible(filename) {
+        if entry.has_changed() {
+            flags.push(IOTraceLogEntryFlag::Changed);
+            color = YELLOW;
+        } else {
+            flags.push(IOTraceLogEntryFlag::OK);
+            color = GREEN;
+        }
+    } else {
+        flags.push(IOTraceLogEntryFlag::MissingFile);
+        err = true;
+        color = RED;
     }
 
     if !err {
@@ -184,12 +276,38 @@ pub fn get_io_trace_log_entry_flags_and_err(entry: &TraceLogEntry) -> (Vec<IOTra
         flags.push(IOTraceLogEntryFlag::Invalid);
     }
 
+    if entry.source == EntrySource::Mapped {
+        flags.push(IOTraceLogEntryFlag::Mapped);
+    }
+
     // reverse elements, for a better looking result
     flags.reverse();
 
     (flags, err, color)
 }
 
+/// Returns `true` if the I/O trace log `io_trace` references files that
+/// have been replaced or modified since tracing
+pub fn has_changed_entries(io_trace: &IOTraceLog) -> bool {
+    io_trace.trace_log.iter().any(|e| e.has_changed())
+}
+
+/// Returns the number of changed and missing files referenced by the I/O trace log `io_trace`
+pub fn get_io_trace_staleness(io_trace: &IOTraceLog) -> (usize, usize) {
+    let mut changed = 0;
+    let mut missing = 0;
+
+    for entry in io_trace.trace_log.iter() {
+        if !util::is_file_accessible(entry.operation.get_filename()) {
+            missing += 1;
+        } else if entry.has_changed() {
+            changed += 1;
+        }
+    }
+
+    (changed, missing)
+}
+
 pub fn system_time_to_date_time(t: std::time::SystemTime) -> DateTime<Utc> {
     let (sec, nsec) = match t.duration_since(std::time::UNIX_EPOCH) {
         Ok(dur) => (dur.as_secs() as i64, dur.subsec_nanos()),
@@ -22,29 +22,68 @@ use std;
 use std::ffi::CString;
 use std::fs::File;
 use std::io::{Error, ErrorKind, Result};
-use std::os::unix::io::IntoRawFd;
+use std::os::unix::io::{AsRawFd, IntoRawFd};
 use std::path::{Path, PathBuf};
 use std::ptr;
+use std::time::{Duration, Instant};
 use serde_derive::{Serialize, Deserialize};
 use log::{trace, debug, info, warn, error, log, LevelFilter};
 use crate::constants;
 
-/// Represents a file backed memory mapping
+/// Specifies how files are brought into the page cache, from the least to the most invasive strategy
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize, Deserialize)]
+pub enum PrefetchStrategy {
+    /// Only call posix_fadvise(2) with `POSIX_FADV_WILLNEED`, no mapping is retained
+    Fadvise,
+    /// Additionally read the file via readahead(2), no mapping is retained
+    Readahead,
+    /// Additionally mmap(2) the file, and retain the mapping
+    Mmap,
+    /// Additionally mlock(2) the mapping, to prevent eviction of the file's pages
+    Mlock,
+}
+
+impl PrefetchStrategy {
+    /// Returns `true` if the strategy creates a memory mapping, that is retained until it is freed
+    pub fn is_mapping(self) -> bool {
+        self >= PrefetchStrategy::Mmap
+    }
+}
+
+/// Represents a cached file, along with its file backed memory mapping,
+/// if the strategy that cached the file retains a mapping
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MemoryMapping {
     pub filename: PathBuf,
     pub addr: usize,
     pub len: usize,
+    pub strategy: PrefetchStrategy,
+    /// Point in time the file has been cached at (not serialized)
+    #[serde(skip, default = "Instant::now")]
+    pub cached_at: Instant,
 }
 
 impl MemoryMapping {
-    pub fn new(filename: &Path, addr: usize, len: usize) -> MemoryMapping {
+    pub fn new(filename: &Path, addr: usize, len: usize, strategy: PrefetchStrategy) -> MemoryMapping {
         MemoryMapping {
             filename: PathBuf::from(filename),
             addr,
             len,
+            strategy,
+            cached_at: Instant::now(),
         }
     }
+
+    /// Returns `true` if a memory mapping has been retained, that has to be freed
+    pub fn is_mapped(&self) -> bool {
+        self.strategy.is_mapping()
+    }
+
+    /// Returns `true` if the file may be assumed to be cached still. A retained mapping keeps
+    /// the file cached, otherwise its pages may be evicted, so it expires after some time
+    pub fn is_cached(&self) -> bool {
+        self.is_mapped() || self.cached_at.elapsed() < Duration::from_secs(constants::PREFETCH_UNMAPPED_EXPIRY_SECS)
+    }
 }
 
 #[cfg(target_pointer_width = "64")]
@@ -57,18 +96,20 @@ type StatSize = i32;
 /// This currently performs the following actions:
 ///   * Open file `filename` and query it's size.
 ///     Return an Err if file exceeds the max. prefetch size
+///   * If `strategy` is `Fadvise`, only call posix_fadvise(2) with `POSIX_FADV_WILLNEED`
 ///   * Give the system's kernel a readahead hint via readahead(2) syscall
-///   * Additionally mmap(2) the file
+///   * Additionally mmap(2) the file, unless `strategy` is `Readahead`
 ///   * Call posix_fadvise(2) with `POSIX_FADV_WILLNEED` | `POSIX_FADV_SEQUENTIAL`
 ///     to give the kernel a hint on how we are about to use that file
 ///   * Call madvise(2) with `MADV_WILLNEED` | `MADV_SEQUENTIAL` | `MADV_MERGEABLE`
 ///     to give the kernel a hint on how we are about to use that memory mapping
-///   * Call mlock(2) if `with_mlock` is set to `true` to prevent
+///   * Call mlock(2) if `strategy` is `Mlock` to prevent
 ///     eviction of the files pages from the page cache
 ///
-/// Returns a `MemoryMapping` representing the newly created file backed mapping
-/// or an Err if the requested actions could not be performed
-pub fn cache_file(filename: &Path, with_mlock: bool) -> Result<MemoryMapping> {
+/// Returns a `MemoryMapping` representing the newly created file backed mapping,
+/// (without a mapping for the strategies that retain none), or an Err if the
+/// requested actions could not be performed
+pub fn cache_file(filename: &Path, strategy: PrefetchStrategy) -> Result<MemoryMapping> {
     trace!("Caching file: {:?}", filename);
 
     let file = File::open(filename)?;
@@ -92,6 +133,21 @@ pub fn cache_file(filename: &Path, with_mlock: bool) -> Result<MemoryMapping> {
 
         let custom_error = Error::new(ErrorKind::Other, "Maximum allowed file size for prefetching exceeded!");
         Err(custom_error)
+    } else if strategy == PrefetchStrategy::Fadvise {
+        // Only give the kernel a hint, the pages will be read asynchronously
+        let result = unsafe { libc::posix_fadvise(fd, 0, stat.st_size as libc::off_t, libc::POSIX_FADV_WILLNEED) };
+
+        // Try to close the file descriptor
+        unsafe { libc::close(fd) };
+
+        if result != 0 {
+            Err(std::io::Error::from_raw_os_error(result))
+        } else {
+            trace!("Successfully called posix_fadvise() for: {:?}", filename);
+
+            let mapping = MemoryMapping::new(filename, 0, stat.st_size as usize, strategy);
+            Ok(mapping)
+        }
     } else {
         // Manually fault in all pages
         let result = unsafe { libc::readahead(fd, 0, stat.st_size as usize) };
@@ -104,6 +160,14 @@ pub fn cache_file(filename: &Path, with_mlock: bool) -> Result<MemoryMapping> {
         } else {
             trace!("Successfully called readahead() for: {:?}", filename);
 
+            if !strategy.is_mapping() {
+                // No mapping will be retained
+                unsafe { libc::close(fd) };
+
+                let mapping = MemoryMapping::new(filename, 0, stat.st_size as usize, strategy);
+                return Ok(mapping);
+            }
+
             // Call to readahead succeeded, now mmap() and mlock() if requested
 
             let addr = unsafe {
@@ -171,7 +235,7 @@ pub fn cache_file(filename: &Path, with_mlock: bool) -> Result<MemoryMapping> {
                     } else {
                         trace!("Successfully called madvise() for: {:?}", filename);
 
-                        if with_mlock {
+                        if strategy == PrefetchStrategy::Mlock {
                             let result = unsafe { libc::mlock(addr as *mut libc::c_void, stat.st_size as usize) };
 
                             if result < 0 as libc::c_int {
@@ -189,7 +253,7 @@ pub fn cache_file(filename: &Path, with_mlock: bool) -> Result<MemoryMapping> {
                                 } else {
                                     trace!("Successfully called close() for: {:?}", filename);
 
-                                    let mapping = MemoryMapping::new(filename, addr as usize, stat.st_size as usize);
+                                    let mapping = MemoryMapping::new(filename, addr as usize, stat.st_size as usize, strategy);
                                     Ok(mapping)
                                 }
                             }
@@ -198,7 +262,7 @@ pub fn cache_file(filename: &Path, with_mlock: bool) -> Result<MemoryMapping> {
                             // Try to close the file descriptor
                             unsafe { libc::close(fd) };
 
-                            let mapping = MemoryMapping::new(filename, addr as usize, stat.st_size as usize);
+                            let mapping = MemoryMapping::new(filename, addr as usize, stat.st_size as usize, strategy);
                             Ok(mapping)
                         }
                     }
@@ -208,8 +272,193 @@ pub fn cache_file(filename: &Path, with_mlock: bool) -> Result<MemoryMapping> {
     }
 }
 
+/// Cache the page ranges `ranges` of the file `filename` into the systems page cache
+/// Ranges are specified as `(offset, length)` pairs in bytes, see `get_resident_ranges(...)`.
+/// This performs the same actions as `cache_file(...)`, but restricted to the
+/// specified ranges, instead of the whole file. Ranges that lie beyond the
+/// current end of the file will be clamped or skipped.
+///
+/// Returns a `MemoryMapping` representing the newly created file backed mapping,
+/// (without a mapping for the strategies that retain none), or an Err if the
+/// requested actions could not be performed
+pub fn cache_file_ranges(filename: &Path, ranges: &[(u64, u64)], strategy: PrefetchStrategy) -> Result<MemoryMapping> {
+    trace!("Caching {} range(s) of file: {:?}", ranges.len(), filename);
+
+    let file = File::open(filename)?;
+    let fd = file.as_raw_fd();
+
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let file_size = stat.st_size as u64;
+
+    // clamp ranges to the current size of the file
+    let ranges: Vec<(u64, u64)> = ranges
+        .iter()
+        .filter(|(offset, len)| *offset < file_size && *len > 0)
+        .map(|(offset, len)| (*offset, std::cmp::min(*len, file_size - *offset)))
+        .collect();
+
+    let ranges_size: u64 = ranges.iter().map(|(_offset, len)| len).sum();
+
+    if stat.st_mode & libc::S_ISUID == libc::S_ISUID || stat.st_mode & libc::S_ISGID == libc::S_ISGID {
+        let custom_error = Error::new(ErrorKind::Other, "Not prefetching SUID/SGID files!");
+        return Err(custom_error);
+    } else if ranges_size > constants::MAX_ALLOWED_PREFETCH_SIZE as u64 {
+        let custom_error = Error::new(ErrorKind::Other, "Maximum allowed file size for prefetching exceeded!");
+        return Err(custom_error);
+    } else if ranges.is_empty() {
+        let custom_error = Error::new(ErrorKind::Other, "No page ranges to prefetch!");
+        return Err(custom_error);
+    }
+
+    // only the `Mmap` and `Mlock` strategies create a mapping
+    let addr = if strategy.is_mapping() {
+        let addr = unsafe { libc::mmap(ptr::null_mut(), file_size as usize, libc::PROT_READ, libc::MAP_SHARED, fd, 0) };
+
+        if addr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        trace!("Successfully called mmap() for: {:?}", filename);
+
+        addr
+    } else {
+        ptr::null_mut()
+    };
+
+    let unmap = || {
+        if strategy.is_mapping() {
+            unsafe { libc::munmap(addr, file_size as usize) };
+        }
+    };
+
+    for (offset, len) in ranges.iter() {
+        if strategy >= PrefetchStrategy::Readahead {
+            let result = unsafe { libc::readahead(fd, *offset as libc::off64_t, *len as usize) };
+
+            if result < 0 {
+                let err = std::io::Error::last_os_error();
+                unmap();
+
+                return Err(err);
+            }
+        }
+
+        let result = unsafe { libc::posix_fadvise(fd, *offset as libc::off_t, *len as libc::off_t, libc::POSIX_FADV_WILLNEED) };
+
+        // posix_fadvise(2) returns the error number, instead of setting errno
+        if result != 0 {
+            unmap();
+
+            return Err(std::io::Error::from_raw_os_error(result));
+        }
+
+        if !strategy.is_mapping() {
+            continue;
+        }
+
+        // offsets are page aligned, since they originate from mincore(2)
+        let range_addr = unsafe { (addr as *mut u8).add(*offset as usize) } as *mut libc::c_void;
+
+        let result = unsafe { libc::madvise(range_addr, *len as usize, libc::MADV_WILLNEED | libc::MADV_MERGEABLE) };
+
+        if result < 0 as libc::c_int {
+            let err = std::io::Error::last_os_error();
+            unmap();
+
+            return Err(err);
+        }
+
+        if strategy == PrefetchStrategy::Mlock {
+            let result = unsafe { libc::mlock(range_addr, *len as usize) };
+
+            if result < 0 as libc::c_int {
+                let err = std::io::Error::last_os_error();
+                unmap();
+
+                return Err(err);
+            }
+        }
+    }
+
+    trace!("Successfully cached {} byte(s) of file: {:?}", ranges_size, filename);
+
+    let mapping = MemoryMapping::new(filename, addr as usize, file_size as usize, strategy);
+    Ok(mapping)
+}
+
+/// Query the pages of the file `filename` that are currently resident in the
+/// page cache, by mapping the file and calling mincore(2) on the mapping.
+/// Adjacent resident pages are coalesced into ranges.
+///
+/// Returns a `Vec` of `(offset, length)` pairs in bytes
+pub fn get_resident_ranges(filename: &Path) -> Result<Vec<(u64, u64)>> {
+    let file = File::open(filename)?;
+    let fd = file.as_raw_fd();
+
+    let file_size = file.metadata()?.len();
+    if file_size == 0 {
+        return Ok(vec![]);
+    }
+
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+    let num_pages = ((file_size + page_size - 1) / page_size) as usize;
+
+    let addr = unsafe { libc::mmap(ptr::null_mut(), file_size as usize, libc::PROT_READ, libc::MAP_SHARED, fd, 0) };
+
+    if addr == libc::MAP_FAILED {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut residency = vec![0u8; num_pages];
+    let result = unsafe { libc::mincore(addr, file_size as usize, residency.as_mut_ptr()) };
+    let err = std::io::Error::last_os_error();
+
+    unsafe { libc::munmap(addr, file_size as usize) };
+
+    if result < 0 as libc::c_int {
+        return Err(err);
+    }
+
+    Ok(coalesce_resident_pages(&residency, page_size, file_size))
+}
+
+/// Helper function, that converts a page residency vector as returned by mincore(2)
+/// into a `Vec` of `(offset, length)` pairs in bytes, clamped to `file_size`
+fn coalesce_resident_pages(residency: &[u8], page_size: u64, file_size: u64) -> Vec<(u64, u64)> {
+    let mut result = vec![];
+    let mut start: Option<u64> = None;
+
+    for (page, flags) in residency.iter().enumerate() {
+        let offset = page as u64 * page_size;
+
+        if flags & 1 == 1 {
+            if start.is_none() {
+                start = Some(offset);
+            }
+        } else if let Some(s) = start.take() {
+            result.push((s, offset - s));
+        }
+    }
+
+    if let Some(s) = start {
+        let end = std::cmp::min(residency.len() as u64 * page_size, file_size);
+        result.push((s, end - s));
+    }
+
+    result
+}
+
 /// Unmaps a memory mapping that was previously created by `cache_file(...)`
+/// or `cache_file_ranges(...)`. Does nothing, if the strategy retained no mapping
 pub fn free_mapping(mapping: &MemoryMapping) -> bool {
+    if !mapping.is_mapped() {
+        return true;
+    }
+
     let result = unsafe { libc::munmap(mapping.addr as *mut libc::c_void, mapping.len) };
 
     result == 0
@@ -230,3 +479,67 @@ pub fn prime_metadata_cache(filename: &Path) -> Result<()> {
         Ok(())
     }
 }
+
+/// Prime the kernel's dentry caches by reading the entries of the directory `dirname`
+pub fn prime_directory_cache(dirname: &Path) -> Result<()> {
+    trace!("Caching directory entries of: {:?}", dirname);
+
+    let mut count = 0;
+    for entry in std::fs::read_dir(dirname)? {
+        // d_type is provided by readdir(2) on most file systems,
+        // so this will usually not require an additional stat(2)
+        let _file_type = entry?.file_type();
+        count += 1;
+    }
+
+    trace!("Successfully enumerated {} entries of: {:?}", count, dirname);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_cached() {
+        let mut mapping = MemoryMapping::new(Path::new("/a"), 0, 4096, PrefetchStrategy::Fadvise);
+        assert!(mapping.is_cached());
+
+        let expiry = Duration::from_secs(constants::PREFETCH_UNMAPPED_EXPIRY_SECS + 1);
+
+        if let Some(cached_at) = Instant::now().checked_sub(expiry) {
+            mapping.cached_at = cached_at;
+            assert!(!mapping.is_cached());
+
+            // retained mappings never expire
+            mapping.strategy = PrefetchStrategy::Mlock;
+            assert!(mapping.is_cached());
+        }
+    }
+
+    #[test]
+    fn test_coalesce_resident_pages() {
+        assert!(coalesce_resident_pages(&[], 4096, 0).is_empty());
+        assert!(coalesce_resident_pages(&[0, 0, 0], 4096, 12288).is_empty());
+
+        assert_eq!(
+            vec![(0, 4096), (8192, 8192)],
+            coalesce_resident_pages(&[1, 0, 1, 1], 4096, 16384)
+        );
+
+        // other bits reported by mincore(2) are reserved, only the lowest one denotes residency
+        assert_eq!(vec![(4096, 4096)], coalesce_resident_pages(&[2, 1, 0], 4096, 12288));
+    }
+
+    #[test]
+    fn test_coalesce_resident_pages_partial_last_page() {
+        assert_eq!(vec![(4096, 4196)], coalesce_resident_pages(&[0, 1, 1], 4096, 8292));
+        assert_eq!(vec![(0, 100)], coalesce_resident_pages(&[1], 4096, 100));
+    }
+
+    #[test]
+    fn test_coalesce_resident_pages_all_resident() {
+        assert_eq!(vec![(0, 10000)], coalesce_resident_pages(&[1, 1, 1], 4096, 10000));
+        assert_eq!(vec![(0, 12288)], coalesce_resident_pages(&[1, 1, 1], 4096, 12288));
+    }
+}
@@ -18,14 +18,20 @@
     along with Precached.  If not, see <http://www.gnu.org/licenses/>.
 */
 
+pub mod block_device;
+pub mod cgroup;
 pub mod daemon;
 pub mod deref;
+pub mod elf;
 pub mod files;
+pub mod io_pressure;
 pub mod iotrace;
 pub mod mem;
 pub mod memory;
 pub mod mountinfo;
 pub mod namespace;
+pub mod prefetch_queue;
+pub mod pressure;
 pub mod sched;
 pub mod system;
 pub mod task_scheduler;
@@ -33,18 +39,26 @@ pub mod thread;
 pub mod thread_pool;
 pub mod trace_event;
 pub mod tracer;
+pub mod tracer_backend;
 pub mod utmpx;
 pub mod vec;
 
+pub use self::block_device::*;
+pub use self::cgroup::*;
 pub use self::daemon::*;
 pub use self::deref::*;
+pub use self::elf::*;
 pub use self::files::*;
+pub use self::io_pressure::*;
 pub use self::iotrace::*;
 pub use self::mem::*;
 pub use self::memory::*;
 pub use self::mountinfo::*;
 pub use self::namespace::*;
+pub use self::prefetch_queue::*;
+pub use self::pressure::*;
 pub use self::tracer::*;
+pub use self::tracer_backend::*;
 pub use self::sched::*;
 pub use self::system::*;
 pub use self::task_scheduler::*;
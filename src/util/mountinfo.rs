@@ -30,43 +30,132 @@ pub struct MountInfo {
     pub parent_id: i32,
     pub major: i32,
     pub minor: i32,
+    /// The path of the directory of the filesystem that forms the root of this mount
     pub source: PathBuf,
+    /// The mount point, relative to the root directory of the process
     pub dest: PathBuf,
+    /// The type of the filesystem, e.g. `ext4` or `overlay`
+    pub fs_type: String,
+    /// Filesystem specific information, e.g. the device name
+    pub mount_source: String,
+    /// Per superblock mount options, e.g. the directories of an overlay filesystem
+    pub super_options: String,
+}
+
+impl MountInfo {
+    /// Returns the directories of an overlay filesystem, in the order in which
+    /// they are searched for files: The upper directory first, then the lower ones
+    pub fn get_overlay_dirs(&self) -> Vec<PathBuf> {
+        let mut upper_dirs = vec![];
+        let mut lower_dirs = vec![];
+
+        for option in self.super_options.split(',') {
+            if option.starts_with("upperdir=") {
+                upper_dirs.push(PathBuf::from(&option["upperdir=".len()..]));
+            } else if option.starts_with("lowerdir=") {
+                lower_dirs.extend(option["lowerdir=".len()..].split(':').map(PathBuf::from));
+            }
+        }
+
+        upper_dirs.append(&mut lower_dirs);
+
+        upper_dirs
+    }
+}
+
+/// Parses a single line of `/proc/<pid>/mountinfo`, e.g.:
+/// `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`
+/// The number of optional fields preceding the separator `-` varies
+pub fn parse_mountinfo_line(line: &str) -> Option<MountInfo> {
+    let fields: Vec<&str> = line.split(' ').collect();
+
+    if fields.len() < 5 {
+        return None;
+    }
+
+    let id = fields[0].parse::<i32>().ok()?;
+    let parent_id = fields[1].parse::<i32>().ok()?;
+
+    let tmp: Vec<&str> = fields[2].split(':').collect();
+    let major = tmp.get(0)?.parse::<i32>().ok()?;
+    let minor = tmp.get(1)?.parse::<i32>().ok()?;
+
+    let source = PathBuf::from(fields[3].to_owned());
+    let dest = PathBuf::from(fields[4].to_owned());
+
+    let separator = fields.iter().skip(5).position(|f| *f == "-").map(|i| i + 5);
+    let get_field = |offset: usize| {
+        separator
+            .and_then(|i| fields.get(i + offset))
+            .map(|f| (*f).to_owned())
+            .unwrap_or_default()
+    };
+
+    Some(MountInfo {
+        id,
+        parent_id,
+        major,
+        minor,
+        source,
+        dest,
+        fs_type: get_field(1),
+        mount_source: get_field(2),
+        super_options: get_field(3),
+    })
 }
 
 /// Parses `/proc/<pid>/mountinfo` and returns it as Vec<MountInfo>
 pub fn parse_proc_mountinfo(pid: i32) -> std::io::Result<Vec<MountInfo>> {
+    parse_mountinfo_file(&Path::new("/proc").join(format!("{}", pid)).join("mountinfo"))
+}
+
+/// Parses the mountinfo of the mount namespace of our own process
+pub fn parse_own_mountinfo() -> std::io::Result<Vec<MountInfo>> {
+    parse_mountinfo_file(Path::new("/proc/self/mountinfo"))
+}
+
+fn parse_mountinfo_file(filename: &Path) -> std::io::Result<Vec<MountInfo>> {
     let mut result = Vec::new();
 
-    let file = File::open(format!("/proc/{}/mountinfo", pid))?;
+    let file = File::open(filename)?;
     let reader = BufReader::new(file);
 
     for line in reader.lines() {
-        let line = line.unwrap();
-        let fields: Vec<&str> = line.split(' ').collect();
-
-        let id = fields[0].parse::<i32>().unwrap();
-        let parent_id = fields[1].parse::<i32>().unwrap();
-
-        let tmp: Vec<&str> = fields[2].split(':').collect();
-        let major = tmp[0].parse::<i32>().unwrap();
-        let minor = tmp[1].parse::<i32>().unwrap();
-
-        let source = PathBuf::from(fields[3].to_owned());
-        let dest = PathBuf::from(fields[4].to_owned());
-
-        let mountinfo = MountInfo {
-            id,
-            parent_id,
-            major,
-            minor,
-            source,
-            dest,
-        };
-
-        // println!("{:?}", mountinfo);
-        result.push(mountinfo);
+        if let Some(mountinfo) = parse_mountinfo_line(&line?) {
+            // println!("{:?}", mountinfo);
+            result.push(mountinfo);
+        }
     }
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::util::mountinfo::*;
+
+    #[test]
+    fn test_parse_mountinfo_line() {
+        let mountinfo =
+            parse_mountinfo_line("36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue").unwrap();
+
+        assert_eq!((98, 0), (mountinfo.major, mountinfo.minor));
+        assert_eq!(PathBuf::from("/mnt1"), mountinfo.source);
+        assert_eq!(PathBuf::from("/mnt2"), mountinfo.dest);
+        assert_eq!("ext3", mountinfo.fs_type);
+        assert_eq!("/dev/root", mountinfo.mount_source);
+
+        assert!(parse_mountinfo_line("36 35").is_none());
+    }
+
+    #[test]
+    fn test_get_overlay_dirs() {
+        let mountinfo = parse_mountinfo_line(
+            "512 480 0:52 / / rw,relatime - overlay overlay rw,lowerdir=/l/a:/l/b,upperdir=/u/diff,workdir=/u/work",
+        )
+        .unwrap();
+
+        let expected: Vec<PathBuf> = vec!["/u/diff", "/l/a", "/l/b"].into_iter().map(PathBuf::from).collect();
+        assert_eq!(expected, mountinfo.get_overlay_dirs());
+    }
+}
@@ -18,7 +18,10 @@
     along with Precached.  If not, see <http://www.gnu.org/licenses/>.
 */
 
+use std::fs;
 use std::iter::FromIterator;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use crate::util::mountinfo::MountInfo;
 
@@ -56,3 +59,183 @@ pub fn find_source_path(mounts: &[MountInfo], filename: &Path) -> Option<PathBuf
 
     result
 }
+
+/// Maps the path `filename`, as seen by the process `pid`, into the current mount namespace.
+/// `mounts` are the mounts of the process, `host_mounts` the ones of the current mount namespace.
+/// The following strategies are tried in order, the first path that refers to the same file as
+/// `/proc/<pid>/root/<filename>` is returned:
+///  * The path itself, e.g. if the process runs in the current mount namespace
+///  * Flatpak: `/app` and `/usr` are resolved using the deployment paths from `/.flatpak-info`
+///  * Bind mounts, e.g. of Snap packages and containers, are resolved by their device and root
+///  * Overlay filesystems are resolved by looking up the file in their upper and lower directories
+///  * The path the kernel reports for the file, after opening it through `/proc/<pid>/root`
+///
+/// If the file is not accessible through `/proc/<pid>/root`, e.g. because the process
+/// has exited in the meantime, `find_source_path()` is used as a fallback
+pub fn canonicalize_path(pid: i32, mounts: &[MountInfo], host_mounts: &[MountInfo], filename: &Path) -> Option<PathBuf> {
+    let target = match fs::metadata(get_proc_root_path(pid, filename)) {
+        Ok(metadata) => metadata,
+        Err(_) => return find_source_path(mounts, filename),
+    };
+
+    let is_target = |path: &Path| fs::metadata(path).map_or(false, |m| is_same_file(&m, &target));
+
+    if is_target(filename) {
+        return Some(filename.to_path_buf());
+    }
+
+    if let Some(path) = find_flatpak_path(pid, filename).filter(|p| is_target(p)) {
+        return Some(path);
+    }
+
+    if let Some(path) = find_bind_mount_path(mounts, host_mounts, filename).filter(|p| is_target(p)) {
+        return Some(path);
+    }
+
+    if let Some(path) = find_overlay_paths(mounts, filename).into_iter().find(|p| is_target(p)) {
+        return Some(path);
+    }
+
+    find_proc_root_path(pid, filename).filter(|p| is_target(p))
+}
+
+/// Returns the path of `filename` below `/proc/<pid>/root`
+fn get_proc_root_path(pid: i32, filename: &Path) -> PathBuf {
+    let relative = filename.strip_prefix("/").unwrap_or(filename);
+
+    Path::new("/proc").join(format!("{}", pid)).join("root").join(relative)
+}
+
+/// Returns `true` if `a` and `b` describe the same file. Overlay filesystems report
+/// their own device number, so their files are compared by size and modification time
+fn is_same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    (a.dev() == b.dev() && a.ino() == b.ino())
+        || (a.file_type() == b.file_type() && a.len() == b.len() && a.mtime() == b.mtime() && a.mtime_nsec() == b.mtime_nsec())
+}
+
+/// Returns the mount of `mounts` that contains `filename`
+pub fn find_mount<'a>(mounts: &'a [MountInfo], filename: &Path) -> Option<&'a MountInfo> {
+    let mut result: Option<&MountInfo> = None;
+
+    // later mounts shadow earlier ones on the same mount point
+    for mount in mounts.iter().filter(|m| filename.starts_with(&m.dest)) {
+        if result.map_or(true, |r| mount.dest.components().count() >= r.dest.components().count()) {
+            result = Some(mount);
+        }
+    }
+
+    result
+}
+
+/// Returns the path of `filename`, relative to the root directory of the filesystem mounted by `mount`
+fn get_filesystem_path(mount: &MountInfo, filename: &Path) -> Option<PathBuf> {
+    let relative = filename.strip_prefix(&mount.dest).ok()?;
+
+    Some(mount.source.join(relative))
+}
+
+/// Resolve `filename` using a mount of the same filesystem in the current mount namespace
+fn find_bind_mount_path(mounts: &[MountInfo], host_mounts: &[MountInfo], filename: &Path) -> Option<PathBuf> {
+    let mount = find_mount(mounts, filename)?;
+    let fs_path = get_filesystem_path(mount, filename)?;
+
+    host_mounts
+        .iter()
+        .filter(|m| m.major == mount.major && m.minor == mount.minor && fs_path.starts_with(&m.source))
+        .max_by_key(|m| m.source.components().count())
+        .and_then(|m| fs_path.strip_prefix(&m.source).ok().map(|r| m.dest.join(r)))
+}
+
+/// Resolve `filename` by looking it up in the directories of the overlay filesystem containing it
+fn find_overlay_paths(mounts: &[MountInfo], filename: &Path) -> Vec<PathBuf> {
+    match find_mount(mounts, filename) {
+        Some(mount) if mount.fs_type == "overlay" => match get_filesystem_path(mount, filename) {
+            Some(fs_path) => {
+                let relative = fs_path.strip_prefix("/").unwrap_or(&fs_path).to_path_buf();
+
+                mount.get_overlay_dirs().iter().map(|d| d.join(&relative)).collect()
+            }
+
+            None => vec![],
+        },
+
+        _ => vec![],
+    }
+}
+
+/// Resolve `filename` using the deployment paths of the Flatpak application and runtime,
+/// if the process `pid` runs inside of a Flatpak sandbox
+fn find_flatpak_path(pid: i32, filename: &Path) -> Option<PathBuf> {
+    let info = fs::read_to_string(get_proc_root_path(pid, Path::new("/.flatpak-info"))).ok()?;
+    let (app_path, runtime_path) = parse_flatpak_info(&info);
+
+    if let (Some(app_path), Ok(relative)) = (app_path, filename.strip_prefix("/app")) {
+        return Some(app_path.join(relative));
+    }
+
+    if let (Some(runtime_path), Ok(relative)) = (runtime_path, filename.strip_prefix("/usr")) {
+        return Some(runtime_path.join(relative));
+    }
+
+    None
+}
+
+/// Returns the paths of the application and the runtime deployments, from the
+/// `[Instance]` section of the content `info` of a `/.flatpak-info` file
+fn parse_flatpak_info(info: &str) -> (Option<PathBuf>, Option<PathBuf>) {
+    let mut app_path = None;
+    let mut runtime_path = None;
+
+    let mut in_instance_section = false;
+
+    for line in info.lines().map(|l| l.trim()) {
+        if line.starts_with('[') {
+            in_instance_section = line == "[Instance]";
+        } else if in_instance_section {
+            if line.starts_with("app-path=") {
+                app_path = Some(PathBuf::from(&line["app-path=".len()..]));
+            } else if line.starts_with("runtime-path=") {
+                runtime_path = Some(PathBuf::from(&line["runtime-path=".len()..]));
+            }
+        }
+    }
+
+    (app_path, runtime_path)
+}
+
+/// Open `filename` through `/proc/<pid>/root`, and return the path that the kernel reports for it
+fn find_proc_root_path(pid: i32, filename: &Path) -> Option<PathBuf> {
+    // use `O_PATH`, so that opening the file has no side effects, e.g. on FIFOs
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_PATH)
+        .open(get_proc_root_path(pid, filename))
+        .ok()?;
+
+    fs::read_link(format!("/proc/self/fd/{}", file.as_raw_fd())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::namespace::*;
+
+    #[test]
+    fn test_parse_flatpak_info() {
+        let info = "[Application]\nname=org.example.App\n\n[Instance]\n\
+                    app-path=/var/lib/flatpak/app/org.example.App/x86_64/stable/1a2b/files\n\
+                    runtime-path=/var/lib/flatpak/runtime/org.example.Platform/x86_64/1.0/3c4d/files\n";
+
+        let (app_path, runtime_path) = parse_flatpak_info(info);
+
+        assert_eq!(
+            Some(PathBuf::from("/var/lib/flatpak/app/org.example.App/x86_64/stable/1a2b/files")),
+            app_path
+        );
+        assert_eq!(
+            Some(PathBuf::from(
+                "/var/lib/flatpak/runtime/org.example.Platform/x86_64/1.0/3c4d/files"
+            )),
+            runtime_path
+        );
+    }
+}
@@ -0,0 +1,467 @@
+/*
+    Precached - A Linux process monitor and pre-caching daemon
+    Copyright (C) 2017-2020 the precached developers
+
+    This file is part of precached.
+
+    Precached is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Precached is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Precached.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::PathBuf;
+use std::time::Instant;
+use serde_derive::{Serialize, Deserialize};
+use crate::constants;
+use crate::iotrace::{IOOperation, TraceLogEntry};
+
+/// The priority of a prefetch request. Requests of higher priority are served first
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize, Deserialize)]
+pub enum PrefetchPriority {
+    /// Priming of caches, e.g. of statically whitelisted programs or of file metadata
+    Background,
+    /// Prefetching of frequently used programs, when the system is idle
+    HotApplication,
+    /// Prefetching for a program that is being started right now
+    Interactive,
+}
+
+/// Specifies what will be prefetched for the entries of a chunk
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum PrefetchKind {
+    /// Map and lock the contents of the files
+    Data,
+    /// Prime the dentry and inode caches only
+    Metadata,
+}
+
+/// The state of a queued prefetch request, as reported via IPC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefetchQueueEntry {
+    pub name: String,
+    pub priority: PrefetchPriority,
+    pub kind: PrefetchKind,
+    pub pending_chunks: usize,
+    pub pending_entries: usize,
+}
+
+/// A part of a prefetch request, that is processed by a single prefetcher thread
+#[derive(Debug, Clone)]
+pub struct PrefetchChunk<C> {
+    pub name: String,
+    pub priority: PrefetchPriority,
+    pub kind: PrefetchKind,
+    /// The chunk shall not be processed before this point in time, used for timeline-paced prefetching
+    pub not_before: Option<Instant>,
+    pub entries: Vec<TraceLogEntry>,
+    /// The entries reside on a rotational device, and shall be processed sequentially,
+    /// ordered by their physical location on disk
+    pub ordered: bool,
+    /// Data shared by all chunks of a request, e.g. the blacklist
+    pub context: C,
+    sequence: u64,
+}
+
+/// The result of taking the next chunk from the prefetch queue
+#[derive(Debug)]
+pub enum PopResult<C> {
+    /// The chunk that shall be served next
+    Chunk(PrefetchChunk<C>),
+    /// No chunk is due yet, the earliest pending chunk will be due at the specified point in time
+    Pending(Instant),
+    /// No chunks are pending
+    Empty,
+}
+
+impl<C> PartialEq for PrefetchChunk<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<C> Eq for PrefetchChunk<C> {}
+
+impl<C> PartialOrd for PrefetchChunk<C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<C> Ord for PrefetchChunk<C> {
+    /// Among the chunks that are due, the "greatest" chunk is served first: The one with the
+    /// highest priority, then the one that was due first, then the one that has been queued first
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| match (self.not_before, other.not_before) {
+                (Some(a), Some(b)) => b.cmp(&a),
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (None, None) => Ordering::Equal,
+            })
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+/// Central queue of the prefetcher: Orders the chunks of all pending prefetch requests by
+/// their priority, and skips files that are already queued by a request of equal or higher
+/// priority. Chunks that are not due yet are held back, so that they never delay chunks
+/// that are. `C` is the type of the context data that is shared by the chunks of a request
+#[derive(Debug)]
+pub struct PrefetchQueue<C> {
+    chunks: BinaryHeap<PrefetchChunk<C>>,
+    delayed: Vec<PrefetchChunk<C>>,
+    queued_files: HashMap<PathBuf, PrefetchPriority>,
+    sequence: u64,
+}
+
+impl<C: Clone> PrefetchQueue<C> {
+    pub fn new() -> Self {
+        PrefetchQueue {
+            chunks: BinaryHeap::new(),
+            delayed: vec![],
+            queued_files: HashMap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Queue a prefetch request for the time slots `slots`, given as pairs of the point in time
+    /// the slot is due, and its entries. Each slot is split into chunks of at most
+    /// `PREFETCH_CHUNK_SIZE` entries. Returns the number of queued chunks
+    pub fn push(
+        &mut self,
+        name: &str,
+        priority: PrefetchPriority,
+        kind: PrefetchKind,
+        slots: Vec<(Option<Instant>, Vec<TraceLogEntry>)>,
+        context: C,
+    ) -> usize {
+        self.push_slots(name, priority, kind, slots, context, false)
+    }
+
+    /// Queue a prefetch request for the time slots `slots`, whose entries reside on a rotational
+    /// device. Each slot forms a single chunk, so that its entries can be processed sequentially,
+    /// ordered by their physical location on disk. Returns the number of queued chunks
+    pub fn push_ordered(
+        &mut self,
+        name: &str,
+        priority: PrefetchPriority,
+        kind: PrefetchKind,
+        slots: Vec<(Option<Instant>, Vec<TraceLogEntry>)>,
+        context: C,
+    ) -> usize {
+        self.push_slots(name, priority, kind, slots, context, true)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push_slots(
+        &mut self,
+        name: &str,
+        priority: PrefetchPriority,
+        kind: PrefetchKind,
+        slots: Vec<(Option<Instant>, Vec<TraceLogEntry>)>,
+        context: C,
+        ordered: bool,
+    ) -> usize {
+        let mut result = 0;
+
+        let chunk_size = if ordered {
+            usize::max_value()
+        } else {
+            constants::PREFETCH_CHUNK_SIZE
+        };
+
+        for (not_before, entries) in slots {
+            let entries: Vec<TraceLogEntry> = entries
+                .into_iter()
+                .filter(|e| kind != PrefetchKind::Data || self.reserve_file(e, priority))
+                .collect();
+
+            for chunk in entries.chunks(chunk_size) {
+                self.sequence += 1;
+
+                let chunk = PrefetchChunk {
+                    name: String::from(name),
+                    priority,
+                    kind,
+                    not_before,
+                    entries: chunk.to_vec(),
+                    ordered,
+                    context: context.clone(),
+                    sequence: self.sequence,
+                };
+
+                if not_before.is_some() {
+                    self.delayed.push(chunk);
+                } else {
+                    self.chunks.push(chunk);
+                }
+
+                result += 1;
+            }
+        }
+
+        result
+    }
+
+    /// Returns `false` if the file referenced by the entry `entry` is already queued with
+    /// priority `priority` or higher, otherwise marks it as queued and returns `true`.
+    /// Metadata operations are never skipped
+    fn reserve_file(&mut self, entry: &TraceLogEntry, priority: PrefetchPriority) -> bool {
+        match entry.operation {
+            IOOperation::OpenDir(_) => true,
+
+            _ => {
+                let filename = entry.operation.get_filename();
+
+                match self.queued_files.get(filename) {
+                    Some(queued) if *queued >= priority => false,
+
+                    _ => {
+                        self.queued_files.insert(filename.to_path_buf(), priority);
+                        true
+                    }
+                }
+            }
+        }
+    }
+
+    /// Move the held back chunks, that are due at `now`, to the chunks that may be served
+    fn promote_due_chunks(&mut self, now: Instant) {
+        let (due, delayed): (Vec<PrefetchChunk<C>>, Vec<PrefetchChunk<C>>) = std::mem::replace(&mut self.delayed, vec![])
+            .into_iter()
+            .partition(|c| c.not_before.map_or(true, |t| t <= now));
+
+        self.delayed = delayed;
+        self.chunks.extend(due);
+    }
+
+    /// Remove the chunk that shall be served next from the queue. Only chunks that are due
+    /// at `now` are served, otherwise the point in time the next chunk will be due is returned
+    pub fn pop(&mut self, now: Instant) -> PopResult<C> {
+        self.promote_due_chunks(now);
+
+        match self.chunks.pop() {
+            Some(chunk) => PopResult::Chunk(chunk),
+
+            None => match self.get_next_deadline() {
+                Some(deadline) => PopResult::Pending(deadline),
+                None => PopResult::Empty,
+            },
+        }
+    }
+
+    /// Returns the point in time the earliest of the held back chunks will be due
+    pub fn get_next_deadline(&self) -> Option<Instant> {
+        self.delayed.iter().filter_map(|c| c.not_before).min()
+    }
+
+    /// Remove all ordered chunks with priority `priority` and kind `kind` from the queue, that
+    /// are due at `now`. Used to batch the entries of several requests for a rotational device
+    pub fn pop_ordered(&mut self, priority: PrefetchPriority, kind: PrefetchKind, now: Instant) -> Vec<PrefetchChunk<C>> {
+        self.promote_due_chunks(now);
+
+        let (result, remaining): (Vec<PrefetchChunk<C>>, Vec<PrefetchChunk<C>>) =
+            std::mem::replace(&mut self.chunks, BinaryHeap::new())
+                .into_vec()
+                .into_iter()
+                .partition(|c| c.ordered && c.priority == priority && c.kind == kind);
+
+        self.chunks = BinaryHeap::from(remaining);
+
+        result
+    }
+
+    /// Release the files of the processed chunk `chunk`, so that they may be queued again
+    pub fn complete(&mut self, chunk: &PrefetchChunk<C>) {
+        if chunk.kind == PrefetchKind::Data {
+            for entry in chunk.entries.iter() {
+                let filename = entry.operation.get_filename();
+
+                // the file may have been queued again by a request of higher priority
+                if self.queued_files.get(filename) == Some(&chunk.priority) {
+                    self.queued_files.remove(filename);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of pending chunks
+    pub fn len(&self) -> usize {
+        self.chunks.len() + self.delayed.len()
+    }
+
+    /// Returns `true` if no chunks are pending
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty() && self.delayed.is_empty()
+    }
+
+    /// Returns the pending requests, highest priority first
+    pub fn get_state(&self) -> Vec<PrefetchQueueEntry> {
+        let mut result: Vec<PrefetchQueueEntry> = vec![];
+
+        let mut chunks: Vec<&PrefetchChunk<C>> = self.chunks.iter().chain(self.delayed.iter()).collect();
+        chunks.sort();
+
+        for chunk in chunks.iter().rev() {
+            match result
+                .iter_mut()
+                .find(|e| e.name == chunk.name && e.priority == chunk.priority && e.kind == chunk.kind)
+            {
+                Some(entry) => {
+                    entry.pending_chunks += 1;
+                    entry.pending_entries += chunk.entries.len();
+                }
+
+                None => result.push(PrefetchQueueEntry {
+                    name: chunk.name.clone(),
+                    priority: chunk.priority,
+                    kind: chunk.kind,
+                    pending_chunks: 1,
+                    pending_entries: chunk.entries.len(),
+                }),
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::util::prefetch_queue::*;
+
+    fn entries(files: &[&str]) -> Vec<TraceLogEntry> {
+        files
+            .iter()
+            .map(|f| TraceLogEntry::new(IOOperation::Open(PathBuf::from(f)), 0))
+            .collect()
+    }
+
+    fn push(queue: &mut PrefetchQueue<()>, name: &str, priority: PrefetchPriority, files: &[&str]) -> usize {
+        queue.push(name, priority, PrefetchKind::Data, vec![(None, entries(files))], ())
+    }
+
+    fn push_ordered(queue: &mut PrefetchQueue<()>, name: &str, priority: PrefetchPriority, files: &[&str]) -> usize {
+        queue.push_ordered(name, priority, PrefetchKind::Data, vec![(None, entries(files))], ())
+    }
+
+    fn pop(queue: &mut PrefetchQueue<()>) -> Option<PrefetchChunk<()>> {
+        match queue.pop(Instant::now()) {
+            PopResult::Chunk(chunk) => Some(chunk),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_prefetch_queue_priorities() {
+        let mut queue = PrefetchQueue::new();
+
+        push(&mut queue, "whitelist", PrefetchPriority::Background, &["/a"]);
+        push(&mut queue, "hot", PrefetchPriority::HotApplication, &["/b"]);
+        push(&mut queue, "app", PrefetchPriority::Interactive, &["/c"]);
+
+        let names: Vec<String> = std::iter::from_fn(|| pop(&mut queue)).map(|c| c.name).collect();
+        assert_eq!(vec!["app", "hot", "whitelist"], names);
+    }
+
+    #[test]
+    fn test_prefetch_queue_pending_chunks() {
+        let mut queue = PrefetchQueue::new();
+
+        let now = Instant::now();
+        let deadline = now + Duration::from_secs(5);
+
+        queue.push(
+            "app",
+            PrefetchPriority::Interactive,
+            PrefetchKind::Data,
+            vec![(Some(deadline), entries(&["/a"]))],
+            (),
+        );
+        push(&mut queue, "whitelist", PrefetchPriority::Background, &["/b"]);
+
+        // chunks that are not due yet must not delay chunks of lower priority
+        match queue.pop(now) {
+            PopResult::Chunk(chunk) => assert_eq!("whitelist", chunk.name),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        match queue.pop(now) {
+            PopResult::Pending(t) => assert_eq!(deadline, t),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        match queue.pop(deadline) {
+            PopResult::Chunk(chunk) => assert_eq!("app", chunk.name),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_prefetch_queue_ordered() {
+        let mut queue = PrefetchQueue::new();
+
+        let files: Vec<String> = (0..constants::PREFETCH_CHUNK_SIZE * 2).map(|n| format!("/{}", n)).collect();
+        let files: Vec<&str> = files.iter().map(|f| f.as_str()).collect();
+
+        assert_eq!(1, push_ordered(&mut queue, "a", PrefetchPriority::Background, &files));
+        assert_eq!(1, push_ordered(&mut queue, "b", PrefetchPriority::Background, &["/x"]));
+        assert_eq!(1, push(&mut queue, "c", PrefetchPriority::Background, &["/y"]));
+
+        let chunk = pop(&mut queue).unwrap();
+        assert!(chunk.ordered);
+        assert_eq!(constants::PREFETCH_CHUNK_SIZE * 2, chunk.entries.len());
+
+        let batch = queue.pop_ordered(chunk.priority, chunk.kind, Instant::now());
+        assert_eq!(1, batch.len());
+        assert_eq!("b", batch[0].name);
+
+        assert_eq!(1, queue.len());
+        assert!(!pop(&mut queue).unwrap().ordered);
+    }
+
+    #[test]
+    fn test_prefetch_queue_deduplication() {
+        let mut queue = PrefetchQueue::new();
+
+        assert_eq!(1, push(&mut queue, "hot", PrefetchPriority::HotApplication, &["/a"]));
+        assert_eq!(0, push(&mut queue, "whitelist", PrefetchPriority::Background, &["/a"]));
+        assert_eq!(1, push(&mut queue, "app", PrefetchPriority::Interactive, &["/a"]));
+
+        let chunk = pop(&mut queue).unwrap();
+        queue.complete(&chunk);
+
+        assert_eq!(1, push(&mut queue, "app", PrefetchPriority::Interactive, &["/a"]));
+    }
+
+    #[test]
+    fn test_prefetch_queue_covers_all_entries() {
+        let mut queue = PrefetchQueue::new();
+
+        let files: Vec<String> = (0..constants::PREFETCH_CHUNK_SIZE * 2 + 3)
+            .map(|i| format!("/{}", i))
+            .collect();
+        let files: Vec<&str> = files.iter().map(|f| f.as_str()).collect();
+
+        assert_eq!(3, push(&mut queue, "app", PrefetchPriority::Interactive, &files));
+        assert_eq!(files.len(), queue.get_state()[0].pending_entries);
+
+        let mut queue = PrefetchQueue::new();
+        assert_eq!(1, push(&mut queue, "app", PrefetchPriority::Interactive, &["/a"]));
+    }
+}
@@ -0,0 +1,55 @@
+/*
+    Precached - A Linux process monitor and pre-caching daemon
+    Copyright (C) 2017-2020 the precached developers
+
+    This file is part of precached.
+
+    Precached is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Precached is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Precached.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::path::Path;
+use crate::util;
+
+/// Returns the percentage of time in which at least one task was stalled on the resource
+/// `resource` (one of `cpu`, `io` or `memory`), averaged over the last 10 seconds.
+/// Returns `None` if the kernel lacks support for pressure stall information (PSI)
+pub fn read_pressure(resource: &str) -> Option<f32> {
+    let filename = Path::new("/proc/pressure").join(resource);
+
+    util::read_uncompressed_text_file(&filename)
+        .ok()
+        .and_then(|text| parse_pressure(&text))
+}
+
+/// Returns the `some avg10` value from the content `text` of a `/proc/pressure/*` file
+fn parse_pressure(text: &str) -> Option<f32> {
+    text.lines()
+        .find(|l| l.starts_with("some "))
+        .and_then(|l| l.split_whitespace().find(|f| f.starts_with("avg10=")))
+        .and_then(|f| f["avg10=".len()..].parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::pressure::*;
+
+    #[test]
+    fn test_parse_pressure() {
+        let text = "some avg10=12.50 avg60=3.10 avg300=0.70 total=123456\n\
+                    full avg10=8.00 avg60=2.00 avg300=0.50 total=65432\n";
+
+        assert_eq!(Some(12.5), parse_pressure(text));
+        assert_eq!(None, parse_pressure(""));
+    }
+}
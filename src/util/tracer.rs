@@ -19,7 +19,7 @@
 */
 
 use nix::unistd::{gettid, Pid};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::fs::OpenOptions;
 use std::io;
@@ -50,60 +50,288 @@ use crate::util;
 #[derive(Debug, Clone)]
 pub struct PerTracerData {
     pub start_time: Instant,
+    pub last_activity: Instant,
+    pub quiet_period: Duration,
+    pub max_duration: Duration,
     pub trace_time_expired: bool,
     pub process_exited: bool,
+    pub descendants: HashSet<libc::pid_t>,
     pub trace_log: iotrace::IOTraceLog,
 }
 
 impl PerTracerData {
-    pub fn new(trace_log: iotrace::IOTraceLog) -> PerTracerData {
+    pub fn new(trace_log: iotrace::IOTraceLog, quiet_period: Duration, max_duration: Duration) -> PerTracerData {
         PerTracerData {
             start_time: Instant::now(),
+            last_activity: Instant::now(),
+            quiet_period,
+            max_duration,
             trace_time_expired: false,
             process_exited: false,
+            descendants: HashSet::new(),
             trace_log,
         }
     }
+
+    /// Returns `true` while the trace window of the process is open
+    pub fn is_active(&self) -> bool {
+        !self.trace_time_expired && !self.process_exited
+    }
+
+    /// Record a file access of the traced process, postponing the end of the quiet period
+    pub fn touch(&mut self) {
+        self.last_activity = Instant::now();
+    }
+}
+
+/// Returns the quiet period and the maximum trace duration for the program `exe`,
+/// honoring the per-program overrides of the configuration file
+pub fn get_trace_durations(exe: &Path, globals: &Globals) -> (Duration, Duration) {
+    let config_file = globals.get_config_file();
+
+    let mut quiet_period = config_file
+        .trace_quiet_period
+        .unwrap_or(constants::IO_TRACE_QUIET_PERIOD_MILLIS);
+    let mut max_duration = config_file.trace_max_duration.unwrap_or(constants::IO_TRACE_MAX_TIME_SECS);
+
+    if let Some(trace_override) = config_file
+        .trace_overrides
+        .as_ref()
+        .and_then(|overrides| overrides.iter().find(|o| o.program == exe))
+    {
+        quiet_period = trace_override.trace_quiet_period.unwrap_or(quiet_period);
+        max_duration = trace_override.trace_max_duration.unwrap_or(max_duration);
+    }
+
+    (Duration::from_millis(quiet_period), Duration::from_secs(max_duration))
 }
 
-/// Check for, and prune expired tracers; save their logs if valid
-pub fn check_expired_tracers(
-    active_tracers: &mut HashMap<libc::pid_t, PerTracerData>,
+/// Remove all tracers from `active_tracers` whose tracing time expired, or whose
+/// process has exited, and return them, so that they can be finalized
+pub fn take_expired_tracers(active_tracers: &mut HashMap<libc::pid_t, PerTracerData>) -> Vec<(libc::pid_t, PerTracerData)> {
+    let mut result = vec![];
+
+    let now = Instant::now();
+
+    for (pid, v) in active_tracers.iter_mut() {
+        if v.process_exited {
+            v.trace_log.stop_reason = iotrace::TraceStopReason::ProcessExited;
+        } else if now - v.start_time > v.max_duration {
+            debug!(
+                "Maximum tracing time reached for process '{}' with pid: {}",
+                v.trace_log.comm, pid
+            );
+
+            v.trace_time_expired = true;
+            v.trace_log.stop_reason = iotrace::TraceStopReason::TimeLimit;
+        } else if now - v.last_activity > v.quiet_period {
+            debug!("Startup phase finished for process '{}' with pid: {}", v.trace_log.comm, pid);
+
+            v.trace_time_expired = true;
+            v.trace_log.stop_reason = iotrace::TraceStopReason::Quiescent;
+        }
+
+        if v.trace_time_expired || v.process_exited {
+            result.push((*pid, v.clone()));
+        }
+    }
+
+    // collect and prune expired tracers
+    active_tracers.retain(|_k, v| !v.trace_time_expired && !v.process_exited);
+
+    result
+}
+
+/// Remove the tracer of the exited process `pid` from `active_tracers` and return it, so that
+/// it can be finalized. Returns `None` if process `pid` is not traced, or has not exited yet
+pub fn take_exited_tracer(active_tracers: &mut HashMap<libc::pid_t, PerTracerData>, pid: libc::pid_t) -> Option<PerTracerData> {
+    if !active_tracers.get(&pid).map_or(false, |v| v.process_exited) {
+        return None;
+    }
+
+    let mut v = active_tracers.remove(&pid)?;
+    v.trace_log.stop_reason = iotrace::TraceStopReason::ProcessExited;
+
+    Some(v)
+}
+
+/// The criteria applied to I/O trace logs when they are saved
+struct SaveOptions {
+    min_len: usize,
+    min_prefetch_size: u64,
+    keep_written_files: bool,
+    max_entries: usize,
+    max_size: u64,
+    policy: iotrace::TruncationPolicy,
+}
+
+/// Finalize the tracer of process `pid`: Merge its I/O trace log with the ones of previous
+/// runs of the program, and save it, if valid. Files that have been opened for writing are
+/// removed, unless `keep_written_files` is set. If the I/O trace log exceeds `max_entries`
+/// or `max_size`, it is truncated according to `policy`. Returns the file name of the saved
+/// I/O trace log
+#[allow(clippy::too_many_arguments)]
+pub fn finalize_tracer(
+    pid: libc::pid_t,
+    mut v: PerTracerData,
     iotrace_dir: &Path,
     min_len: usize,
     min_prefetch_size: u64,
-    _globals: &mut Globals,
-) {
-    for (pid, v) in active_tracers.iter_mut() {
-        if Instant::now() - v.start_time > Duration::from_secs(constants::IO_TRACE_TIME_SECS) {
-            let comm = &v.trace_log.comm;
+    keep_written_files: bool,
+    max_entries: usize,
+    max_size: u64,
+    policy: iotrace::TruncationPolicy,
+) -> Option<PathBuf> {
+    let comm = v.trace_log.comm.clone();
 
-            debug!("Tracing time expired for process '{}' with pid: {}", comm, pid);
+    v.trace_log.trace_stopped_at = Utc::now();
 
-            v.trace_time_expired = true;
-            v.trace_log.trace_stopped_at = Utc::now();
+    // record which pages of the traced files have actually been used
+    snapshot_resident_pages(&mut v.trace_log);
+
+    let options = SaveOptions {
+        min_len,
+        min_prefetch_size,
+        keep_written_files,
+        max_entries,
+        max_size,
+        policy,
+    };
+
+    let filename = iotrace_dir
+        .join(Path::new(&constants::IOTRACE_DIR))
+        .join(Path::new(&format!("{}.trace", v.trace_log.hash)));
 
-            let filename = iotrace_dir
+    match merge_and_save(v.trace_log.clone(), &filename, &options) {
+        Err(e) => {
+            error!(
+                "Error while saving the I/O trace log for process '{}' with pid: {}. {}",
+                comm, pid, e
+            );
+
+            None
+        }
+
+        Ok(false) => {
+            // the I/O trace log did not meet the minimum length or size criteria
+            None
+        }
+
+        Ok(true) => {
+            info!("Successfully saved I/O trace log for process '{}' with pid: {}", comm, pid);
+
+            // provide a fallback for invocations of the program with unknown command lines.
+            // Only the current run is merged, since the fallback keeps its own history of runs
+            let exe_only = v.trace_log.to_exe_only();
+            let exe_only_filename = iotrace_dir
                 .join(Path::new(&constants::IOTRACE_DIR))
-                .join(Path::new(&format!("{}.trace", v.trace_log.hash)));
+                .join(Path::new(&format!("{}.trace", exe_only.hash)));
+
+            merge_and_save(exe_only, &exe_only_filename, &options).unwrap_or_else(|e| {
+                error!("Error while saving the fallback I/O trace log for process '{}': {}", comm, e);
+                false
+            });
+
+            // share the I/O trace log with identical copies of the executable at different paths
+            if let Some(build_id_only) = v.trace_log.to_build_id_only() {
+                let build_id_only_filename = iotrace_dir
+                    .join(Path::new(&constants::IOTRACE_DIR))
+                    .join(Path::new(&format!("{}.trace", build_id_only.hash)));
+
+                merge_and_save(build_id_only, &build_id_only_filename, &options).unwrap_or_else(|e| {
+                    error!("Error while saving the build-id I/O trace log for process '{}': {}", comm, e);
+                    false
+                });
+            }
 
-            match v.trace_log.save(&filename, min_len, min_prefetch_size, false) {
-                Err(e) => error!(
-                    "Error while saving the I/O trace log for process '{}' with pid: {}. {}",
-                    comm, pid, e
-                ),
+            Some(filename)
+        }
+    }
+}
+
+/// Merge the I/O trace log `trace_log` of the current run of a program with the I/O trace log
+/// of previous runs stored in `filename`, unless that one is invalid, e.g. because the binary has
+/// been updated, and save the result to `filename`. Returns `false` if nothing has been written
+fn merge_and_save(mut trace_log: iotrace::IOTraceLog, filename: &Path, options: &SaveOptions) -> io::Result<bool> {
+    let comm = trace_log.comm.clone();
+
+    if let Ok(previous) = iotrace::IOTraceLog::from_file(filename) {
+        let (_flags, err, _) = util::get_io_trace_flags_and_err(&previous);
 
-                Ok(()) => {
-                    info!("Successfully saved I/O trace log for process '{}' with pid: {}", comm, pid);
+        if !err {
+            debug!(
+                "Merging {} previous run(s) into the I/O trace log for process '{}'",
+                previous.num_runs, comm
+            );
 
-                    // schedule an optimization pass for the newly saved trace log
-                    // debug!("Queued an optimization request for {:?}", filename);
-                    // events::queue_internal_event(EventType::OptimizeIOTraceLog(filename), globals);
+            trace_log.merge(&previous, constants::IO_TRACE_MAX_MERGED_RUNS);
+        }
+    }
+
+    if !options.keep_written_files {
+        let count = trace_log.remove_written_files();
+
+        if count > 0 {
+            debug!(
+                "Removed {} entries of written files from the I/O trace log for process '{}'",
+                count, comm
+            );
+        }
+    }
+
+    // the merged trace log may exceed the limits, even if the current run alone does not
+    trace_log.truncated = false;
+
+    let count = trace_log.truncate(options.max_entries, options.max_size, options.policy);
+
+    if count > 0 {
+        warn!(
+            "The I/O trace log for process '{}' exceeded the size limits, dropped {} entries ({:?})",
+            comm, count, options.policy
+        );
+    }
+
+    trace_log.save(filename, options.min_len, options.min_prefetch_size, false)
+}
+
+/// Returns the regular files that are currently mapped into the address space of process `pid`.
+/// The file names are translated to our mount namespace, described by `host_mounts`
+pub fn get_mapped_files(pid: libc::pid_t, host_mounts: &[util::MountInfo]) -> Vec<PathBuf> {
+    let mut result = vec![];
+
+    if let Ok(process) = Process::new(pid) {
+        if let Ok(mapped_files) = process.get_mapped_files() {
+            for filename in mapped_files.iter().map(PathBuf::from).filter(|f| f.is_absolute()) {
+                let filename = match process.mountinfo.as_ref() {
+                    Some(mountinfo) => util::canonicalize_path(pid, mountinfo, host_mounts, &filename).unwrap_or(filename),
+                    None => filename,
+                };
+
+                // skip deleted files, anonymous shared memory and device nodes
+                if util::is_file(&filename) && !result.contains(&filename) {
+                    result.push(filename);
                 }
             }
         }
     }
 
-    // collect and prune expired tracers
-    active_tracers.retain(|_k, v| !v.trace_time_expired);
+    result
+}
+
+/// Take a snapshot of the pages of each file referenced by the I/O trace log `trace_log`,
+/// that are resident in the page cache, so that only those ranges will be prefetched later on
+fn snapshot_resident_pages(trace_log: &mut iotrace::IOTraceLog) {
+    let mut resident_ranges = HashMap::new();
+
+    for filename in trace_log.file_map.keys() {
+        match util::get_resident_ranges(filename) {
+            Err(e) => trace!("Could not query resident pages of file {:?}: {}", filename, e),
+
+            Ok(ranges) => {
+                resident_ranges.insert(filename.clone(), ranges);
+            }
+        }
+    }
+
+    trace_log.set_resident_ranges(&resident_ranges);
 }
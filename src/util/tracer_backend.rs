@@ -0,0 +1,339 @@
+/*
+    Precached - A Linux process monitor and pre-caching daemon
+    Copyright (C) 2017-2020 the precached developers
+
+    This file is part of precached.
+
+    Precached is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Precached is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Precached.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use fanotify::safe as fan;
+use log::{trace, debug, info, warn, error, log, LevelFilter};
+use crate::constants;
+use crate::hooks::fanotify_logger::ACTIVE_TRACERS;
+use crate::process::Process;
+use crate::util;
+
+/// fanotify event mask bit: Writable file has been closed
+pub const FAN_CLOSE_WRITE_EVENT: u64 = 0x0000_0008;
+
+/// fanotify event mask bit: File has been opened
+pub const FAN_OPEN_EVENT: u64 = 0x0000_0020;
+
+/// fanotify event mask bit: File has been opened for execution (since Linux 5.0)
+pub const FAN_OPEN_EXEC_EVENT: u64 = 0x0000_1000;
+
+/// fanotify event mask bit: Event occurred against a directory
+pub const FAN_ONDIR_EVENT: u64 = 0x4000_0000;
+
+/// A file access of a process, reported by a tracer backend. The event mask
+/// uses the fanotify event mask bits, regardless of the backend in use
+#[derive(Debug, Clone)]
+pub struct TracerEvent {
+    pub pid: libc::pid_t,
+    pub mask: u64,
+    pub filename: PathBuf,
+}
+
+/// A source of file access events of processes
+pub trait TracerBackend: Send {
+    /// Returns the name of the backend
+    fn get_name(&self) -> &'static str;
+
+    /// Returns the time to wait before polling again, if no events are pending
+    fn get_poll_interval(&self) -> Duration;
+
+    /// Returns all file access events that occurred since the last call
+    fn get_events(&mut self) -> Vec<TracerEvent>;
+}
+
+/// Select the best tracer backend supported by the system: fanotify if available,
+/// otherwise fall back to sampling `/proc`, e.g. when running inside a container
+pub fn select_tracer_backend() -> Box<dyn TracerBackend> {
+    match FanotifyBackend::new() {
+        Ok(backend) => Box::new(backend),
+
+        Err(e) => {
+            warn!("{}, falling back to the /proc based tracer backend", e);
+
+            Box::new(ProcBackend::new())
+        }
+    }
+}
+
+/// Tracer backend that watches the whole root filesystem using fanotify
+pub struct FanotifyBackend {
+    fanotify: fan::Fanotify,
+}
+
+impl FanotifyBackend {
+    /// Initialize fanotify and register a watch on the root filesystem, include
+    /// executions and directory events, if supported by the running kernel
+    pub fn new() -> io::Result<FanotifyBackend> {
+        let fanotify = fan::Fanotify::new_nonblocking()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Could not initialize fanotify: {:?}", e)))?;
+
+        let mask = FAN_OPEN_EVENT | FAN_CLOSE_WRITE_EVENT | FAN_ONDIR_EVENT;
+
+        fanotify
+            .add_filesystem(mask | FAN_OPEN_EXEC_EVENT, "/".to_string())
+            .or_else(|e| {
+                info!("Kernel does not support FAN_OPEN_EXEC, falling back: {}", e);
+                fanotify.add_filesystem(mask, "/".to_string())
+            })
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("Could not add fanotify root filesystem watch: {}", e),
+                )
+            })?;
+
+        Ok(FanotifyBackend { fanotify })
+    }
+}
+
+impl TracerBackend for FanotifyBackend {
+    fn get_name(&self) -> &'static str {
+        "fanotify"
+    }
+
+    fn get_poll_interval(&self) -> Duration {
+        Duration::from_millis(constants::FANOTIFY_THREAD_YIELD_MILLIS)
+    }
+
+    fn get_events(&mut self) -> Vec<TracerEvent> {
+        match self.fanotify.get_events() {
+            Err(_e) => vec![],
+
+            Ok(events) => events
+                .iter()
+                .map(|event| TracerEvent {
+                    pid: event.pid as libc::pid_t,
+                    mask: event.mask as u64,
+                    filename: PathBuf::from(event.filename.clone()),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Per process state of the `/proc` based tracer backend
+struct SampledProcess {
+    process: Process,
+    /// The files reported so far, along with the fanotify event mask of the access
+    seen_files: HashSet<(PathBuf, u64)>,
+}
+
+/// Tracer backend that periodically samples the memory mappings and the open
+/// file descriptors of all traced processes and of their descendants. Files that
+/// are opened and closed again in between two samples are missed, so the resulting
+/// I/O trace logs are less complete than the ones recorded by the fanotify backend
+pub struct ProcBackend {
+    processes: HashMap<libc::pid_t, SampledProcess>,
+}
+
+impl ProcBackend {
+    pub fn new() -> ProcBackend {
+        ProcBackend {
+            processes: HashMap::new(),
+        }
+    }
+
+    /// Returns the files mapped into the address space of `process`,
+    /// along with the fanotify event mask describing the access
+    fn sample_mappings(process: &Process) -> Vec<(PathBuf, u64)> {
+        match process.get_mappings() {
+            Err(_e) => vec![],
+
+            Ok(mappings) => mappings
+                .into_iter()
+                .filter(|m| m.file.is_absolute())
+                .map(|m| {
+                    let mask = if m.flags.contains('x') {
+                        FAN_OPEN_EXEC_EVENT
+                    } else {
+                        FAN_OPEN_EVENT
+                    };
+
+                    (m.file, mask)
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the files currently opened by the process `pid`,
+    /// along with the fanotify event mask describing the access
+    fn sample_file_descriptors(pid: libc::pid_t) -> Vec<(PathBuf, u64)> {
+        let mut result = vec![];
+
+        let entries = match fs::read_dir(format!("/proc/{}/fd", pid)) {
+            Err(_e) => return result,
+            Ok(entries) => entries,
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            // skip sockets, pipes and anonymous inodes
+            let filename = match fs::read_link(entry.path()) {
+                Ok(filename) if filename.is_absolute() => filename,
+                _ => continue,
+            };
+
+            let fdinfo = Path::new("/proc")
+                .join(format!("{}", pid))
+                .join("fdinfo")
+                .join(entry.file_name());
+
+            let written = util::read_uncompressed_text_file(&fdinfo)
+                .ok()
+                .and_then(|text| parse_fdinfo_flags(&text))
+                .map_or(false, |flags| flags & libc::O_ACCMODE != libc::O_RDONLY);
+
+            let mask = if written {
+                FAN_OPEN_EVENT | FAN_CLOSE_WRITE_EVENT
+            } else {
+                FAN_OPEN_EVENT
+            };
+
+            result.push((filename, mask));
+        }
+
+        result
+    }
+}
+
+impl TracerBackend for ProcBackend {
+    fn get_name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn get_poll_interval(&self) -> Duration {
+        Duration::from_millis(constants::PROC_TRACER_SAMPLE_INTERVAL_MILLIS)
+    }
+
+    fn get_events(&mut self) -> Vec<TracerEvent> {
+        let tracers: Vec<(libc::pid_t, Vec<libc::pid_t>)> = ACTIVE_TRACERS
+            .lock()
+            .iter()
+            .filter(|(_pid, tracer_data)| tracer_data.is_active())
+            .map(|(pid, tracer_data)| (*pid, tracer_data.descendants.iter().cloned().collect()))
+            .collect();
+
+        // sample the traced processes along with their descendants, the ones seen
+        // so far as well as the ones that have not accessed any files yet
+        let mut pids: Vec<libc::pid_t> = vec![];
+
+        for (pid, descendants) in tracers {
+            for pid in std::iter::once(pid).chain(descendants).chain(get_descendants(pid)) {
+                if !pids.contains(&pid) {
+                    pids.push(pid);
+                }
+            }
+        }
+
+        // forget about processes that are not being traced anymore
+        self.processes.retain(|pid, _v| pids.contains(pid));
+
+        let mut result = vec![];
+
+        for pid in pids {
+            if !self.processes.contains_key(&pid) {
+                match Process::new(pid) {
+                    Err(e) => {
+                        trace!("Could not sample process with pid {}: {}", pid, e);
+                        continue;
+                    }
+
+                    Ok(process) => {
+                        let sampled_process = SampledProcess {
+                            process,
+                            seen_files: HashSet::new(),
+                        };
+
+                        self.processes.insert(pid, sampled_process);
+                    }
+                }
+            }
+
+            let sampled_process = self.processes.get_mut(&pid).unwrap();
+
+            let mut files = Self::sample_mappings(&sampled_process.process);
+            files.append(&mut Self::sample_file_descriptors(pid));
+
+            // only report accesses that have not been seen during earlier samples, a file
+            // that has been opened read-only before may be opened for writing later on
+            for (filename, mask) in files {
+                if sampled_process.seen_files.insert((filename.clone(), mask)) {
+                    result.push(TracerEvent { pid, mask, filename });
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Returns the pids of all living descendants of process `pid`, as listed
+/// in the `/proc/<pid>/task/<tid>/children` files of the process tree
+fn get_descendants(pid: libc::pid_t) -> Vec<libc::pid_t> {
+    let mut result = vec![];
+    let mut pending = vec![pid];
+
+    while let Some(pid) = pending.pop() {
+        let tasks = match fs::read_dir(format!("/proc/{}/task", pid)) {
+            Err(_e) => continue,
+            Ok(tasks) => tasks,
+        };
+
+        for task in tasks.filter_map(|t| t.ok()) {
+            let children = match util::read_uncompressed_text_file(&task.path().join("children")) {
+                Err(_e) => continue,
+                Ok(children) => children,
+            };
+
+            for child in children.split_whitespace().filter_map(|c| c.parse::<libc::pid_t>().ok()) {
+                if !result.contains(&child) {
+                    result.push(child);
+                    pending.push(child);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns the flags of a file descriptor from the content `fdinfo`
+/// of a `/proc/<pid>/fdinfo/<fd>` file
+fn parse_fdinfo_flags(fdinfo: &str) -> Option<i32> {
+    fdinfo
+        .lines()
+        .find(|l| l.starts_with("flags:"))
+        .and_then(|l| i32::from_str_radix(l["flags:".len()..].trim(), 8).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::tracer_backend::*;
+
+    #[test]
+    fn test_parse_fdinfo_flags() {
+        assert_eq!(Some(0o100002), parse_fdinfo_flags("pos:\t0\nflags:\t0100002\nmnt_id:\t25\n"));
+        assert_eq!(None, parse_fdinfo_flags("pos:\t0\n"));
+    }
+}